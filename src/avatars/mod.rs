@@ -1,4 +1,4 @@
-use std::fs::File;
+use std::{f64::consts::PI, fs::File};
 
 use cairo_rs::{Context, FontSlant, FontWeight, Format, ImageSurface};
 
@@ -36,4 +36,150 @@ impl AvatarClient {
             .map_err(|error| AppError::fs_error(error))?;
         Ok(path)
     }
+
+    pub fn render_initials(seed: &[u8], text: &str, size: u32) -> Result<Vec<u8>, AppError> {
+        let (surface, cr) = canvas(size)?;
+        let size = size as f64;
+        let (red, green, blue) = seed_color(seed);
+        cr.set_source_rgb(red, green, blue);
+        cr.paint()
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+
+        cr.select_font_face("Sans", FontSlant::Normal, FontWeight::Bold);
+        cr.set_font_size(size * 0.42);
+        let extents = cr
+            .text_extents(text)
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+        cr.move_to(
+            size / 2.0 - (extents.width() / 2.0 + extents.x_bearing()),
+            size / 2.0 - (extents.height() / 2.0 + extents.y_bearing()),
+        );
+        cr.set_source_rgb(1.0, 1.0, 1.0);
+        cr.show_text(text)
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+        encode_png(&surface)
+    }
+
+    pub fn render_mystery_person(size: u32) -> Result<Vec<u8>, AppError> {
+        let (surface, cr) = canvas(size)?;
+        let size = size as f64;
+        cr.set_source_rgb(0.78, 0.78, 0.78);
+        cr.paint()
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+
+        cr.set_source_rgb(0.97, 0.97, 0.97);
+        cr.arc(size * 0.5, size * 0.38, size * 0.19, 0.0, 2.0 * PI);
+        cr.fill()
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+        cr.arc(size * 0.5, size * 1.08, size * 0.42, 0.0, 2.0 * PI);
+        cr.fill()
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+        encode_png(&surface)
+    }
+
+    pub fn render_identicon(seed: &[u8], size: u32) -> Result<Vec<u8>, AppError> {
+        let (surface, cr) = canvas(size)?;
+        let cell = size as f64 / 6.0;
+        cr.set_source_rgb(0.94, 0.94, 0.94);
+        cr.paint()
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+
+        let (red, green, blue) = seed_color(seed);
+        cr.set_source_rgb(red, green, blue);
+        for (column, row, _) in symmetric_cells(seed, 5, 2)
+            .into_iter()
+            .filter(|(_, _, shade)| *shade == 1)
+        {
+            cr.rectangle(
+                cell / 2.0 + column as f64 * cell,
+                cell / 2.0 + row as f64 * cell,
+                cell,
+                cell,
+            );
+        }
+        cr.fill()
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+        encode_png(&surface)
+    }
+
+    pub fn render_retro(seed: &[u8], size: u32) -> Result<Vec<u8>, AppError> {
+        let (surface, cr) = canvas(size)?;
+        let cell = size as f64 / 8.0;
+        cr.set_source_rgb(1.0, 1.0, 1.0);
+        cr.paint()
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+
+        let (red, green, blue) = seed_color(seed);
+        let cells = symmetric_cells(seed, 8, 3);
+        for (level, brightness) in [(1, 1.0), (2, 0.6)] {
+            cr.set_source_rgb(red * brightness, green * brightness, blue * brightness);
+            for (column, row, _) in cells.iter().filter(|(_, _, shade)| *shade == level) {
+                cr.rectangle(*column as f64 * cell, *row as f64 * cell, cell, cell);
+            }
+            cr.fill()
+                .map_err(|error| AppError::avatat_generation_error(error))?;
+        }
+        encode_png(&surface)
+    }
+
+    pub fn render_blank(size: u32) -> Result<Vec<u8>, AppError> {
+        let (surface, _) = canvas(size)?;
+        encode_png(&surface)
+    }
+}
+
+fn canvas(size: u32) -> Result<(ImageSurface, Context), AppError> {
+    let surface = ImageSurface::create(Format::ARgb32, size as i32, size as i32)
+        .map_err(|error| AppError::avatat_generation_error(error))?;
+    let cr = Context::new(&surface).map_err(|error| AppError::avatat_generation_error(error))?;
+    Ok((surface, cr))
+}
+
+fn encode_png(surface: &ImageSurface) -> Result<Vec<u8>, AppError> {
+    let mut encoded_image = Vec::new();
+    surface
+        .write_to_png(&mut encoded_image)
+        .map_err(|error| AppError::avatat_generation_error(error))?;
+    Ok(encoded_image)
+}
+
+fn seed_color(seed: &[u8]) -> (f64, f64, f64) {
+    let hue = match seed.len() {
+        0 | 1 => 0.0,
+        _ => u16::from_be_bytes([seed[0], seed[1]]) as f64 / u16::MAX as f64 * 6.0,
+    };
+    // HSL to RGB with a fixed saturation and lightness, so every hue stays readable.
+    let (saturation, lightness) = (0.55, 0.55);
+    let chroma = (1.0 - (2.0 * lightness - 1.0_f64).abs()) * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (red, green, blue) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    (red + m, green + m, blue + m)
+}
+
+// Cells of a grid mirrored around its vertical axis, each with a shade in
+// `0..shades` taken from the seed bytes that follow the color.
+fn symmetric_cells(seed: &[u8], grid: usize, shades: u8) -> Vec<(usize, usize, u8)> {
+    let half = (grid + 1) / 2;
+    let mut cells = Vec::new();
+    if seed.is_empty() {
+        return cells;
+    }
+    for row in 0..grid {
+        for column in 0..half {
+            let shade = seed[(2 + row * half + column) % seed.len()] % shades;
+            cells.push((column, row, shade));
+            if grid - 1 - column != column {
+                cells.push((grid - 1 - column, row, shade));
+            }
+        }
+    }
+    cells
 }
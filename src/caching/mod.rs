@@ -0,0 +1,73 @@
+pub mod render_cache;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_web::{
+    http::header::{
+        CacheControl, CacheDirective, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch,
+        ETAG, IF_NONE_MATCH, LAST_MODIFIED,
+    },
+    HttpRequest, HttpResponseBuilder,
+};
+use mongodb::bson::DateTime;
+
+use crate::models::DeliveryPolicy;
+
+pub struct Validators {
+    pub etag: EntityTag,
+    pub last_modified: HttpDate,
+}
+
+impl Validators {
+    pub fn new(tag: &str, last_modified: DateTime) -> Validators {
+        // HTTP dates have a one second resolution, anything finer breaks If-Modified-Since.
+        let seconds = (last_modified.timestamp_millis() / 1000).max(0) as u64;
+        Validators {
+            etag: EntityTag::new_strong(tag.to_string()),
+            last_modified: HttpDate::from(UNIX_EPOCH + Duration::from_secs(seconds)),
+        }
+    }
+
+    // If-None-Match takes precedence, If-Modified-Since is only used without it.
+    pub fn not_modified(&self, request: &HttpRequest) -> bool {
+        if request.headers().contains_key(IF_NONE_MATCH) {
+            return match IfNoneMatch::parse(request) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&self.etag)),
+                Err(_) => false,
+            };
+        }
+        match IfModifiedSince::parse(request) {
+            Ok(IfModifiedSince(since)) => self.last_modified <= since,
+            Err(_) => false,
+        }
+    }
+
+    pub fn apply<'a>(&self, response: &'a mut HttpResponseBuilder) -> &'a mut HttpResponseBuilder {
+        response
+            .insert_header((ETAG, self.etag.to_string()))
+            .insert_header((LAST_MODIFIED, self.last_modified.to_string()))
+    }
+}
+
+pub fn cache_control(policy: &DeliveryPolicy, versioned: bool) -> CacheControl {
+    match versioned {
+        true => CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(policy.versioned_max_age),
+            CacheDirective::Extension("immutable".to_string(), None),
+        ]),
+        false => CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(policy.max_age),
+        ]),
+    }
+}
+
+// Signed URLs must not outlive their signature in any cache.
+pub fn private_cache_control(policy: &DeliveryPolicy, expires_in: u32) -> CacheControl {
+    CacheControl(vec![
+        CacheDirective::Private,
+        CacheDirective::MaxAge(policy.max_age.min(expires_in)),
+    ])
+}
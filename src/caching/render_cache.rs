@@ -0,0 +1,201 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use actix_web::web::Bytes;
+use serde::Serialize;
+use tokio::sync::OnceCell;
+
+use crate::errors::AppError;
+
+#[derive(Debug, Clone)]
+pub struct RenderedImage {
+    pub data: Bytes,
+    pub mime_type: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    Coalesced,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+            CacheStatus::Coalesced => "coalesced",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub coalesced: u64,
+    pub evictions: u64,
+    pub entries: u64,
+    pub bytes: u64,
+    pub budget_bytes: u64,
+}
+
+struct Entry {
+    image: RenderedImage,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    bytes: u64,
+}
+
+impl Lru {
+    fn get(&mut self, key: &str) -> Option<RenderedImage> {
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.last_used);
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.order.insert(self.clock, key.to_string());
+        Some(entry.image.clone())
+    }
+
+    fn insert(&mut self, key: String, image: RenderedImage, budget_bytes: u64) -> u64 {
+        let size = image.data.len() as u64;
+        if size > budget_bytes {
+            return 0;
+        }
+        if let Some(previous) = self.entries.remove(&key) {
+            self.order.remove(&previous.last_used);
+            self.bytes -= previous.image.data.len() as u64;
+        }
+
+        let mut evictions = 0;
+        while self.bytes + size > budget_bytes {
+            let oldest = match self.order.keys().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            if let Some(evicted_key) = self.order.remove(&oldest) {
+                if let Some(evicted) = self.entries.remove(&evicted_key) {
+                    self.bytes -= evicted.image.data.len() as u64;
+                    evictions += 1;
+                }
+            }
+        }
+
+        self.clock += 1;
+        self.bytes += size;
+        self.order.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                image,
+                last_used: self.clock,
+            },
+        );
+        evictions
+    }
+}
+
+pub struct RenderCache {
+    budget_bytes: u64,
+    lru: Mutex<Lru>,
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<RenderedImage>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl RenderCache {
+    pub fn new(budget_bytes: u64) -> RenderCache {
+        RenderCache {
+            budget_bytes,
+            lru: Mutex::new(Lru::default()),
+            in_flight: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    // Concurrent requests for the same key share a single render, a failed render is
+    // retried by the next waiter and never cached.
+    pub async fn get_or_render<F, Fut>(
+        &self,
+        key: &str,
+        render: F,
+    ) -> Result<(RenderedImage, CacheStatus), AppError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<RenderedImage, AppError>>,
+    {
+        if let Some(image) = self.lru.lock().unwrap().get(key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok((image, CacheStatus::Hit));
+        }
+
+        let cell = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let mut rendered = false;
+        let result = cell
+            .get_or_try_init(|| {
+                rendered = true;
+                render()
+            })
+            .await
+            .cloned();
+        if !rendered {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            return result.map(|image| (image, CacheStatus::Coalesced));
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if let Ok(image) = &result {
+            let evictions =
+                self.lru
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_string(), image.clone(), self.budget_bytes);
+            self.evictions.fetch_add(evictions, Ordering::Relaxed);
+        }
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight
+            .get(key)
+            .map_or(false, |current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(key);
+        }
+        result.map(|image| (image, CacheStatus::Miss))
+    }
+
+    pub fn stats(&self) -> RenderCacheStats {
+        let lru = self.lru.lock().unwrap();
+        RenderCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: lru.entries.len() as u64,
+            bytes: lru.bytes,
+            budget_bytes: self.budget_bytes,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+mod noop;
+mod webhook;
+
+pub use noop::*;
+pub use webhook::*;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use tracing::Instrument;
+
+use crate::{config::Config, errors::AppError};
+
+// Responses served by stampa carry surrogate keys so a CDN can drop every variant of an
+// avatar (sizes, formats, signed and versioned urls) at once instead of url by url.
+pub const SURROGATE_KEY: &str = "surrogate-key";
+
+#[derive(Debug, Default, Serialize)]
+pub struct Purge {
+    pub urls: Vec<String>,
+    pub surrogate_keys: Vec<String>,
+}
+
+impl Purge {
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty() && self.surrogate_keys.is_empty()
+    }
+}
+
+#[async_trait]
+pub trait PurgeHook: Send + Sync {
+    async fn purge(&self, purge: &Purge) -> Result<(), AppError>;
+}
+
+pub fn avatar_surrogate_key(avatar_id: ObjectId) -> String {
+    format!("avatar-{}", avatar_id)
+}
+
+pub fn gravatar_surrogate_key(hash: &str) -> String {
+    format!("gravatar-{}", hash)
+}
+
+pub fn from_config(config: &Config) -> Result<Arc<dyn PurgeHook>, AppError> {
+    match config.purge_hook.as_str() {
+        "webhook" => {
+            let url = config
+                .purge_webhook_url
+                .as_ref()
+                .ok_or(AppError::purge_error(
+                    "PURGE_WEBHOOK_URL is required by the webhook purge hook.",
+                ))?;
+            Ok(Arc::new(WebhookPurgeHook::new(
+                url,
+                config.purge_webhook_token.clone(),
+            )?))
+        }
+        "none" => Ok(Arc::new(NoopPurgeHook)),
+        purge_hook => Err(AppError::purge_error(format!(
+            "Unknown purge hook {}.",
+            purge_hook
+        ))),
+    }
+}
+
+// Purging is best effort and runs in the background: a slow or unavailable CDN must neither
+// fail nor delay the change that triggered it.
+pub fn purge(purge_hook: &Arc<dyn PurgeHook>, purge: Purge) {
+    if purge.is_empty() {
+        return;
+    }
+    let purge_hook = purge_hook.clone();
+    tokio::spawn(
+        async move {
+            if let Err(error) = purge_hook.purge(&purge).await {
+                log::warn!(
+                    "Can not purge {} urls and {} surrogate keys from the CDN: {:?}",
+                    purge.urls.len(),
+                    purge.surrogate_keys.len(),
+                    error
+                );
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+pub fn public_url(template: &str, bucket_name: &str, region: &str, key: &str) -> String {
+    template
+        .replace("{bucket}", bucket_name)
+        .replace("{region}", region)
+        .replace("{key}", key)
+}
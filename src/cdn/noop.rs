@@ -0,0 +1,13 @@
+use async_trait::async_trait;
+
+use super::{Purge, PurgeHook};
+use crate::errors::AppError;
+
+pub struct NoopPurgeHook;
+
+#[async_trait]
+impl PurgeHook for NoopPurgeHook {
+    async fn purge(&self, _purge: &Purge) -> Result<(), AppError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use super::{Purge, PurgeHook};
+use crate::errors::AppError;
+
+const PURGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct WebhookPurgeHook {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl WebhookPurgeHook {
+    pub fn new(url: &str, token: Option<String>) -> Result<WebhookPurgeHook, AppError> {
+        let client = reqwest::Client::builder()
+            .timeout(PURGE_TIMEOUT)
+            .build()
+            .map_err(|error| AppError::purge_error(error))?;
+        Ok(WebhookPurgeHook {
+            client,
+            url: url.to_string(),
+            token,
+        })
+    }
+}
+
+#[async_trait]
+impl PurgeHook for WebhookPurgeHook {
+    async fn purge(&self, purge: &Purge) -> Result<(), AppError> {
+        let mut request = self.client.post(&self.url).json(purge);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|error| AppError::purge_error(error))
+            .map(|_| ())
+    }
+}
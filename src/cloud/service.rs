@@ -1,45 +1,59 @@
-use std::{io::Read, str::FromStr};
+use std::str::FromStr;
 
-use aws_sdk_rekognition::model::{BoundingBox, bounding_box};
-use image::DynamicImage;
 use rusoto_core::{ByteStream, Region};
 use rusoto_s3::{
-    CreateBucketConfiguration, CreateBucketRequest, GetObjectRequest, PutObjectRequest, S3Client,
-    S3,
+    CreateBucketConfiguration, CreateBucketRequest, DeleteObjectRequest, GetObjectRequest,
+    HeadBucketRequest, PutObjectAclRequest, PutObjectRequest, S3Client, S3,
 };
+use tokio_util::io::ReaderStream;
+
+use crate::{cdn, errors::AppError};
+
+const PUBLIC_ACL: &str = "public-read";
+const PRIVATE_ACL: &str = "private";
+
+// S3 compatible storages such as MinIO or the test stub are reached through S3_ENDPOINT.
+fn s3_region(region: &str) -> Result<Region, AppError> {
+    match std::env::var("S3_ENDPOINT") {
+        Ok(endpoint) => Ok(Region::Custom {
+            name: region.to_string(),
+            endpoint,
+        }),
+        Err(_) => Region::from_str(region).map_err(|_| AppError::s3_error("Can not load region.")),
+    }
+}
 
-use crate::errors::AppError;
-    
 pub struct CloudClient {
     s3: S3Client,
     bucket_name: String,
     region: String,
+    url_template: Option<String>,
 }
 
 impl CloudClient {
     pub fn new(bucket_name: String, region: String) -> Result<CloudClient, AppError> {
-        let s3_region = Region::from_str(region.as_str())
-            .map_err(|_| AppError::s3_error("Can not load region."))?;
+        let s3_region = s3_region(&region)?;
         Ok(CloudClient {
             region,
             bucket_name,
             s3: S3Client::new(s3_region),
+            url_template: None,
         })
     }
 
     pub fn new_application_client() -> Result<CloudClient, AppError> {
-        let s3_region = Region::from_str("eu-west-3")
-            .map_err(|_| AppError::s3_error("Can not load region."))?;
+        let s3_region = s3_region("eu-west-3")?;
         Ok(CloudClient {
             region: "eu-west-3".to_string(),
             bucket_name: "user-avatar-stampa".to_string(),
             s3: S3Client::new(s3_region),
+            url_template: None,
         })
     }
 
+    #[tracing::instrument(name = "s3.create_bucket", err)]
     pub async fn create_bucket(bucket_name: String, region: String) -> Result<String, AppError> {
-        let s3_region =
-            Region::from_str(region.as_str()).map_err(|error| AppError::s3_error(error))?;
+        let s3_region = s3_region(&region)?;
         let s3 = S3Client::new(s3_region);
         let location = match region.as_str() {
             "us-east-1" => None,
@@ -57,7 +71,15 @@ impl CloudClient {
         .map(|_| bucket_name)
     }
 
+    pub fn with_url_template(mut self, url_template: Option<String>) -> CloudClient {
+        self.url_template = url_template;
+        self
+    }
+
     pub fn url(&self, key: &str) -> String {
+        if let Some(url_template) = &self.url_template {
+            return cdn::public_url(url_template, &self.bucket_name, &self.region, key);
+        }
         format!(
             "https://{}.s3.{}.amazonaws.com/{}",
             self.bucket_name, self.region, key
@@ -65,29 +87,68 @@ impl CloudClient {
     }
 
     pub async fn put_object(&self, path: &str, key: &str) -> Result<String, AppError> {
-        let mut file = std::fs::File::open(path)
+        self.upload_object(path, key, PUBLIC_ACL).await
+    }
+
+    pub async fn put_private_object(&self, path: &str, key: &str) -> Result<String, AppError> {
+        self.upload_object(path, key, PRIVATE_ACL).await
+    }
+
+    #[tracing::instrument(name = "s3.put_object", skip(self, path), fields(bucket = %self.bucket_name), err)]
+    async fn upload_object(&self, path: &str, key: &str, acl: &str) -> Result<String, AppError> {
+        let file = tokio::fs::File::open(path)
+            .await
             .map_err(|_| AppError::fs_error("Can not read temporary avatar."))?;
-        let mut contents: Vec<u8> = Vec::new();
-        let _ = file.read_to_end(&mut contents);
+        let size = file
+            .metadata()
+            .await
+            .map_err(|_| AppError::fs_error("Can not read temporary avatar."))?
+            .len();
 
         let put_request = PutObjectRequest {
-            acl: Some("public-read".to_string()),
+            acl: Some(acl.to_string()),
             bucket: self.bucket_name.to_owned(),
             key: key.to_owned(),
-            body: Some(contents.into()),
+            content_length: Some(size as i64),
+            body: Some(ByteStream::new_with_size(
+                ReaderStream::new(file),
+                size as usize,
+            )),
             ..Default::default()
         };
 
         self.s3
             .put_object(put_request)
             .await
-            .map_err(|error| {
-                println!("{}", error);
-                AppError::s3_error(error)
-            })
+            .map_err(|error| AppError::s3_error(error))
             .map(|_| self.url(key))
     }
 
+    pub async fn publish_object(&self, key: &str) -> Result<(), AppError> {
+        self.set_object_acl(key, PUBLIC_ACL).await
+    }
+
+    pub async fn unpublish_object(&self, key: &str) -> Result<(), AppError> {
+        self.set_object_acl(key, PRIVATE_ACL).await
+    }
+
+    #[tracing::instrument(name = "s3.put_object_acl", skip(self), fields(bucket = %self.bucket_name), err)]
+    async fn set_object_acl(&self, key: &str, acl: &str) -> Result<(), AppError> {
+        let acl_request = PutObjectAclRequest {
+            acl: Some(acl.to_string()),
+            bucket: self.bucket_name.to_owned(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+
+        self.s3
+            .put_object_acl(acl_request)
+            .await
+            .map_err(|error| AppError::s3_error(error))
+            .map(|_| ())
+    }
+
+    #[tracing::instrument(name = "s3.get_object", skip(self), fields(bucket = %self.bucket_name), err)]
     pub async fn get_object(&self, key: &str) -> Result<ByteStream, AppError> {
         let get_request = GetObjectRequest {
             bucket: self.bucket_name.to_owned(),
@@ -106,35 +167,29 @@ impl CloudClient {
             })?
     }
 
-    pub async fn detect_face(
-        client: aws_sdk_rekognition::Client,
-        image: aws_sdk_rekognition::model::Image,
-    ) -> Result<BoundingBox, AppError> {
-        Ok(client
-            .detect_faces()
-            .image(image)
-            .attributes(aws_sdk_rekognition::model::Attribute::All)
-            .send()
+    #[tracing::instrument(name = "s3.delete_object", skip(self), fields(bucket = %self.bucket_name), err)]
+    pub async fn delete_object(&self, key: &str) -> Result<(), AppError> {
+        let delete_request = DeleteObjectRequest {
+            bucket: self.bucket_name.to_owned(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+
+        self.s3
+            .delete_object(delete_request)
             .await
-            .map_err(|error| AppError::s3_error(error))?
-            .face_details
-            .ok_or(AppError::s3_error("Can not get face details."))?[0]
-            .bounding_box()
-            .ok_or(AppError::s3_error("Can not get bouding boxes."))?
-            .to_owned())
+            .map_err(|error| AppError::s3_error(error))
+            .map(|_| ())
     }
 
-    pub fn crop_face(
-        mut avatar: DynamicImage,
-        h: u32,
-        w: u32,
-        bounding_box: BoundingBox,
-    ) -> DynamicImage {
-        let left = bounding_box.left().unwrap() * w as f32;
-        let top = bounding_box.top().unwrap() * h as f32;
-        let width = bounding_box.width().unwrap() * w as f32;
-        let height = bounding_box.height().unwrap() * h as f32;
-        
-        avatar.crop(left as u32, top as u32, width as u32, height as u32)
+    #[tracing::instrument(name = "s3.head_bucket", skip(self), fields(bucket = %self.bucket_name), err)]
+    pub async fn check_bucket(&self) -> Result<(), AppError> {
+        self.s3
+            .head_bucket(HeadBucketRequest {
+                bucket: self.bucket_name.to_owned(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|error| AppError::s3_error(error))
     }
 }
@@ -1,6 +1,10 @@
-use std::fmt::{self};
+use std::{
+    fmt::{self},
+    str::FromStr,
+};
 
 use dotenv::dotenv;
+use mongodb::bson::oid::ObjectId;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -9,6 +13,95 @@ pub struct Config {
     pub port: i32,
     pub database_url: String,
     pub database_name: String,
+    #[serde(default = "default_mail_transport")]
+    pub mail_transport: String,
+    #[serde(default = "default_mail_from")]
+    pub mail_from: String,
+    #[serde(default = "default_mail_directory")]
+    pub mail_directory: String,
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    #[serde(default = "default_face_detector")]
+    pub face_detector: String,
+    pub face_model_path: Option<String>,
+    #[serde(default = "default_moderator")]
+    pub moderator: String,
+    pub gravatar_project: Option<String>,
+    #[serde(default = "default_public_url")]
+    pub public_url: String,
+    #[serde(default = "default_render_cache_bytes")]
+    pub render_cache_bytes: u64,
+    pub url_template: Option<String>,
+    #[serde(default = "default_purge_hook")]
+    pub purge_hook: String,
+    pub purge_webhook_url: Option<String>,
+    pub purge_webhook_token: Option<String>,
+    #[serde(default)]
+    pub cors_allowed_origins: String,
+    #[serde(default = "default_domain_refresh_seconds")]
+    pub domain_refresh_seconds: u64,
+    #[serde(default = "default_log_format")]
+    pub log_format: String,
+    #[serde(default = "default_readiness_timeout_ms")]
+    pub readiness_timeout_ms: u64,
+    #[serde(default = "default_shutdown_grace_seconds")]
+    pub shutdown_grace_seconds: u64,
+    #[serde(default = "default_migration_retry_seconds")]
+    pub migration_retry_seconds: u64,
+}
+
+fn default_mail_transport() -> String {
+    "file".to_string()
+}
+
+fn default_mail_from() -> String {
+    "stampa <no-reply@stampa.local>".to_string()
+}
+
+fn default_mail_directory() -> String {
+    "./tmp/mails".to_string()
+}
+
+fn default_face_detector() -> String {
+    "none".to_string()
+}
+
+fn default_moderator() -> String {
+    "local".to_string()
+}
+
+fn default_public_url() -> String {
+    "http://localhost:8080".to_string()
+}
+
+fn default_render_cache_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_purge_hook() -> String {
+    "none".to_string()
+}
+
+fn default_domain_refresh_seconds() -> u64 {
+    30
+}
+
+fn default_log_format() -> String {
+    "text".to_string()
+}
+
+fn default_readiness_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_shutdown_grace_seconds() -> u64 {
+    5
+}
+
+fn default_migration_retry_seconds() -> u64 {
+    5
 }
 
 impl Config {
@@ -30,10 +123,15 @@ impl Config {
             .map_err(|_| "Failed to load configuration from environment.".into())
     }
 
+    pub fn gravatar_project_id(&self) -> Result<Option<ObjectId>, Box<dyn std::error::Error>> {
+        match &self.gravatar_project {
+            Some(project_id) => Ok(Some(ObjectId::from_str(project_id)?)),
+            None => Ok(None),
+        }
+    }
+
     pub async fn connect_mongo(&self) -> Result<mongodb::Database, Box<dyn std::error::Error>> {
-        let client = mongodb::Client::with_uri_str(&self.database_url)
-            .await
-            .expect("Failed to connect to MongoDB.");
+        let client = mongodb::Client::with_uri_str(&self.database_url).await?;
         Ok(client.database(&self.database_name))
     }
 }
@@ -0,0 +1,192 @@
+mod verification;
+
+pub use verification::*;
+
+use std::{collections::HashMap, sync::RwLock, time::Duration};
+
+use actix_web::{
+    http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ORIGIN},
+    web, HttpRequest, HttpResponseBuilder,
+};
+use mongodb::{bson::oid::ObjectId, Database};
+
+use crate::{errors::AppError, models::DomainPolicy, repositories::ProjectRepository, AppState};
+
+// CORS checks and Host routing happen on every request, so project domain policies are
+// kept in memory. The instance handling a settings update refreshes them at once, the
+// others reload them periodically.
+pub struct DomainRegistry {
+    global_origins: Vec<String>,
+    project_origins: RwLock<HashMap<ObjectId, Vec<String>>>,
+    custom_domains: RwLock<HashMap<String, ObjectId>>,
+}
+
+impl DomainRegistry {
+    pub fn new(global_origins: Vec<String>) -> DomainRegistry {
+        DomainRegistry {
+            global_origins: global_origins
+                .iter()
+                .map(|origin| normalize_origin(origin))
+                .collect(),
+            project_origins: RwLock::new(HashMap::new()),
+            custom_domains: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Policies are rebuilt from scratch so projects that dropped their origins or domain
+    // on another instance are forgotten too.
+    pub async fn load(&self, database: &Database) -> Result<(), AppError> {
+        let domain_policies = ProjectRepository::new(database.clone())
+            .get_domain_policies()
+            .await?;
+        let registry = DomainRegistry::new(Vec::new());
+        for (project_id, domain_policy) in domain_policies {
+            registry.update(project_id, &domain_policy);
+        }
+        *self.project_origins.write().unwrap() = registry.project_origins.into_inner().unwrap();
+        *self.custom_domains.write().unwrap() = registry.custom_domains.into_inner().unwrap();
+        Ok(())
+    }
+
+    pub fn update(&self, project_id: ObjectId, domain_policy: &DomainPolicy) {
+        self.project_origins.write().unwrap().insert(
+            project_id,
+            domain_policy
+                .allowed_origins
+                .iter()
+                .map(|origin| normalize_origin(origin))
+                .collect(),
+        );
+        let mut custom_domains = self.custom_domains.write().unwrap();
+        custom_domains.retain(|_, domain_project_id| *domain_project_id != project_id);
+        // A custom domain only routes to its project once its ownership was verified.
+        if let Some(custom_domain) = &domain_policy.custom_domain {
+            if domain_policy.custom_domain_verified {
+                custom_domains.insert(normalize_host(custom_domain), project_id);
+            }
+        }
+    }
+
+    // API routes are not tied to a single project, so a project's origins must not open
+    // them for every other project; only the global list applies.
+    pub fn origin_allowed(&self, origin: &str) -> bool {
+        self.global_origins.contains(&normalize_origin(origin))
+    }
+
+    pub fn project_origin_allowed(&self, project_id: ObjectId, origin: &str) -> bool {
+        let origin = normalize_origin(origin);
+        self.global_origins.contains(&origin)
+            || self
+                .project_origins
+                .read()
+                .unwrap()
+                .get(&project_id)
+                .map_or(false, |origins| origins.contains(&origin))
+    }
+
+    pub fn project_for_host(&self, host: &str) -> Option<ObjectId> {
+        self.custom_domains
+            .read()
+            .unwrap()
+            .get(&normalize_host(host))
+            .copied()
+    }
+
+    pub fn request_project(&self, request: &HttpRequest) -> Option<ObjectId> {
+        self.project_for_host(request.connection_info().host())
+    }
+
+    // Delivery routes answer for a single project, so they only echo that project's origins.
+    pub fn apply_cors<'a>(
+        &self,
+        request: &HttpRequest,
+        project_id: Option<ObjectId>,
+        response: &'a mut HttpResponseBuilder,
+    ) -> &'a mut HttpResponseBuilder {
+        let origin = request
+            .headers()
+            .get(ORIGIN)
+            .and_then(|value| value.to_str().ok())
+            .filter(|origin| match project_id {
+                Some(project_id) => self.project_origin_allowed(project_id, origin),
+                None => self.global_origins.contains(&normalize_origin(origin)),
+            });
+        if let Some(origin) = origin {
+            response.insert_header((ACCESS_CONTROL_ALLOW_ORIGIN, origin.to_string()));
+        }
+        response
+    }
+}
+
+pub async fn refresh_periodically(app_state: web::Data<AppState>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(error) = app_state.domains.load(&app_state.database).await {
+            log::warn!("Can not refresh the project domain policies: {:?}", error);
+        }
+    }
+}
+
+pub fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_lowercase()
+}
+
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim().to_lowercase();
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name.to_string(),
+        _ => host,
+    }
+}
+
+pub fn url_host(url: &str) -> Option<String> {
+    let authority = url
+        .split_once("://")?
+        .1
+        .split(|c| matches!(c, '/' | '?' | '#'))
+        .next()?;
+    let host = normalize_host(
+        authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host),
+    );
+    match host.is_empty() {
+        true => None,
+        false => Some(host),
+    }
+}
+
+pub fn parse_origins(origins: &str) -> Vec<String> {
+    origins
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub fn valid_origin(origin: &str) -> bool {
+    let origin = normalize_origin(origin);
+    let host = match origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+    {
+        Some(host) => host,
+        None => return false,
+    };
+    !host.is_empty() && !host.contains('/')
+}
+
+pub fn valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').count() >= 2
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use trust_dns_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+
+use crate::errors::AppError;
+
+const CHALLENGE_LABEL: &str = "_stampa-challenge";
+
+#[async_trait]
+pub trait DomainVerifier: Send + Sync {
+    async fn txt_records(&self, name: &str) -> Vec<String>;
+}
+
+pub struct DnsDomainVerifier {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsDomainVerifier {
+    pub fn new() -> Result<DnsDomainVerifier, AppError> {
+        let resolver = match TokioAsyncResolver::tokio_from_system_conf() {
+            Ok(resolver) => resolver,
+            Err(_) => TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+                .map_err(|error| AppError::domain_error(error))?,
+        };
+        Ok(DnsDomainVerifier { resolver })
+    }
+}
+
+#[async_trait]
+impl DomainVerifier for DnsDomainVerifier {
+    // A missing record and an unreachable resolver both leave the domain unverified.
+    async fn txt_records(&self, name: &str) -> Vec<String> {
+        match self.resolver.txt_lookup(name).await {
+            Ok(lookup) => lookup
+                .iter()
+                .map(|txt| {
+                    txt.txt_data()
+                        .iter()
+                        .map(|data| String::from_utf8_lossy(data).into_owned())
+                        .collect()
+                })
+                .collect(),
+            Err(error) => {
+                log::debug!("TXT lookup of {} failed: {:?}", name, error);
+                Vec::new()
+            }
+        }
+    }
+}
+
+pub fn challenge_name(custom_domain: &str) -> String {
+    format!("{}.{}", CHALLENGE_LABEL, custom_domain)
+}
+
+pub fn challenge_value(token: &str) -> String {
+    format!("stampa-verification={}", token)
+}
+
+pub async fn verify_domain(
+    verifier: &dyn DomainVerifier,
+    custom_domain: &str,
+    token: &str,
+) -> bool {
+    let expected = challenge_value(token);
+    verifier
+        .txt_records(&challenge_name(custom_domain))
+        .await
+        .iter()
+        .any(|record| record.trim() == expected)
+}
@@ -2,6 +2,9 @@ use std::fmt::{self, Display};
 
 use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use serde::Serialize;
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
 
 #[derive(Debug)]
 pub enum AppErrorType {
@@ -9,10 +12,24 @@ pub enum AppErrorType {
     NotFoundError,
     NotInProject,
     S3Error,
+    FsError,
     LoginError,
     AvatarGenerationError,
     UserExistError,
     UnvalidFormError,
+    MailError,
+    PayloadTooLargeError,
+    UnsupportedFormatError,
+    ImageDimensionsError,
+    FaceDetectionError,
+    NoFaceError,
+    ModerationError,
+    ForbiddenError,
+    DuplicateError,
+    AnimationError,
+    PurgeError,
+    MetricsError,
+    DomainError,
 }
 
 #[derive(Debug)]
@@ -20,6 +37,45 @@ pub struct AppError {
     pub message: Option<String>,
     pub cause: Option<String>,
     pub error_type: AppErrorType,
+    pub details: Vec<FieldError>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl AppErrorType {
+    // Codes are part of the API contract, clients match on them instead of messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppErrorType::DbError => "database_error",
+            AppErrorType::NotFoundError => "not_found",
+            AppErrorType::NotInProject => "not_in_project",
+            AppErrorType::S3Error => "storage_error",
+            AppErrorType::FsError => "filesystem_error",
+            AppErrorType::LoginError => "invalid_credentials",
+            AppErrorType::AvatarGenerationError => "image_processing_error",
+            AppErrorType::UserExistError => "user_exists",
+            AppErrorType::UnvalidFormError => "invalid_request",
+            AppErrorType::MailError => "mail_error",
+            AppErrorType::PayloadTooLargeError => "payload_too_large",
+            AppErrorType::UnsupportedFormatError => "unsupported_format",
+            AppErrorType::ImageDimensionsError => "invalid_dimensions",
+            AppErrorType::FaceDetectionError => "face_detection_error",
+            AppErrorType::NoFaceError => "no_face",
+            AppErrorType::ModerationError => "moderation_error",
+            AppErrorType::ForbiddenError => "forbidden",
+            AppErrorType::DuplicateError => "duplicate",
+            AppErrorType::AnimationError => "invalid_animation",
+            AppErrorType::PurgeError => "purge_error",
+            AppErrorType::MetricsError => "metrics_error",
+            AppErrorType::DomainError => "domain_error",
+        }
+    }
 }
 
 impl AppError {
@@ -27,14 +83,9 @@ impl AppError {
         match &*self {
             AppError {
                 message: Some(message),
-                cause: _,
-                error_type: _,
+                ..
             } => message.clone(),
-            AppError {
-                message: None,
-                cause: _,
-                error_type: _,
-            } => "An internal error occured".to_string(),
+            AppError { message: None, .. } => "An internal error occurred".to_string(),
         }
     }
 
@@ -43,6 +94,7 @@ impl AppError {
             message: None,
             cause: Some(error.to_string()),
             error_type: crate::errors::AppErrorType::DbError,
+            details: Vec::new(),
         }
     }
 
@@ -50,7 +102,8 @@ impl AppError {
         AppError {
             message: None,
             cause: Some(error.to_string()),
-            error_type: crate::errors::AppErrorType::DbError,
+            error_type: crate::errors::AppErrorType::AvatarGenerationError,
+            details: Vec::new(),
         }
     }
 
@@ -61,7 +114,8 @@ impl AppError {
                 ressource_id.to_string()
             )),
             cause: Some(ressource_id.to_string()),
-            error_type: crate::errors::AppErrorType::AvatarGenerationError,
+            error_type: crate::errors::AppErrorType::NotFoundError,
+            details: Vec::new(),
         }
     }
 
@@ -73,22 +127,25 @@ impl AppError {
             )),
             cause: Some(ressource_id.to_string()),
             error_type: crate::errors::AppErrorType::NotInProject,
+            details: Vec::new(),
         }
     }
 
     pub fn s3_error(error: impl ToString) -> AppError {
         AppError {
-            message: Some(format!("Internal error")),
+            message: None,
             cause: Some(error.to_string()),
-            error_type: crate::errors::AppErrorType::NotInProject,
+            error_type: crate::errors::AppErrorType::S3Error,
+            details: Vec::new(),
         }
     }
 
     pub fn fs_error(error: impl ToString) -> AppError {
         AppError {
-            message: Some(format!("Internal error")),
+            message: None,
             cause: Some(error.to_string()),
-            error_type: crate::errors::AppErrorType::NotInProject,
+            error_type: crate::errors::AppErrorType::FsError,
+            details: Vec::new(),
         }
     }
 
@@ -97,6 +154,7 @@ impl AppError {
             message: Some(format!("Can not login user {}.", username.to_string())),
             cause: Some(format!("Can not login user {}.", username.to_string())),
             error_type: crate::errors::AppErrorType::LoginError,
+            details: Vec::new(),
         }
     }
 
@@ -105,6 +163,7 @@ impl AppError {
             message: Some(format!("User {} already exist.", username.to_string())),
             cause: Some(format!("User {} already exist", username.to_string())),
             error_type: crate::errors::AppErrorType::UserExistError,
+            details: Vec::new(),
         }
     }
 
@@ -113,6 +172,179 @@ impl AppError {
             message: Some(error.to_string()),
             cause: Some(error.to_string()),
             error_type: crate::errors::AppErrorType::UnvalidFormError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn validation_error(errors: ValidationErrors) -> AppError {
+        let mut details = Vec::new();
+        flatten_validation_errors(None, &errors, &mut details);
+        details.sort_by(|a, b| a.field.cmp(&b.field));
+        AppError {
+            message: Some("The request contains invalid fields.".to_string()),
+            cause: Some(errors.to_string()),
+            error_type: crate::errors::AppErrorType::UnvalidFormError,
+            details,
+        }
+    }
+
+    pub fn payload_too_large_error(max_bytes: u64) -> AppError {
+        AppError {
+            message: Some(format!(
+                "The uploaded file exceeds the maximum size of {} bytes.",
+                max_bytes
+            )),
+            cause: None,
+            error_type: crate::errors::AppErrorType::PayloadTooLargeError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn unsupported_format_error(format: impl ToString) -> AppError {
+        AppError {
+            message: Some(format!(
+                "The image format {} is not allowed.",
+                format.to_string()
+            )),
+            cause: None,
+            error_type: crate::errors::AppErrorType::UnsupportedFormatError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn image_dimensions_error(error: impl ToString) -> AppError {
+        AppError {
+            message: Some(error.to_string()),
+            cause: None,
+            error_type: crate::errors::AppErrorType::ImageDimensionsError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn face_detection_error(error: impl ToString) -> AppError {
+        AppError {
+            message: None,
+            cause: Some(error.to_string()),
+            error_type: crate::errors::AppErrorType::FaceDetectionError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn no_face_error() -> AppError {
+        AppError {
+            message: Some("No face was found in the image.".to_string()),
+            cause: None,
+            error_type: crate::errors::AppErrorType::NoFaceError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn moderation_error(error: impl ToString) -> AppError {
+        AppError {
+            message: None,
+            cause: Some(error.to_string()),
+            error_type: crate::errors::AppErrorType::ModerationError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn forbidden_error(error: impl ToString) -> AppError {
+        AppError {
+            message: Some(error.to_string()),
+            cause: Some(error.to_string()),
+            error_type: crate::errors::AppErrorType::ForbiddenError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn duplicate_error(avatar_id: impl ToString) -> AppError {
+        AppError {
+            message: Some(format!(
+                "The image is a duplicate of avatar {}.",
+                avatar_id.to_string()
+            )),
+            cause: Some(avatar_id.to_string()),
+            error_type: crate::errors::AppErrorType::DuplicateError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn animation_error(error: impl ToString) -> AppError {
+        AppError {
+            message: Some(error.to_string()),
+            cause: None,
+            error_type: crate::errors::AppErrorType::AnimationError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn purge_error(error: impl ToString) -> AppError {
+        AppError {
+            message: None,
+            cause: Some(error.to_string()),
+            error_type: crate::errors::AppErrorType::PurgeError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn metrics_error(error: impl ToString) -> AppError {
+        AppError {
+            message: None,
+            cause: Some(error.to_string()),
+            error_type: crate::errors::AppErrorType::MetricsError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn domain_error(error: impl ToString) -> AppError {
+        AppError {
+            message: None,
+            cause: Some(error.to_string()),
+            error_type: crate::errors::AppErrorType::DomainError,
+            details: Vec::new(),
+        }
+    }
+
+    pub fn mail_error(error: impl ToString) -> AppError {
+        AppError {
+            message: None,
+            cause: Some(error.to_string()),
+            error_type: crate::errors::AppErrorType::MailError,
+            details: Vec::new(),
+        }
+    }
+}
+
+fn flatten_validation_errors(
+    prefix: Option<&str>,
+    errors: &ValidationErrors,
+    details: &mut Vec<FieldError>,
+) {
+    for (field, kind) in errors.errors() {
+        let field = match prefix {
+            Some(prefix) => format!("{}.{}", prefix, field),
+            None => field.to_string(),
+        };
+        match kind {
+            ValidationErrorsKind::Field(field_errors) => {
+                details.extend(field_errors.iter().map(|error| FieldError {
+                    field: field.clone(),
+                    code: error.code.to_string(),
+                    message: error.message.as_ref().map(|message| message.to_string()),
+                }))
+            }
+            ValidationErrorsKind::Struct(errors) => {
+                flatten_validation_errors(Some(&field), errors, details)
+            }
+            ValidationErrorsKind::List(errors) => {
+                for (index, errors) in errors {
+                    flatten_validation_errors(
+                        Some(&format!("{}[{}]", field, index)),
+                        errors,
+                        details,
+                    )
+                }
+            }
         }
     }
 }
@@ -123,9 +355,34 @@ impl Display for AppError {
     }
 }
 
+// RFC 7807 problem details, `code` and `errors` are extension members.
 #[derive(Serialize)]
-pub struct AppErrorResponse {
-    pub error: String,
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl AppError {
+    pub fn problem_details(&self) -> ProblemDetails {
+        let status = self.status_code();
+        ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: self.message(),
+            code: self.error_type.code(),
+            errors: self.details.clone(),
+            request_id: crate::telemetry::request_id(),
+        }
+    }
 }
 
 impl ResponseError for AppError {
@@ -133,18 +390,40 @@ impl ResponseError for AppError {
         match self.error_type {
             AppErrorType::DbError => StatusCode::INTERNAL_SERVER_ERROR,
             AppErrorType::NotFoundError => StatusCode::NOT_FOUND,
-            AppErrorType::NotInProject => StatusCode::UNAUTHORIZED,
+            AppErrorType::NotInProject => StatusCode::FORBIDDEN,
             AppErrorType::LoginError => StatusCode::UNAUTHORIZED,
             AppErrorType::S3Error => StatusCode::INTERNAL_SERVER_ERROR,
+            AppErrorType::FsError => StatusCode::INTERNAL_SERVER_ERROR,
             AppErrorType::AvatarGenerationError => StatusCode::INTERNAL_SERVER_ERROR,
-            AppErrorType::UserExistError => StatusCode::UNAUTHORIZED,
-            AppErrorType::UnvalidFormError => StatusCode::UNAUTHORIZED,
+            AppErrorType::UserExistError => StatusCode::CONFLICT,
+            AppErrorType::UnvalidFormError => StatusCode::BAD_REQUEST,
+            AppErrorType::MailError => StatusCode::INTERNAL_SERVER_ERROR,
+            AppErrorType::PayloadTooLargeError => StatusCode::PAYLOAD_TOO_LARGE,
+            AppErrorType::UnsupportedFormatError => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppErrorType::ImageDimensionsError => StatusCode::UNPROCESSABLE_ENTITY,
+            AppErrorType::FaceDetectionError => StatusCode::INTERNAL_SERVER_ERROR,
+            AppErrorType::NoFaceError => StatusCode::UNPROCESSABLE_ENTITY,
+            AppErrorType::ModerationError => StatusCode::INTERNAL_SERVER_ERROR,
+            AppErrorType::ForbiddenError => StatusCode::FORBIDDEN,
+            AppErrorType::DuplicateError => StatusCode::CONFLICT,
+            AppErrorType::AnimationError => StatusCode::UNPROCESSABLE_ENTITY,
+            AppErrorType::PurgeError => StatusCode::INTERNAL_SERVER_ERROR,
+            AppErrorType::MetricsError => StatusCode::INTERNAL_SERVER_ERROR,
+            AppErrorType::DomainError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).json(AppErrorResponse {
-            error: self.message(),
-        })
+        // Causes can hold database or storage internals, they only ever reach the logs.
+        let code = self.error_type.code();
+        let cause = self.cause.as_deref().unwrap_or_default();
+        if self.status_code().is_server_error() {
+            tracing::error!(code, cause, "request failed");
+        } else {
+            tracing::debug!(code, cause, "request failed");
+        }
+        HttpResponse::build(self.status_code())
+            .content_type(PROBLEM_CONTENT_TYPE)
+            .json(self.problem_details())
     }
 }
@@ -0,0 +1,172 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+
+use super::{FaceBox, FaceDetector};
+use crate::{
+    errors::AppError,
+    models::{CropPolicy, FaceSelection, NoFaceBehavior},
+};
+
+const ENTROPY_SAMPLE_SIZE: u32 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CropRegion {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub async fn detect_crop_region(
+    detector: &dyn FaceDetector,
+    image: &DynamicImage,
+    policy: &CropPolicy,
+) -> Result<Option<CropRegion>, AppError> {
+    // A failing detector is not the same as an image without faces, the upload fails with it.
+    let faces = detector.detect(image).await?;
+    crop_region(image, &faces, policy)
+}
+
+pub fn crop_region(
+    image: &DynamicImage,
+    faces: &[FaceBox],
+    policy: &CropPolicy,
+) -> Result<Option<CropRegion>, AppError> {
+    let (width, height) = (image.width(), image.height());
+    Ok(match select_face(faces, policy.face_selection) {
+        Some(face) => Some(face_region(width, height, face, policy)),
+        None => match policy.no_face {
+            NoFaceBehavior::Reject => return Err(AppError::no_face_error()),
+            NoFaceBehavior::Keep => None,
+            NoFaceBehavior::Center => Some(center_region(width, height)),
+            NoFaceBehavior::Smart => Some(smart_region(image)),
+        },
+    })
+}
+
+pub fn crop_image(
+    image: DynamicImage,
+    faces: &[FaceBox],
+    policy: &CropPolicy,
+) -> Result<DynamicImage, AppError> {
+    Ok(match crop_region(&image, faces, policy)? {
+        Some(region) => image.crop_imm(region.left, region.top, region.width, region.height),
+        None => image,
+    })
+}
+
+pub fn select_face(faces: &[FaceBox], selection: FaceSelection) -> Option<&FaceBox> {
+    let faces = faces
+        .iter()
+        .filter(|face| face.width > 0 && face.height > 0);
+    match selection {
+        FaceSelection::Largest => faces.max_by_key(|face| face.area()),
+        FaceSelection::MostConfident => faces.max_by(|a, b| a.confidence.total_cmp(&b.confidence)),
+    }
+}
+
+pub fn face_region(width: u32, height: u32, face: &FaceBox, policy: &CropPolicy) -> CropRegion {
+    let padding = policy.padding.max(0.0);
+    let mut region_width = face.width as f32 * (1.0 + 2.0 * padding);
+    let mut region_height = face.height as f32 * (1.0 + 2.0 * padding);
+    if policy.square {
+        let side = region_width
+            .max(region_height)
+            .min(width.min(height) as f32);
+        region_width = side;
+        region_height = side;
+    }
+    let region_width = (region_width as u32).clamp(1, width);
+    let region_height = (region_height as u32).clamp(1, height);
+
+    let center_x = face.left as f32 + face.width as f32 / 2.0;
+    let center_y = face.top as f32 + face.height as f32 / 2.0;
+    CropRegion {
+        left: clamp_offset(center_x - region_width as f32 / 2.0, region_width, width),
+        top: clamp_offset(center_y - region_height as f32 / 2.0, region_height, height),
+        width: region_width,
+        height: region_height,
+    }
+}
+
+pub fn center_region(width: u32, height: u32) -> CropRegion {
+    let side = width.min(height);
+    CropRegion {
+        left: (width - side) / 2,
+        top: (height - side) / 2,
+        width: side,
+        height: side,
+    }
+}
+
+fn clamp_offset(offset: f32, size: u32, bound: u32) -> u32 {
+    (offset.max(0.0) as u32).min(bound - size)
+}
+
+fn smart_region(image: &DynamicImage) -> CropRegion {
+    let (width, height) = (image.width(), image.height());
+    let side = width.min(height);
+    if width == height {
+        return center_region(width, height);
+    }
+
+    let sample = image
+        .resize(
+            ENTROPY_SAMPLE_SIZE,
+            ENTROPY_SAMPLE_SIZE,
+            FilterType::Triangle,
+        )
+        .to_luma8();
+    let scale = sample.width().max(sample.height()) as f32 / width.max(height) as f32;
+    let sample_side =
+        ((side as f32 * scale).round() as u32).clamp(1, sample.width().min(sample.height()));
+    let offsets = sample.width().max(sample.height()) - sample_side;
+
+    let best_offset = (0..=offsets)
+        .map(|offset| {
+            let entropy = if width > height {
+                entropy(&sample, offset, 0, sample_side, sample.height())
+            } else {
+                entropy(&sample, 0, offset, sample.width(), sample_side)
+            };
+            (offset, entropy)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(offset, _)| offset)
+        .unwrap_or_default();
+    let offset = ((best_offset as f32 / scale) as u32).min(width.max(height) - side);
+
+    if width > height {
+        CropRegion {
+            left: offset,
+            top: 0,
+            width: side,
+            height: side,
+        }
+    } else {
+        CropRegion {
+            left: 0,
+            top: offset,
+            width: side,
+            height: side,
+        }
+    }
+}
+
+fn entropy(image: &GrayImage, left: u32, top: u32, width: u32, height: u32) -> f32 {
+    let mut histogram = [0u32; 256];
+    for y in top..top + height {
+        for x in left..left + width {
+            histogram[image.get_pixel(x, y).0[0] as usize] += 1;
+        }
+    }
+
+    let total = (width * height) as f32;
+    histogram
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let probability = *count as f32 / total;
+            -probability * probability.log2()
+        })
+        .sum()
+}
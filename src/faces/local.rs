@@ -0,0 +1,91 @@
+use std::{cell::RefCell, collections::HashMap, io::Cursor, sync::Arc};
+
+use async_trait::async_trait;
+use image::DynamicImage;
+use rustface::{Detector, ImageData};
+
+use super::{FaceBox, FaceDetector};
+use crate::errors::AppError;
+
+const MIN_FACE_SIZE: u32 = 20;
+const SCORE_THRESHOLD: f64 = 2.0;
+
+thread_local! {
+    // Detectors hold `Rc`s and can not move between threads, each blocking worker keeps its own.
+    static DETECTORS: RefCell<HashMap<String, Box<dyn Detector>>> = RefCell::new(HashMap::new());
+}
+
+pub struct LocalFaceDetector {
+    model_path: Arc<String>,
+    model: Arc<Vec<u8>>,
+}
+
+impl LocalFaceDetector {
+    pub fn new(model_path: &str) -> Result<LocalFaceDetector, AppError> {
+        let model = std::fs::read(model_path).map_err(|error| {
+            AppError::face_detection_error(format!(
+                "Can not read face model {}: {}",
+                model_path, error
+            ))
+        })?;
+        rustface::read_model(Cursor::new(&model))
+            .map_err(|error| AppError::face_detection_error(error))?;
+        Ok(LocalFaceDetector {
+            model_path: Arc::new(model_path.to_string()),
+            model: Arc::new(model),
+        })
+    }
+}
+
+fn create_detector(model: &[u8]) -> Result<Box<dyn Detector>, AppError> {
+    let model = rustface::read_model(Cursor::new(model))
+        .map_err(|error| AppError::face_detection_error(error))?;
+    let mut detector = rustface::create_detector_with_model(model);
+    detector.set_min_face_size(MIN_FACE_SIZE);
+    detector.set_score_thresh(SCORE_THRESHOLD);
+    detector.set_pyramid_scale_factor(0.8);
+    detector.set_slide_window_step(4, 4);
+    Ok(detector)
+}
+
+#[async_trait]
+impl FaceDetector for LocalFaceDetector {
+    async fn detect(&self, image: &DynamicImage) -> Result<Vec<FaceBox>, AppError> {
+        let model_path = self.model_path.clone();
+        let model = self.model.clone();
+        let gray_image = image.to_luma8();
+
+        // The seeta detector is CPU bound and not Send, so it lives on a blocking thread.
+        // The model is parsed once per blocking thread and reused by the following uploads.
+        tokio::task::spawn_blocking(move || {
+            DETECTORS.with(|detectors| {
+                let mut detectors = detectors.borrow_mut();
+                if !detectors.contains_key(model_path.as_str()) {
+                    detectors.insert(model_path.to_string(), create_detector(&model)?);
+                }
+                let detector = detectors.get_mut(model_path.as_str()).unwrap();
+
+                let (image_width, image_height) = gray_image.dimensions();
+                let image_data = ImageData::new(&gray_image, image_width, image_height);
+                Ok(detector
+                    .detect(&image_data)
+                    .iter()
+                    .filter_map(|face| {
+                        let bounding_box = face.bbox();
+                        FaceBox::from_pixels(
+                            bounding_box.x() as f32,
+                            bounding_box.y() as f32,
+                            bounding_box.width() as f32,
+                            bounding_box.height() as f32,
+                            face.score() as f32,
+                            image_width,
+                            image_height,
+                        )
+                    })
+                    .collect())
+            })
+        })
+        .await
+        .map_err(|error| AppError::face_detection_error(error))?
+    }
+}
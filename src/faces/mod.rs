@@ -0,0 +1,112 @@
+mod crop;
+mod local;
+mod rekognition;
+
+pub use crop::*;
+pub use local::*;
+pub use rekognition::*;
+
+use async_trait::async_trait;
+use image::DynamicImage;
+
+use crate::{config::Config, errors::AppError};
+
+#[derive(Debug, Clone)]
+pub struct FaceBox {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    pub confidence: f32,
+}
+
+impl FaceBox {
+    pub fn from_pixels(
+        left: f32,
+        top: f32,
+        width: f32,
+        height: f32,
+        confidence: f32,
+        image_width: u32,
+        image_height: u32,
+    ) -> Option<FaceBox> {
+        if !(left.is_finite() && top.is_finite() && width.is_finite() && height.is_finite()) {
+            return None;
+        }
+        let right = (left + width).clamp(0.0, image_width as f32);
+        let bottom = (top + height).clamp(0.0, image_height as f32);
+        let left = left.clamp(0.0, image_width as f32);
+        let top = top.clamp(0.0, image_height as f32);
+        if right - left < 1.0 || bottom - top < 1.0 {
+            return None;
+        }
+        Some(FaceBox {
+            left: left as u32,
+            top: top as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+            confidence,
+        })
+    }
+
+    pub fn from_relative(
+        left: f32,
+        top: f32,
+        width: f32,
+        height: f32,
+        confidence: f32,
+        image_width: u32,
+        image_height: u32,
+    ) -> Option<FaceBox> {
+        FaceBox::from_pixels(
+            left * image_width as f32,
+            top * image_height as f32,
+            width * image_width as f32,
+            height * image_height as f32,
+            confidence,
+            image_width,
+            image_height,
+        )
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+#[async_trait]
+pub trait FaceDetector: Send + Sync {
+    async fn detect(&self, image: &DynamicImage) -> Result<Vec<FaceBox>, AppError>;
+}
+
+pub struct NoFaceDetector;
+
+#[async_trait]
+impl FaceDetector for NoFaceDetector {
+    async fn detect(&self, _image: &DynamicImage) -> Result<Vec<FaceBox>, AppError> {
+        Ok(Vec::new())
+    }
+}
+
+pub async fn from_config(config: &Config) -> Result<Box<dyn FaceDetector>, AppError> {
+    match config.face_detector.as_str() {
+        "rekognition" => Ok(Box::new(RekognitionFaceDetector::from_env().await)),
+        // The SeetaFace model is a 1.2 MB binary with its own license, it is not vendored in the
+        // repository nor embedded in the binary, deployments mount it and point FACE_MODEL_PATH at it.
+        "local" => {
+            let model_path =
+                config
+                    .face_model_path
+                    .as_ref()
+                    .ok_or(AppError::face_detection_error(
+                        "FACE_MODEL_PATH is required by the local face detector.",
+                    ))?;
+            Ok(Box::new(LocalFaceDetector::new(model_path)?))
+        }
+        "none" => Ok(Box::new(NoFaceDetector)),
+        detector => Err(AppError::face_detection_error(format!(
+            "Unknown face detector {}.",
+            detector
+        ))),
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::Cursor;
+
+use async_trait::async_trait;
+use aws_sdk_rekognition::{model::Image, types::Blob, Client};
+use image::{imageops::FilterType, DynamicImage, ImageError, ImageOutputFormat};
+
+use super::{FaceBox, FaceDetector};
+use crate::errors::AppError;
+
+pub const MAX_REKOGNITION_SIDE: u32 = 1024;
+const REKOGNITION_JPEG_QUALITY: u8 = 90;
+
+pub struct RekognitionFaceDetector {
+    client: Client,
+}
+
+impl RekognitionFaceDetector {
+    pub async fn from_env() -> RekognitionFaceDetector {
+        let shared_config = aws_config::load_from_env().await;
+        RekognitionFaceDetector {
+            client: Client::new(&shared_config),
+        }
+    }
+}
+
+// Rekognition rejects images over 5 MB and gains nothing from full resolution uploads.
+pub fn rekognition_image(image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    let mut image = DynamicImage::ImageRgb8(image.to_rgb8());
+    if image.width().max(image.height()) > MAX_REKOGNITION_SIDE {
+        image = image.resize(
+            MAX_REKOGNITION_SIDE,
+            MAX_REKOGNITION_SIDE,
+            FilterType::Triangle,
+        );
+    }
+    let mut encoded_image = Vec::new();
+    image.write_to(
+        &mut Cursor::new(&mut encoded_image),
+        ImageOutputFormat::Jpeg(REKOGNITION_JPEG_QUALITY),
+    )?;
+    Ok(encoded_image)
+}
+
+#[async_trait]
+impl FaceDetector for RekognitionFaceDetector {
+    async fn detect(&self, image: &DynamicImage) -> Result<Vec<FaceBox>, AppError> {
+        let encoded_image =
+            rekognition_image(image).map_err(|error| AppError::face_detection_error(error))?;
+
+        let face_details = self
+            .client
+            .detect_faces()
+            .image(Image::builder().bytes(Blob::new(encoded_image)).build())
+            .send()
+            .await
+            .map_err(|error| AppError::face_detection_error(error))?
+            .face_details
+            .unwrap_or_default();
+
+        Ok(face_details
+            .iter()
+            .filter_map(|face_detail| {
+                let bounding_box = face_detail.bounding_box()?;
+                FaceBox::from_relative(
+                    bounding_box.left()?,
+                    bounding_box.top()?,
+                    bounding_box.width()?,
+                    bounding_box.height()?,
+                    face_detail.confidence().unwrap_or_default() / 100.0,
+                    image.width(),
+                    image.height(),
+                )
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,149 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+use sha2::{Digest, Sha256};
+
+use crate::models::Fingerprint;
+
+const HASH_SIZE: u32 = 8;
+const DCT_SIZE: usize = 32;
+
+pub fn compute(image: &DynamicImage) -> Fingerprint {
+    Fingerprint {
+        ahash: to_hex(average_hash(image)),
+        dhash: to_hex(difference_hash(image)),
+        phash: to_hex(perceptual_hash(image)),
+    }
+}
+
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn from_hex(hash: &str) -> Option<u64> {
+    u64::from_str_radix(hash, 16).ok()
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+fn grayscale(image: &DynamicImage, width: u32, height: u32) -> GrayImage {
+    image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_luma8()
+}
+
+pub fn average_hash(image: &DynamicImage) -> u64 {
+    let sample = grayscale(image, HASH_SIZE, HASH_SIZE);
+    let mean =
+        sample.pixels().map(|pixel| pixel.0[0] as u32).sum::<u32>() / (HASH_SIZE * HASH_SIZE);
+
+    sample.pixels().fold(0, |hash, pixel| {
+        (hash << 1) | (pixel.0[0] as u32 > mean) as u64
+    })
+}
+
+pub fn difference_hash(image: &DynamicImage) -> u64 {
+    let sample = grayscale(image, HASH_SIZE + 1, HASH_SIZE);
+    let mut hash = 0;
+    for y in 0..HASH_SIZE {
+        for x in 0..HASH_SIZE {
+            let left = sample.get_pixel(x, y).0[0];
+            let right = sample.get_pixel(x + 1, y).0[0];
+            hash = (hash << 1) | (left < right) as u64;
+        }
+    }
+    hash
+}
+
+pub fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let sample = grayscale(image, DCT_SIZE as u32, DCT_SIZE as u32);
+    let pixels: Vec<f64> = sample.pixels().map(|pixel| pixel.0[0] as f64).collect();
+    let hash_size = HASH_SIZE as usize;
+
+    // Only the low frequencies of the 2D DCT are needed.
+    let mut coefficients = Vec::with_capacity(hash_size * hash_size);
+    for v in 0..hash_size {
+        for u in 0..hash_size {
+            let mut sum = 0.0;
+            for y in 0..DCT_SIZE {
+                for x in 0..DCT_SIZE {
+                    sum += pixels[y * DCT_SIZE + x] * dct_factor(u, x) * dct_factor(v, y);
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    // The DC coefficient only carries the average brightness.
+    let mut sorted_coefficients = coefficients[1..].to_vec();
+    sorted_coefficients.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted_coefficients[sorted_coefficients.len() / 2];
+
+    coefficients.iter().fold(0, |hash, coefficient| {
+        (hash << 1) | (*coefficient > median) as u64
+    })
+}
+
+fn dct_factor(frequency: usize, position: usize) -> f64 {
+    (std::f64::consts::PI * (2 * position + 1) as f64 * frequency as f64 / (2 * DCT_SIZE) as f64)
+        .cos()
+}
+
+pub fn cluster(hashes: &[u64], threshold: u32) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    for a in 0..hashes.len() {
+        for b in a + 1..hashes.len() {
+            if hamming_distance(hashes[a], hashes[b]) <= threshold {
+                let (root_a, root_b) = (find(&mut parents, a), find(&mut parents, b));
+                parents[root_b] = root_a;
+            }
+        }
+    }
+
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    let mut cluster_indexes = vec![None; hashes.len()];
+    for index in 0..hashes.len() {
+        let root = find(&mut parents, index);
+        match cluster_indexes[root] {
+            Some(cluster_index) => clusters[cluster_index].push(index),
+            None => {
+                cluster_indexes[root] = Some(clusters.len());
+                clusters.push(vec![index]);
+            }
+        }
+    }
+    clusters.retain(|cluster| cluster.len() > 1);
+    clusters
+}
+
+// Indexes of the hashes within the threshold of at least one of the targets.
+pub fn near_any(hashes: &[u64], targets: &[u64], threshold: u32) -> Vec<usize> {
+    (0..hashes.len())
+        .filter(|index| {
+            targets
+                .iter()
+                .any(|target| hamming_distance(hashes[*index], *target) <= threshold)
+        })
+        .collect()
+}
+
+fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut current = index;
+    while parents[current] != root {
+        let next = parents[current];
+        parents[current] = root;
+        current = next;
+    }
+    root
+}
@@ -0,0 +1,171 @@
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    avatars::AvatarClient,
+    errors::AppError,
+    models::{EmailHash, Moderation},
+};
+
+pub const DEFAULT_SIZE: u32 = 80;
+pub const MAX_SIZE: u32 = 2048;
+// Requested sizes are rounded up to one of these so arbitrary `s` values can not each
+// trigger a fresh render and fill the render cache.
+pub const SIZE_BUCKETS: [u32; 16] = [
+    16, 24, 32, 48, 64, 80, 96, 128, 160, 200, 256, 400, 512, 800, 1024, MAX_SIZE,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fallback {
+    NotFound,
+    MysteryPerson,
+    Identicon,
+    Initials(String),
+    Retro,
+    Blank,
+    Redirect(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Rating {
+    G,
+    Pg,
+    R,
+    X,
+}
+
+pub fn email_hash(external_id: &str) -> Option<EmailHash> {
+    let email = external_id.trim().to_lowercase();
+    if !validator::validate_email(&email) {
+        return None;
+    }
+    Some(EmailHash {
+        md5: to_hex(&Md5::digest(email.as_bytes())),
+        sha256: to_hex(&Sha256::digest(email.as_bytes())),
+    })
+}
+
+// Gravatar clients append an extension and do not care about the case of the hash.
+pub fn normalize_hash(hash: &str) -> Option<String> {
+    let hash = hash.split('.').next().unwrap_or_default().to_lowercase();
+    match (hash.len(), hash.chars().all(|c| c.is_ascii_hexdigit())) {
+        (32, true) | (64, true) => Some(hash),
+        _ => None,
+    }
+}
+
+pub fn parse_size(size: Option<u32>) -> u32 {
+    let size = size.unwrap_or(DEFAULT_SIZE);
+    SIZE_BUCKETS
+        .iter()
+        .copied()
+        .find(|bucket| *bucket >= size)
+        .unwrap_or(MAX_SIZE)
+}
+
+pub fn parse_rating(rating: Option<&str>) -> Rating {
+    match rating.map(str::to_lowercase).as_deref() {
+        Some("pg") => Rating::Pg,
+        Some("r") => Rating::R,
+        Some("x") => Rating::X,
+        _ => Rating::G,
+    }
+}
+
+// Moderation labels stand in for Gravatar's self-declared ratings.
+pub fn avatar_rating(moderation: &Moderation) -> Rating {
+    moderation
+        .labels
+        .iter()
+        .map(|label| {
+            let names = [Some(label.name.as_str()), label.parent.as_deref()];
+            if names.contains(&Some("Explicit Nudity")) {
+                Rating::X
+            } else if names.iter().any(|name| {
+                matches!(
+                    name,
+                    Some("Suggestive") | Some("Violence") | Some("Visually Disturbing")
+                )
+            }) {
+                Rating::R
+            } else {
+                Rating::Pg
+            }
+        })
+        .fold(Rating::G, |rating, label_rating| {
+            match label_rating > rating {
+                true => label_rating,
+                false => rating,
+            }
+        })
+}
+
+pub fn parse_force_default(force_default: Option<&str>) -> bool {
+    matches!(
+        force_default.map(str::to_lowercase).as_deref(),
+        Some("y") | Some("yes") | Some("true") | Some("1")
+    )
+}
+
+pub fn parse_fallback(
+    fallback: Option<&str>,
+    name: Option<&str>,
+    initials: Option<&str>,
+) -> Fallback {
+    let fallback = match fallback {
+        Some(fallback) => fallback,
+        None => return Fallback::MysteryPerson,
+    };
+    match fallback.to_lowercase().as_str() {
+        "404" => Fallback::NotFound,
+        "mp" | "mm" | "mysteryman" => Fallback::MysteryPerson,
+        "identicon" => Fallback::Identicon,
+        "initials" => Fallback::Initials(initials_text(name, initials)),
+        "retro" => Fallback::Retro,
+        "blank" => Fallback::Blank,
+        _ if fallback.starts_with("http://") || fallback.starts_with("https://") => {
+            Fallback::Redirect(fallback.to_string())
+        }
+        _ => Fallback::MysteryPerson,
+    }
+}
+
+fn initials_text(name: Option<&str>, initials: Option<&str>) -> String {
+    let text: String = match (initials, name) {
+        (Some(initials), _) => initials.chars().take(2).collect(),
+        (None, Some(name)) => name
+            .split_whitespace()
+            .filter_map(|word| word.chars().next())
+            .take(2)
+            .collect(),
+        (None, None) => String::new(),
+    };
+    match text.is_empty() {
+        true => "?".to_string(),
+        false => text.to_uppercase(),
+    }
+}
+
+pub fn render_fallback(fallback: &Fallback, hash: &str, size: u32) -> Result<Vec<u8>, AppError> {
+    let seed = from_hex(hash);
+    match fallback {
+        Fallback::MysteryPerson => AvatarClient::render_mystery_person(size),
+        Fallback::Identicon => AvatarClient::render_identicon(&seed, size),
+        Fallback::Initials(text) => AvatarClient::render_initials(&seed, text, size),
+        Fallback::Retro => AvatarClient::render_retro(&seed, size),
+        Fallback::Blank => AvatarClient::render_blank(size),
+        Fallback::NotFound | Fallback::Redirect(_) => Err(AppError::avatat_generation_error(
+            "The fallback can not be rendered.",
+        )),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hash: &str) -> Vec<u8> {
+    (0..hash.len() / 2)
+        .filter_map(|index| u8::from_str_radix(&hash[index * 2..index * 2 + 2], 16).ok())
+        .collect()
+}
@@ -9,9 +9,11 @@ use crate::{
     avatars::AvatarClient,
     cloud::CloudClient,
     errors::AppError,
-    models::User,
-    repositories::UserRepository,
-    utils::{encode_jwt, Claims},
+    fingerprints,
+    models::{EmailInvitation, OutputPolicy, Rendition, User},
+    placeholders, renditions,
+    repositories::{InvitationRepository, ProjectRepository, UserRepository},
+    utils::{encode_jwt, parse_object_id, Claims},
     AppState,
 };
 
@@ -21,6 +23,9 @@ pub struct RegisterPayload {
     username: String,
     #[validate(length(min = 6))]
     password: String,
+    #[validate(email)]
+    email: Option<String>,
+    invitation_token: Option<String>,
 }
 
 #[derive(Deserialize, Validate)]
@@ -41,43 +46,131 @@ pub async fn register(
     user: web::Json<RegisterPayload>,
 ) -> Result<impl Responder, AppError> {
     user.validate()
-        .map_err(|error| AppError::unvalid_form_error(error))?;
+        .map_err(|error| AppError::validation_error(error))?;
 
     let username = &user.username;
     let password = &user.password;
+    let email = user.email.as_ref().map(|email| email.trim().to_lowercase());
     let user_id = ObjectId::new();
 
     let user_repository = UserRepository::new(app.database.clone());
     user_repository.exist(username).await?;
+    if let Some(email) = &email {
+        user_repository.email_exist(email).await?;
+    }
+    // An unknown token fails before anything is created.
+    let invitation = match &user.invitation_token {
+        Some(token) => Some(
+            InvitationRepository::new(app.database.clone())
+                .get_by_token(token)
+                .await?,
+        ),
+        None => None,
+    };
 
     let hashed_password =
         hash(password.as_str(), DEFAULT_COST).map_err(|error| AppError::db_error(error))?;
 
     let user_avatar = AvatarClient::generate_avatar(&user_id.to_string(), &username[0..2])?;
 
-    let avatar_url = CloudClient::new_application_client()?
-        .put_object(&user_avatar, &user_id.to_string())
-        .await?;
-
-    user_repository
-        .create(User {
-            id: user_id,
-            username: username.to_string(),
-            password: hashed_password,
-            projects: Vec::new(),
-            invitations: Vec::new(),
-            avatar: avatar_url,
-        })
-        .await
-        .map(|_| {
-            encode_jwt(Claims {
-                exp: (Utc::now() + Duration::days(365)).timestamp() as usize,
-                sub: user_id.to_string(),
+    let generated_avatar =
+        image::open(&user_avatar).map_err(|error| AppError::avatat_generation_error(error))?;
+    let (placeholder, encoded_renditions) = tokio::task::spawn_blocking(move || {
+        renditions::encode_all(&generated_avatar, &OutputPolicy::default()).map(
+            |encoded_renditions| (placeholders::compute(&generated_avatar), encoded_renditions),
+        )
+    })
+    .await
+    .map_err(|error| AppError::avatat_generation_error(error))??;
+
+    let cloud_client =
+        CloudClient::new_application_client()?.with_url_template(app.url_template.clone());
+
+    // Objects are tracked as they are uploaded so a failed registration does not leave them
+    // behind in the bucket.
+    let mut uploaded_keys = Vec::new();
+    let created = async {
+        let avatar_url = cloud_client
+            .put_object(&user_avatar, &user_id.to_string())
+            .await?;
+        uploaded_keys.push(user_id.to_string());
+
+        let mut user_renditions = Vec::new();
+        for encoded_rendition in encoded_renditions {
+            let rendition_image = renditions::write(&user_avatar, &encoded_rendition)?;
+            let rendition_storage_key =
+                format!("{}.{}", user_id, encoded_rendition.format.extension());
+            let rendition_url = cloud_client
+                .put_object(&rendition_image.path, &rendition_storage_key)
+                .await?;
+            uploaded_keys.push(rendition_storage_key.clone());
+            user_renditions.push(Rendition {
+                format: encoded_rendition.format,
+                mime_type: encoded_rendition.format.mime_type().to_string(),
+                url: rendition_url,
+                storage_key: rendition_storage_key,
+                size: rendition_image.size,
+                content_hash: Some(fingerprints::content_hash(&encoded_rendition.data)),
+            });
+        }
+
+        user_repository
+            .create(User {
                 id: user_id,
+                username: username.to_string(),
+                password: hashed_password,
+                email: email.clone(),
+                projects: Vec::new(),
+                invitations: Vec::new(),
+                avatar: avatar_url,
+                placeholder: Some(placeholder),
+                renditions: user_renditions,
             })
-            .map_err(|error| AppError::db_error(error))
-            .map(|jwt_token| HttpResponse::Ok().json(RegisterResponse { token: jwt_token }))
-        })
+            .await
+    }
+    .await;
+
+    if let Err(error) = created {
+        for key in uploaded_keys {
+            if let Err(cleanup_error) = cloud_client.delete_object(&key).await {
+                log::warn!(
+                    "Could not delete {} after a failed registration: {:?}",
+                    key,
+                    cleanup_error
+                );
+            }
+        }
+        return Err(error);
+    }
+
+    if let Some(invitation) = invitation {
+        attach_invitation(&app, user_id, invitation).await?;
+    }
+
+    encode_jwt(Claims {
+        exp: (Utc::now() + Duration::days(365)).timestamp() as usize,
+        sub: user_id.to_string(),
+        id: user_id,
+    })
+    .map_err(|error| AppError::db_error(error))
+    .map(|jwt_token| HttpResponse::Ok().json(RegisterResponse { token: jwt_token }))
+}
+
+async fn attach_invitation(
+    app: &web::Data<AppState>,
+    user_id: ObjectId,
+    invitation: EmailInvitation,
+) -> Result<(), AppError> {
+    let project_object_id = parse_object_id(&invitation.project)?;
+    UserRepository::new(app.database.clone())
+        .add_invitation(user_id, &invitation.project)
+        .await?;
+    ProjectRepository::new(app.database.clone())
+        .add_invitation(project_object_id, user_id.to_string().as_str())
+        .await?;
+    InvitationRepository::new(app.database.clone())
+        .delete(invitation.id)
+        .await
 }
 
 pub async fn login(
@@ -85,7 +178,7 @@ pub async fn login(
     user: web::Json<LoginPayload>,
 ) -> Result<impl Responder, AppError> {
     user.validate()
-        .map_err(|error| AppError::unvalid_form_error(error))?;
+        .map_err(|error| AppError::validation_error(error))?;
 
     let username = &user.username;
     let password = &user.password;
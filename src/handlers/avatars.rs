@@ -1,93 +1,1269 @@
-use std::{fs::File, str::FromStr};
+use std::{collections::HashMap, sync::Arc};
 
+use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse, Responder};
-use aws_config::timeout::Http;
-use mongodb::bson::oid::ObjectId;
+use futures::TryStreamExt;
+use image::DynamicImage;
+use mongodb::bson::{oid::ObjectId, DateTime};
 use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
 
 use crate::{
-    avatars::AvatarClient,
+    cdn,
     cloud::CloudClient,
     errors::AppError,
-    models::Avatar,
-    repositories::{ProjectRepository, UserRepository},
-    utils::Claims,
+    faces, fingerprints, gravatar, metrics,
+    models::{
+        AnimationSummary, Avatar, AvatarVersion, Fingerprint, MetadataSummary, Moderation,
+        ModerationStatus, Placeholder, ProjectSettings, Rendition, Visibility,
+    },
+    moderation, placeholders,
+    renditions::{self, EncodedRendition},
+    repositories::{
+        AvatarRepository, AvatarVersionRepository, FingerprintProjection, ProjectProjection,
+        ProjectRepository, UserRepository,
+    },
+    signing::UrlSigner,
+    telemetry,
+    uploads::{
+        animation, metadata::SanitizedImage, read_text_field, TemporaryImage, MAX_UPLOAD_BYTES,
+    },
+    utils::{parse_object_id, Claims},
     AppState,
 };
 
+const DEFAULT_PAGE_LIMIT: u64 = 20;
+const MAX_PAGE_LIMIT: u64 = 100;
+const DEFAULT_DUPLICATE_THRESHOLD: u32 = 5;
+const MAX_DUPLICATE_THRESHOLD: u32 = 32;
+// Clustering compares every pair, so a single request only scans a bounded window of
+// fingerprints and returns a cursor to the next one.
+const DEFAULT_DUPLICATE_SCAN: u64 = 1000;
+const MAX_DUPLICATE_SCAN: u64 = 5000;
+
 #[derive(Serialize, Deserialize)]
 pub struct AvatarUpload {
     name: String,
     project: String,
+    external_id: Option<String>,
+    image: String,
+}
+
+#[derive(Deserialize)]
+pub struct AvatarUploadQuery {
+    name: String,
+    project: String,
+    external_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AvatarListQuery {
+    project: String,
+    page: Option<u64>,
+    limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct AvatarPage {
+    avatars: Vec<Avatar>,
+    page: u64,
+    limit: u64,
+    total: u64,
+}
+
+#[derive(Deserialize)]
+pub struct AvatarRename {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct AvatarReplace {
     image: String,
 }
 
+struct StoredImage {
+    storage_key: String,
+    url: String,
+    extension: String,
+    width: u32,
+    height: u32,
+    metadata: MetadataSummary,
+    moderation: Moderation,
+    placeholder: Placeholder,
+    content_hash: String,
+    fingerprint: Fingerprint,
+    animation: Option<AnimationSummary>,
+    renditions: Vec<Rendition>,
+}
+
+#[derive(Deserialize)]
+pub struct ModerationQueueQuery {
+    project: String,
+}
+
+#[derive(Deserialize)]
+pub struct DuplicateQuery {
+    project: String,
+    threshold: Option<u32>,
+    algorithm: Option<String>,
+    after: Option<String>,
+    limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateClusters {
+    algorithm: String,
+    threshold: u32,
+    clusters: Vec<Vec<Avatar>>,
+    next: Option<String>,
+}
+
+struct PreparedImage {
+    decoded_image: DynamicImage,
+    review_frames: Option<Vec<DynamicImage>>,
+    sanitized_image: SanitizedImage,
+    animation: Option<AnimationSummary>,
+    static_image: Option<TemporaryImage>,
+    encoded_renditions: Vec<EncodedRendition>,
+    placeholder: Placeholder,
+    fingerprint: Fingerprint,
+}
+
+// Everything between the upload and the storage is CPU bound, it runs on a single blocking
+// thread like the renders. Face detectors are async, they are driven from that thread.
+fn prepare_image(
+    app: &AppState,
+    runtime: &Handle,
+    settings: &ProjectSettings,
+    avatar_id: ObjectId,
+    image: TemporaryImage,
+) -> Result<PreparedImage, AppError> {
+    let _timer = metrics::RENDER_DURATION
+        .with_label_values(&["upload"])
+        .start_timer();
+    let upload_policy = &settings.upload_policy;
+    let animation_policy = &settings.animation_policy;
+    let decoded_image = image.validate(upload_policy)?;
+    let metadata = image.read_metadata()?;
+    let orientation = metadata.summary.orientation.unwrap_or(1);
+    let mut decoded_image = metadata.orient(decoded_image);
+    let mut frames = match animation_policy.allow {
+        true => image
+            .decode_frames(animation_policy)?
+            .map(|frames| animation::orient_frames(frames, orientation)),
+        false => None,
+    };
+
+    if settings.crop_policy.enabled {
+        let region = runtime.block_on(faces::detect_crop_region(
+            app.face_detector.as_ref(),
+            &decoded_image,
+            &settings.crop_policy,
+        ))?;
+        if let Some(region) = region {
+            decoded_image =
+                decoded_image.crop_imm(region.left, region.top, region.width, region.height);
+            frames = frames.map(|frames| animation::crop_frames(frames, region));
+        }
+    }
+
+    let frames = frames.map(|frames| animation::resize_frames(frames, animation_policy.max_size));
+    if let Some(frames) = &frames {
+        decoded_image = DynamicImage::ImageRgba8(frames[0].buffer().clone());
+    }
+    let review_frames = frames.as_ref().map(|frames| {
+        moderation::sample_frames(frames, moderation::MAX_REVIEWED_FRAMES)
+            .into_iter()
+            .map(|frame| DynamicImage::ImageRgba8(frame.buffer().clone()))
+            .collect::<Vec<DynamicImage>>()
+    });
+    let (sanitized_image, animation, static_image) = match frames {
+        Some(frames) => {
+            let (sanitized_image, animation) = image.sanitize_frames(frames, metadata)?;
+            let static_image = image.static_rendition(&decoded_image)?;
+            (sanitized_image, Some(animation), Some(static_image))
+        }
+        None => (
+            image.sanitize(&decoded_image, metadata, upload_policy.keep_icc_profile)?,
+            None,
+            None,
+        ),
+    };
+    log::info!(
+        "Stripped metadata of avatar {}: {:?}",
+        avatar_id,
+        sanitized_image.metadata
+    );
+
+    // Animations would lose their motion, so only still images get renditions.
+    let encoded_renditions = match animation {
+        Some(_) => Vec::new(),
+        None => renditions::encode_all(&decoded_image, &settings.output_policy)?,
+    };
+
+    Ok(PreparedImage {
+        placeholder: placeholders::compute(&decoded_image),
+        fingerprint: fingerprints::compute(&decoded_image),
+        decoded_image,
+        review_frames,
+        sanitized_image,
+        animation,
+        static_image,
+        encoded_renditions,
+    })
+}
+
+async fn upload_image(
+    app: &web::Data<AppState>,
+    project: &ProjectProjection,
+    avatar_id: ObjectId,
+    image: TemporaryImage,
+) -> Result<StoredImage, AppError> {
+    let upload_policy = &project.settings.upload_policy;
+    let blocking_app = app.clone();
+    let settings = project.settings.clone();
+    let runtime = Handle::current();
+    let PreparedImage {
+        decoded_image,
+        review_frames,
+        sanitized_image,
+        animation,
+        static_image,
+        encoded_renditions,
+        placeholder,
+        fingerprint,
+    } = tokio::task::spawn_blocking(move || {
+        prepare_image(&blocking_app, &runtime, &settings, avatar_id, image)
+    })
+    .await
+    .map_err(|error| AppError::avatat_generation_error(error))??;
+
+    if upload_policy.reject_duplicates {
+        let duplicate = AvatarRepository::new(app.database.clone())
+            .get_by_content_hash(project._id, &sanitized_image.content_hash)
+            .await?;
+        if let Some(duplicate) = duplicate.filter(|duplicate| duplicate._id != avatar_id) {
+            return Err(AppError::duplicate_error(duplicate._id));
+        }
+    }
+
+    let moderation = match &review_frames {
+        Some(review_frames) => {
+            moderation::review_frames(
+                app.moderator.as_ref(),
+                review_frames,
+                &project.settings.moderation_policy,
+            )
+            .await
+        }
+        None => {
+            moderation::review(
+                app.moderator.as_ref(),
+                &decoded_image,
+                &project.settings.moderation_policy,
+            )
+            .await
+        }
+    };
+
+    let extension = sanitized_image.image.extension();
+    let storage_key = format!("{}-{}.{}", avatar_id, ObjectId::new(), extension);
+    let cloud_client = CloudClient::new(project._id.to_string(), project.region.clone())?
+        .with_url_template(
+            project
+                .settings
+                .delivery_policy
+                .url_template
+                .clone()
+                .or_else(|| app.url_template.clone()),
+        );
+    let visibility = project.settings.access_policy.visibility;
+    if moderation.status != ModerationStatus::Approved {
+        log::info!(
+            "Avatar {} is waiting for review: {:?}",
+            avatar_id,
+            moderation.labels
+        );
+    }
+    let url = put_moderated_object(
+        &cloud_client,
+        &sanitized_image.image.path,
+        &storage_key,
+        &moderation,
+        visibility,
+    )
+    .await?;
+
+    let animation = match (animation, static_image) {
+        (Some(mut animation), Some(static_image)) => {
+            let static_storage_key = format!("{}-{}-static.png", avatar_id, ObjectId::new());
+            animation.static_url = Some(
+                put_moderated_object(
+                    &cloud_client,
+                    &static_image.path,
+                    &static_storage_key,
+                    &moderation,
+                    visibility,
+                )
+                .await?,
+            );
+            animation.static_storage_key = Some(static_storage_key);
+            Some(animation)
+        }
+        (animation, _) => animation,
+    };
+
+    let mut stored_renditions = Vec::new();
+    for encoded_rendition in encoded_renditions {
+        let rendition_image = sanitized_image.image.rendition(&encoded_rendition)?;
+        let rendition_storage_key = format!(
+            "{}-{}.{}",
+            avatar_id,
+            ObjectId::new(),
+            encoded_rendition.format.extension()
+        );
+        let rendition_url = put_moderated_object(
+            &cloud_client,
+            &rendition_image.path,
+            &rendition_storage_key,
+            &moderation,
+            visibility,
+        )
+        .await?;
+        stored_renditions.push(Rendition {
+            format: encoded_rendition.format,
+            mime_type: encoded_rendition.format.mime_type().to_string(),
+            storage_key: rendition_storage_key,
+            url: rendition_url,
+            size: rendition_image.size,
+            content_hash: Some(fingerprints::content_hash(&encoded_rendition.data)),
+        });
+    }
+
+    Ok(StoredImage {
+        storage_key,
+        url,
+        extension,
+        width: sanitized_image.width,
+        height: sanitized_image.height,
+        metadata: sanitized_image.metadata,
+        moderation,
+        placeholder,
+        content_hash: sanitized_image.content_hash,
+        fingerprint,
+        animation,
+        renditions: stored_renditions,
+    })
+}
+
+// Avatars with a malformed fingerprint can not be compared, they are left out of the clusters.
+fn parse_hashes(projections: Vec<FingerprintProjection>, algorithm: &str) -> Vec<(ObjectId, u64)> {
+    projections
+        .into_iter()
+        .filter_map(|projection| {
+            let hash = match algorithm {
+                "ahash" => projection.fingerprint.ahash,
+                "dhash" => projection.fingerprint.dhash,
+                _ => projection.fingerprint.phash,
+            };
+            match fingerprints::from_hex(&hash) {
+                Some(hash) => Some((projection._id, hash)),
+                None => {
+                    log::warn!(
+                        "Avatar {} has an invalid {} fingerprint: {}",
+                        projection._id,
+                        algorithm,
+                        hash
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+async fn put_moderated_object(
+    cloud_client: &CloudClient,
+    path: &str,
+    key: &str,
+    moderation: &Moderation,
+    visibility: Visibility,
+) -> Result<String, AppError> {
+    match (moderation.status, visibility) {
+        (ModerationStatus::Approved, Visibility::Public) => {
+            cloud_client.put_object(path, key).await
+        }
+        _ => cloud_client.put_private_object(path, key).await,
+    }
+}
+
+// Urls served by stampa itself keep pointing at the current image, so a CDN in front of
+// them has to forget the previous one. Surrogate keys cover their query string variants.
+fn delivery_purge(app: &web::Data<AppState>, avatar: &Avatar) -> cdn::Purge {
+    let public_url = app.public_url.trim_end_matches('/');
+    let mut purge = cdn::Purge {
+        urls: vec![format!("{}/media/{}", public_url, avatar._id)],
+        surrogate_keys: vec![cdn::avatar_surrogate_key(avatar._id)],
+    };
+    if app.gravatar_project == Some(avatar.project) {
+        if let Some(email_hash) = &avatar.email_hash {
+            for hash in [&email_hash.md5, &email_hash.sha256] {
+                purge.urls.push(format!("{}/avatar/{}", public_url, hash));
+                purge.surrogate_keys.push(cdn::gravatar_surrogate_key(hash));
+            }
+        }
+    }
+    purge
+}
+
+async fn url_signer(
+    app: &web::Data<AppState>,
+    project_id: ObjectId,
+) -> Result<Option<UrlSigner>, AppError> {
+    let access_policy = ProjectRepository::new(app.database.clone())
+        .get(project_id)
+        .await?
+        .settings
+        .access_policy;
+    match access_policy.visibility {
+        Visibility::Public => Ok(None),
+        Visibility::Private => Ok(Some(UrlSigner {
+            public_url: app.public_url.clone(),
+            signing_key: ProjectRepository::new(app.database.clone())
+                .get_signing_key(project_id)
+                .await?,
+            ttl: access_policy.url_ttl,
+            review: false,
+        })),
+    }
+}
+
+// Avatars waiting for review are never public, whatever the project visibility.
+async fn review_url_signer(
+    app: &web::Data<AppState>,
+    project: &ProjectProjection,
+) -> Result<UrlSigner, AppError> {
+    Ok(UrlSigner {
+        public_url: app.public_url.clone(),
+        signing_key: ProjectRepository::new(app.database.clone())
+            .get_signing_key(project._id)
+            .await?,
+        ttl: project.settings.access_policy.url_ttl,
+        review: true,
+    })
+}
+
+fn sign_avatars(url_signer: &Option<UrlSigner>, avatars: Vec<Avatar>) -> Vec<Avatar> {
+    match url_signer {
+        Some(url_signer) => avatars
+            .into_iter()
+            .map(|avatar| url_signer.sign_avatar(avatar))
+            .collect(),
+        None => avatars,
+    }
+}
+
+async fn present_avatar(
+    app: &web::Data<AppState>,
+    avatar: Avatar,
+) -> Result<HttpResponse, AppError> {
+    let url_signer = url_signer(app, avatar.project).await?;
+    let avatar = sign_avatars(&url_signer, vec![avatar]).remove(0);
+    Ok(HttpResponse::Ok().json(avatar))
+}
+
+async fn get_admin_avatar(
+    app: &web::Data<AppState>,
+    user_id: ObjectId,
+    avatar_id: &str,
+) -> Result<(Avatar, ProjectProjection), AppError> {
+    let avatar = get_member_avatar(app, user_id, avatar_id).await?;
+    let project = ProjectRepository::new(app.database.clone())
+        .get(avatar.project)
+        .await?;
+    if project.author != user_id {
+        return Err(AppError::forbidden_error(
+            "Only the project author can review avatars.",
+        ));
+    }
+    Ok((avatar, project))
+}
+
+async fn set_moderation_status(
+    app: &web::Data<AppState>,
+    avatar: Avatar,
+    status: ModerationStatus,
+    reviewer: ObjectId,
+) -> Result<Avatar, AppError> {
+    let moderation = Moderation {
+        status,
+        reviewed_by: Some(reviewer),
+        reviewed_at: Some(DateTime::now()),
+        ..avatar.moderation.clone()
+    };
+    AvatarRepository::new(app.database.clone())
+        .set_moderation(avatar._id, &moderation)
+        .await?;
+    log::info!(
+        "Avatar {} moderation set to {:?} by {}",
+        avatar._id,
+        status,
+        reviewer
+    );
+
+    Ok(Avatar {
+        moderation,
+        ..avatar
+    })
+}
+
+async fn get_member_avatar(
+    app: &web::Data<AppState>,
+    user_id: ObjectId,
+    avatar_id: &str,
+) -> Result<Avatar, AppError> {
+    let avatar_object_id = parse_object_id(avatar_id)?;
+    let avatar = AvatarRepository::new(app.database.clone())
+        .get(avatar_object_id)
+        .await?;
+    telemetry::record_project_id(avatar.project);
+
+    UserRepository::new(app.database.clone())
+        .in_project(user_id, &avatar.project.to_string())
+        .await?;
+
+    Ok(avatar)
+}
+
+async fn get_member_external_avatar(
+    app: &web::Data<AppState>,
+    user_id: ObjectId,
+    project_id: &str,
+    external_id: &str,
+) -> Result<Avatar, AppError> {
+    let project_object_id = parse_object_id(project_id)?;
+
+    UserRepository::new(app.database.clone())
+        .in_project(user_id, project_id)
+        .await?;
+
+    AvatarRepository::new(app.database.clone())
+        .get_by_external_id(project_object_id, external_id)
+        .await?
+        .ok_or(AppError::not_found_error(external_id))
+}
+
+async fn prune_versions(
+    app: &web::Data<AppState>,
+    cloud_client: &CloudClient,
+    avatar_id: ObjectId,
+    retention: u32,
+) -> Result<(), AppError> {
+    let repository = AvatarVersionRepository::new(app.database.clone());
+    let versions = repository.get_avatar_versions(avatar_id).await?;
+
+    let mut purged_urls = Vec::new();
+    for version in versions.into_iter().skip(retention as usize) {
+        cloud_client.delete_object(&version.storage_key).await?;
+        for derived_key in version.derived_keys() {
+            cloud_client.delete_object(&derived_key).await?;
+        }
+        repository.delete(version.id).await?;
+        purged_urls.extend(version.object_urls());
+    }
+    cdn::purge(
+        &app.purge_hook,
+        cdn::Purge {
+            urls: purged_urls,
+            surrogate_keys: Vec::new(),
+        },
+    );
+    Ok(())
+}
+
+async fn replace_image(
+    app: &web::Data<AppState>,
+    avatar: Avatar,
+    image: TemporaryImage,
+    uploader: ObjectId,
+) -> Result<Avatar, AppError> {
+    let project = ProjectRepository::new(app.database.clone())
+        .get(avatar.project)
+        .await?;
+
+    let stored_image = upload_image(app, &project, avatar._id, image).await?;
+
+    let new_avatar = Avatar {
+        mime_type: stored_image.extension,
+        url: stored_image.url,
+        storage_key: Some(stored_image.storage_key),
+        width: stored_image.width,
+        height: stored_image.height,
+        uploader: Some(uploader),
+        updated_at: Some(DateTime::now()),
+        metadata: Some(stored_image.metadata),
+        moderation: stored_image.moderation,
+        placeholder: Some(stored_image.placeholder),
+        content_hash: Some(stored_image.content_hash),
+        fingerprint: Some(stored_image.fingerprint),
+        animation: stored_image.animation,
+        renditions: stored_image.renditions,
+        ..avatar.clone()
+    };
+    store_version(app, &project, new_avatar).await
+}
+
+// The image the new avatar replaced is kept as a version.
+async fn store_version(
+    app: &web::Data<AppState>,
+    project: &ProjectProjection,
+    new_avatar: Avatar,
+) -> Result<Avatar, AppError> {
+    let cloud_client = CloudClient::new(project._id.to_string(), project.region.clone())?;
+
+    let avatar = AvatarRepository::new(app.database.clone())
+        .swap(&new_avatar)
+        .await?;
+    AvatarVersionRepository::new(app.database.clone())
+        .create(AvatarVersion::from_avatar(&avatar))
+        .await?;
+    cdn::purge(&app.purge_hook, delivery_purge(app, &avatar));
+
+    prune_versions(
+        app,
+        &cloud_client,
+        avatar._id,
+        project.settings.version_retention,
+    )
+    .await
+    .map(|_| new_avatar)
+}
+
+async fn rollback(
+    app: &web::Data<AppState>,
+    avatar: Avatar,
+    version_id: &str,
+) -> Result<Avatar, AppError> {
+    let version_object_id = parse_object_id(version_id)?;
+    let version_repository = AvatarVersionRepository::new(app.database.clone());
+    let version = version_repository
+        .get(avatar._id, version_object_id)
+        .await?;
+    let project = ProjectRepository::new(app.database.clone())
+        .get(avatar.project)
+        .await?;
+    let cloud_client = CloudClient::new(avatar.project.to_string(), project.region)?;
+
+    let restored_avatar = Avatar {
+        mime_type: version.mime_type,
+        url: version.url,
+        storage_key: Some(version.storage_key),
+        width: version.width,
+        height: version.height,
+        uploader: version.uploader,
+        updated_at: Some(DateTime::now()),
+        metadata: version.metadata,
+        moderation: version.moderation,
+        placeholder: version.placeholder,
+        content_hash: version.content_hash,
+        fingerprint: version.fingerprint,
+        animation: version.animation,
+        renditions: version.renditions,
+        ..avatar.clone()
+    };
+
+    version_repository
+        .create(AvatarVersion::from_avatar(&avatar))
+        .await?;
+    version_repository.delete(version.id).await?;
+    AvatarRepository::new(app.database.clone())
+        .replace(&restored_avatar)
+        .await?;
+    cdn::purge(&app.purge_hook, delivery_purge(app, &avatar));
+
+    prune_versions(
+        app,
+        &cloud_client,
+        avatar._id,
+        project.settings.version_retention,
+    )
+    .await
+    .map(|_| restored_avatar)
+}
+
+async fn rename(
+    app: &web::Data<AppState>,
+    mut avatar: Avatar,
+    name: &str,
+) -> Result<Avatar, AppError> {
+    AvatarRepository::new(app.database.clone())
+        .rename(avatar._id, name)
+        .await?;
+
+    avatar.name = name.to_string();
+    Ok(avatar)
+}
+
+async fn remove(app: &web::Data<AppState>, avatar: Avatar) -> Result<(), AppError> {
+    let project = ProjectRepository::new(app.database.clone())
+        .get(avatar.project)
+        .await?;
+
+    let cloud_client = CloudClient::new(avatar.project.to_string(), project.region)?;
+    cloud_client.delete_object(&avatar.key()).await?;
+    for derived_key in avatar.derived_keys() {
+        cloud_client.delete_object(&derived_key).await?;
+    }
+    prune_versions(app, &cloud_client, avatar._id, 0).await?;
+
+    AvatarRepository::new(app.database.clone())
+        .delete(avatar._id)
+        .await?;
+    let mut purge = delivery_purge(app, &avatar);
+    purge.urls.extend(avatar.object_urls());
+    cdn::purge(&app.purge_hook, purge);
+    Ok(())
+}
+
+async fn create(
+    app: &web::Data<AppState>,
+    user_id: ObjectId,
+    upload: AvatarUploadQuery,
+    image: TemporaryImage,
+) -> Result<Avatar, AppError> {
+    let project_object_id = parse_object_id(&upload.project)?;
+    let avatar_id = ObjectId::new();
+
+    if let Some(external_id) = &upload.external_id {
+        let existing_avatar = AvatarRepository::new(app.database.clone())
+            .get_by_external_id(project_object_id, external_id)
+            .await?;
+        if let Some(existing_avatar) = existing_avatar {
+            let existing_avatar = Avatar {
+                name: upload.name,
+                ..existing_avatar
+            };
+            return replace_image(app, existing_avatar, image, user_id).await;
+        }
+    }
+
+    let project = ProjectRepository::new(app.database.clone())
+        .get(project_object_id)
+        .await?;
+
+    let stored_image = upload_image(app, &project, avatar_id, image).await?;
+
+    let new_avatar = Avatar {
+        _id: avatar_id,
+        project: project_object_id,
+        mime_type: stored_image.extension,
+        name: upload.name,
+        url: stored_image.url,
+        storage_key: Some(stored_image.storage_key),
+        width: stored_image.width,
+        height: stored_image.height,
+        uploader: Some(user_id),
+        updated_at: Some(DateTime::now()),
+        metadata: Some(stored_image.metadata),
+        moderation: stored_image.moderation,
+        placeholder: Some(stored_image.placeholder),
+        content_hash: Some(stored_image.content_hash),
+        fingerprint: Some(stored_image.fingerprint),
+        animation: stored_image.animation,
+        renditions: stored_image.renditions,
+        email_hash: upload.external_id.as_deref().and_then(gravatar::email_hash),
+        external_id: upload.external_id,
+    };
+
+    let avatar_repository = AvatarRepository::new(app.database.clone());
+    if avatar_repository.create_if_absent(&new_avatar).await? {
+        // The gravatar urls of this email may have been cached with a fallback image.
+        cdn::purge(&app.purge_hook, delivery_purge(app, &new_avatar));
+        return Ok(new_avatar);
+    }
+
+    // A concurrent upload claimed the external id first, the stored image becomes its new version.
+    let external_id = new_avatar
+        .external_id
+        .clone()
+        .ok_or(AppError::db_error(format!(
+            "Avatar {} already exists.",
+            new_avatar._id
+        )))?;
+    let existing_avatar = avatar_repository
+        .get_by_external_id(project_object_id, &external_id)
+        .await?
+        .ok_or(AppError::not_found_error(&external_id))?;
+    let new_avatar = Avatar {
+        _id: existing_avatar._id,
+        ..new_avatar
+    };
+    store_version(app, &project, new_avatar).await
+}
+
 pub async fn create_avatar(
     avatar: web::Json<AvatarUpload>,
     app: web::Data<AppState>,
     claims: Option<web::ReqData<Claims>>,
 ) -> Result<impl Responder, AppError> {
     let user_id = claims.unwrap().id;
-    let project_object_id =
-        ObjectId::from_str(&avatar.project).map_err(|error| AppError::db_error(error))?;
-    let repository = ProjectRepository::new(app.database.clone());
-    let avatar_id = ObjectId::new();
-    let key = avatar_id.to_string();
+    let avatar = avatar.into_inner();
+    telemetry::record_project_id(&avatar.project);
 
     UserRepository::new(app.database.clone())
         .in_project(user_id, &avatar.project)
         .await?;
 
-    let project = repository
-        .get(project_object_id)
+    let image = TemporaryImage::from_data_url(
+        &ObjectId::new().to_string(),
+        &avatar.image,
+        MAX_UPLOAD_BYTES,
+    )?;
+    let upload = AvatarUploadQuery {
+        name: avatar.name,
+        project: avatar.project,
+        external_id: avatar.external_id,
+    };
+    let avatar = create(&app, user_id, upload, image).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn upload_avatar_multipart(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    mut payload: Multipart,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let mut name = None;
+    let mut project = None;
+    let mut external_id = None;
+    let mut image = None;
+
+    while let Some(mut field) = payload
+        .try_next()
         .await
-        .map_err(|error| AppError::db_error(error))?;
+        .map_err(|error| AppError::unvalid_form_error(error))?
+    {
+        let field_name = field
+            .content_disposition()
+            .get_name()
+            .unwrap_or_default()
+            .to_string();
+        match field_name.as_str() {
+            "image" => {
+                image = Some(
+                    TemporaryImage::from_stream(
+                        &ObjectId::new().to_string(),
+                        &mut field,
+                        MAX_UPLOAD_BYTES,
+                    )
+                    .await?,
+                );
+            }
+            "name" => name = Some(read_text_field(&mut field).await?),
+            "project" => project = Some(read_text_field(&mut field).await?),
+            "external_id" => external_id = Some(read_text_field(&mut field).await?),
+            _ => {}
+        }
+    }
+
+    let upload = AvatarUploadQuery {
+        name: name.ok_or(AppError::unvalid_form_error("The name field is required."))?,
+        project: project.ok_or(AppError::unvalid_form_error(
+            "The project field is required.",
+        ))?,
+        external_id,
+    };
+    let image = image.ok_or(AppError::unvalid_form_error("The image field is required."))?;
+
+    telemetry::record_project_id(&upload.project);
+    UserRepository::new(app.database.clone())
+        .in_project(user_id, &upload.project)
+        .await?;
 
-    let base64split = avatar.image.split(",").collect::<Vec<&str>>();
-    let image_extension = base64split[0].split(";").collect::<Vec<&str>>()[0]
-        .split("/")
-        .collect::<Vec<&str>>()[1];
-    let filepath = format!("./tmp/{}.{}", key, image_extension);
-    let image_body = base64split[1];
+    let avatar = create(&app, user_id, upload, image).await?;
+    present_avatar(&app, avatar).await
+}
 
-    let decoded_avatar = base64::decode(image_body).map_err(|error| AppError::fs_error(error))?;
-    let tmp_image =
-        image::load_from_memory(&decoded_avatar).map_err(|error| AppError::fs_error(error))?;
-    tmp_image
-        .save(&filepath)
-        .map_err(|error| AppError::fs_error(error))?;
+pub async fn upload_avatar_raw(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    query: web::Query<AvatarUploadQuery>,
+    payload: web::Payload,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let upload = query.into_inner();
 
-    let url = CloudClient::new(avatar.project.clone(), project.region)?
-        .put_object(&filepath, format!("{}.{}", &key, image_extension).as_str())
+    telemetry::record_project_id(&upload.project);
+    UserRepository::new(app.database.clone())
+        .in_project(user_id, &upload.project)
         .await?;
 
-    std::fs::remove_file(&filepath)
-        .map_err(|_| AppError::fs_error("Can not delete temporary avatar."))?;
+    let image =
+        TemporaryImage::from_stream(&ObjectId::new().to_string(), payload, MAX_UPLOAD_BYTES)
+            .await?;
+    let avatar = create(&app, user_id, upload, image).await?;
+    present_avatar(&app, avatar).await
+}
 
-    let new_avatar = Avatar {
-        _id: avatar_id,
-        mime_type: image_extension.to_string(),
-        name: avatar.name.to_string(),
-        url: url.to_string(),
-    };
+pub async fn get_avatars(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    query: web::Query<AvatarListQuery>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    telemetry::record_project_id(&query.project);
+    let project_object_id = parse_object_id(&query.project)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
 
-    repository
-        .add_avatar(project_object_id, new_avatar)
-        .await
-        .map(|_| HttpResponse::Ok().json(avatar))
+    UserRepository::new(app.database.clone())
+        .in_project(user_id, &query.project)
+        .await?;
+
+    let (avatars, total) = AvatarRepository::new(app.database.clone())
+        .get_project_avatars(project_object_id, (page - 1) * limit, limit)
+        .await?;
+    let url_signer = url_signer(&app, project_object_id).await?;
+    Ok(HttpResponse::Ok().json(AvatarPage {
+        avatars: sign_avatars(&url_signer, avatars),
+        page,
+        limit,
+        total,
+    }))
 }
 
 pub async fn get_avatar(
-    avatar: web::Json<AvatarUpload>,
     app: web::Data<AppState>,
     claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let avatar = get_member_avatar(&app, user_id, &path).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn get_external_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let (project_id, external_id) = path.into_inner();
+    let avatar = get_member_external_avatar(&app, user_id, &project_id, &external_id).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn rename_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+    payload: web::Json<AvatarRename>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let avatar = get_member_avatar(&app, user_id, &path).await?;
+    let avatar = rename(&app, avatar, &payload.name).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn rename_external_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<AvatarRename>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let (project_id, external_id) = path.into_inner();
+    let avatar = get_member_external_avatar(&app, user_id, &project_id, &external_id).await?;
+    let avatar = rename(&app, avatar, &payload.name).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn replace_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+    payload: web::Json<AvatarReplace>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let avatar = get_member_avatar(&app, user_id, &path).await?;
+    let image = TemporaryImage::from_data_url(
+        &ObjectId::new().to_string(),
+        &payload.image,
+        MAX_UPLOAD_BYTES,
+    )?;
+    let avatar = replace_image(&app, avatar, image, user_id).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn replace_avatar_raw(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+    payload: web::Payload,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let avatar = get_member_avatar(&app, user_id, &path).await?;
+    let image =
+        TemporaryImage::from_stream(&ObjectId::new().to_string(), payload, MAX_UPLOAD_BYTES)
+            .await?;
+    let avatar = replace_image(&app, avatar, image, user_id).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn replace_external_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<(String, String)>,
+    payload: web::Json<AvatarReplace>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let (project_id, external_id) = path.into_inner();
+    let avatar = get_member_external_avatar(&app, user_id, &project_id, &external_id).await?;
+    let image = TemporaryImage::from_data_url(
+        &ObjectId::new().to_string(),
+        &payload.image,
+        MAX_UPLOAD_BYTES,
+    )?;
+    let avatar = replace_image(&app, avatar, image, user_id).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn replace_external_avatar_raw(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<(String, String)>,
+    payload: web::Payload,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let (project_id, external_id) = path.into_inner();
+    let avatar = get_member_external_avatar(&app, user_id, &project_id, &external_id).await?;
+    let image =
+        TemporaryImage::from_stream(&ObjectId::new().to_string(), payload, MAX_UPLOAD_BYTES)
+            .await?;
+    let avatar = replace_image(&app, avatar, image, user_id).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn delete_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let avatar = get_member_avatar(&app, user_id, &path).await?;
+    remove(&app, avatar)
+        .await
+        .map(|_| HttpResponse::NoContent())
+}
+
+pub async fn delete_external_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let (project_id, external_id) = path.into_inner();
+    let avatar = get_member_external_avatar(&app, user_id, &project_id, &external_id).await?;
+    remove(&app, avatar)
+        .await
+        .map(|_| HttpResponse::NoContent())
+}
+
+pub async fn get_avatar_versions(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let avatar = get_member_avatar(&app, user_id, &path).await?;
+    let versions = AvatarVersionRepository::new(app.database.clone())
+        .get_avatar_versions(avatar._id)
+        .await?;
+    let versions: Vec<AvatarVersion> = match url_signer(&app, avatar.project).await? {
+        Some(url_signer) => versions
+            .into_iter()
+            .map(|version| url_signer.sign_version(version))
+            .collect(),
+        None => versions,
+    };
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+pub async fn rollback_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let (avatar_id, version_id) = path.into_inner();
+    let avatar = get_member_avatar(&app, user_id, &avatar_id).await?;
+    let avatar = rollback(&app, avatar, &version_id).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn get_moderation_queue(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    query: web::Query<ModerationQueueQuery>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    telemetry::record_project_id(&query.project);
+    let project_object_id = parse_object_id(&query.project)?;
+    let project = ProjectRepository::new(app.database.clone())
+        .get(project_object_id)
+        .await?;
+    if project.author != user_id {
+        return Err(AppError::forbidden_error(
+            "Only the project author can review avatars.",
+        ));
+    }
+
+    let avatars = AvatarRepository::new(app.database.clone())
+        .get_pending_avatars(project_object_id)
+        .await?;
+    let url_signer = review_url_signer(&app, &project).await?;
+    Ok(HttpResponse::Ok().json(sign_avatars(&Some(url_signer), avatars)))
+}
+
+pub async fn approve_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
 ) -> Result<impl Responder, AppError> {
     let user_id = claims.unwrap().id;
-    let repository = ProjectRepository::new(app.database.clone());
+    let (avatar, project) = get_admin_avatar(&app, user_id, &path).await?;
+
+    // Private projects keep their objects private and serve them through signed URLs.
+    if project.settings.access_policy.visibility == Visibility::Public {
+        let cloud_client = CloudClient::new(avatar.project.to_string(), project.region)?;
+        cloud_client.publish_object(&avatar.key()).await?;
+        for derived_key in avatar.derived_keys() {
+            cloud_client.publish_object(&derived_key).await?;
+        }
+    }
+    let avatar = set_moderation_status(&app, avatar, ModerationStatus::Approved, user_id).await?;
+    present_avatar(&app, avatar).await
+}
+
+pub async fn reject_avatar(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    let (avatar, _) = get_admin_avatar(&app, user_id, &path).await?;
+    let avatar = set_moderation_status(&app, avatar, ModerationStatus::Rejected, user_id).await?;
+    let mut purge = delivery_purge(&app, &avatar);
+    purge.urls.extend(avatar.object_urls());
+    cdn::purge(&app.purge_hook, purge);
+    present_avatar(&app, avatar).await
+}
+
+pub async fn get_duplicate_avatars(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    query: web::Query<DuplicateQuery>,
+) -> Result<impl Responder, AppError> {
+    let user_id = claims.unwrap().id;
+    telemetry::record_project_id(&query.project);
+    let project_object_id = parse_object_id(&query.project)?;
+    let threshold = query
+        .threshold
+        .unwrap_or(DEFAULT_DUPLICATE_THRESHOLD)
+        .min(MAX_DUPLICATE_THRESHOLD);
+    let algorithm = query.algorithm.clone().unwrap_or("phash".to_string());
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DUPLICATE_SCAN)
+        .clamp(1, MAX_DUPLICATE_SCAN);
+    let after = match &query.after {
+        Some(after) => Some(parse_object_id(after)?),
+        None => None,
+    };
+    if !["ahash", "dhash", "phash"].contains(&algorithm.as_str()) {
+        return Err(AppError::unvalid_form_error(
+            "The algorithm must be one of ahash, dhash or phash.",
+        ));
+    }
 
     UserRepository::new(app.database.clone())
-        .in_project(user_id, &avatar.project)
+        .in_project(user_id, &query.project)
         .await?;
 
-    Ok(HttpResponse::Ok())
+    let avatar_repository = AvatarRepository::new(app.database.clone());
+    let projections = avatar_repository
+        .get_fingerprints(project_object_id, after, None, limit)
+        .await?;
+    let next = match projections.len() as u64 == limit {
+        true => projections.last().map(|projection| projection._id.to_hex()),
+        false => None,
+    };
+    let window = parse_hashes(projections, &algorithm);
+    let window_hashes = Arc::new(window.iter().map(|(_, hash)| *hash).collect::<Vec<u64>>());
+
+    // Avatars of the earlier windows join the clusters of this one when they are close to one of
+    // its avatars, so duplicates uploaded far apart are still reported.
+    let mut candidates = Vec::new();
+    let mut cursor = None;
+    let mut earlier_scanned = after.is_none() || window_hashes.is_empty();
+    while !earlier_scanned {
+        let batch = avatar_repository
+            .get_fingerprints(project_object_id, cursor, after, MAX_DUPLICATE_SCAN)
+            .await?;
+        earlier_scanned = (batch.len() as u64) < MAX_DUPLICATE_SCAN;
+        cursor = batch.last().map(|projection| projection._id);
+        let batch = parse_hashes(batch, &algorithm);
+        let batch_hashes = batch.iter().map(|(_, hash)| *hash).collect::<Vec<u64>>();
+        let targets = window_hashes.clone();
+        let near_indexes = tokio::task::spawn_blocking(move || {
+            fingerprints::near_any(&batch_hashes, &targets, threshold)
+        })
+        .await
+        .map_err(|error| AppError::avatat_generation_error(error))?;
+        candidates.extend(near_indexes.into_iter().map(|index| batch[index]));
+    }
+    candidates.extend(window);
+
+    let hashes = candidates
+        .iter()
+        .map(|(_, hash)| *hash)
+        .collect::<Vec<u64>>();
+    let clusters = tokio::task::spawn_blocking(move || fingerprints::cluster(&hashes, threshold))
+        .await
+        .map_err(|error| AppError::avatat_generation_error(error))?;
+
+    // Only avatars that ended up in a cluster are loaded in full.
+    let avatar_ids = clusters
+        .iter()
+        .flatten()
+        .map(|index| candidates[*index].0)
+        .collect();
+    let avatars: HashMap<ObjectId, Avatar> = avatar_repository
+        .get_many(project_object_id, avatar_ids)
+        .await?
+        .into_iter()
+        .map(|avatar| (avatar._id, avatar))
+        .collect();
+
+    let url_signer = url_signer(&app, project_object_id).await?;
+    let clusters = clusters
+        .into_iter()
+        .map(|cluster| {
+            let avatars = cluster
+                .into_iter()
+                .filter_map(|index| avatars.get(&candidates[index].0).cloned())
+                .collect();
+            sign_avatars(&url_signer, avatars)
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(DuplicateClusters {
+        algorithm,
+        threshold,
+        clusters,
+        next,
+    }))
 }
@@ -0,0 +1,226 @@
+use std::io::Cursor;
+
+use actix_web::{
+    http::header::{CACHE_CONTROL, CONTENT_TYPE, LOCATION, VARY},
+    web, HttpRequest, HttpResponse, Responder,
+};
+use futures::TryStreamExt;
+use image::{imageops::FilterType, ImageOutputFormat};
+use serde::Deserialize;
+
+use crate::{
+    caching::{render_cache::RenderedImage, Validators},
+    cdn,
+    cloud::CloudClient,
+    errors::AppError,
+    gravatar::{self, Fallback},
+    metrics,
+    models::{Avatar, ModerationStatus, Visibility},
+    repositories::{AvatarRepository, ProjectRepository},
+    telemetry, AppState,
+};
+
+const RENDER_CACHE_HEADER: &str = "x-render-cache";
+
+#[derive(Deserialize)]
+pub struct GravatarQuery {
+    s: Option<u32>,
+    size: Option<u32>,
+    d: Option<String>,
+    default: Option<String>,
+    r: Option<String>,
+    rating: Option<String>,
+    f: Option<String>,
+    forcedefault: Option<String>,
+    name: Option<String>,
+    initials: Option<String>,
+}
+
+pub async fn get_gravatar(
+    app: web::Data<AppState>,
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<GravatarQuery>,
+) -> Result<impl Responder, AppError> {
+    let hash = gravatar::normalize_hash(&path);
+    let size = gravatar::parse_size(query.s.or(query.size));
+    let rating = gravatar::parse_rating(query.r.as_deref().or(query.rating.as_deref()));
+    let force_default =
+        gravatar::parse_force_default(query.f.as_deref().or(query.forcedefault.as_deref()));
+
+    // A custom domain serves its own project's avatars instead of the global gravatar project.
+    let project_id = app
+        .domains
+        .request_project(&request)
+        .or(app.gravatar_project);
+    if let Some(project_id) = project_id {
+        telemetry::record_project_id(project_id);
+    }
+    let project = match (&hash, project_id, force_default) {
+        (Some(_), Some(project_id), false) => Some(
+            ProjectRepository::new(app.database.clone())
+                .get(project_id)
+                .await?,
+        ),
+        _ => None,
+    };
+    // Private avatars are only delivered through signed media URLs, lookups get the fallback.
+    let avatar = match (&hash, project) {
+        (Some(hash), Some(project))
+            if project.settings.access_policy.visibility == Visibility::Public =>
+        {
+            AvatarRepository::new(app.database.clone())
+                .get_by_email_hash(project._id, hash)
+                .await?
+                .filter(|avatar| avatar.moderation.status == ModerationStatus::Approved)
+                .filter(|avatar| gravatar::avatar_rating(&avatar.moderation) <= rating)
+                .map(|avatar| (avatar, project.region))
+        }
+        _ => None,
+    };
+    let surrogate_keys = hash
+        .as_deref()
+        .map(cdn::gravatar_surrogate_key)
+        .into_iter()
+        .chain(
+            avatar
+                .as_ref()
+                .map(|(avatar, _)| cdn::avatar_surrogate_key(avatar._id)),
+        )
+        .collect::<Vec<String>>()
+        .join(" ");
+    if let Some((avatar, region)) = avatar {
+        let tag = format!(
+            "{}-{}",
+            avatar.content_hash.clone().unwrap_or_else(|| avatar.key()),
+            size
+        );
+        let validators = Validators::new(
+            &tag,
+            avatar.updated_at.unwrap_or_else(|| avatar._id.timestamp()),
+        );
+        if validators.not_modified(&request) {
+            return Ok(app
+                .domains
+                .apply_cors(
+                    &request,
+                    project_id,
+                    validators.apply(&mut HttpResponse::NotModified()),
+                )
+                .insert_header((VARY, "Origin"))
+                .insert_header((CACHE_CONTROL, "public, max-age=300"))
+                .insert_header((cdn::SURROGATE_KEY, surrogate_keys))
+                .finish());
+        }
+        let (resized_image, cache_status) = app
+            .render_cache
+            .get_or_render(&format!("gravatar:{}", tag), || {
+                render_avatar(&avatar, region, size)
+            })
+            .await?;
+        return Ok(app
+            .domains
+            .apply_cors(
+                &request,
+                project_id,
+                validators.apply(&mut HttpResponse::Ok()),
+            )
+            .insert_header((VARY, "Origin"))
+            .insert_header((CONTENT_TYPE, resized_image.mime_type))
+            .insert_header((CACHE_CONTROL, "public, max-age=300"))
+            .insert_header((cdn::SURROGATE_KEY, surrogate_keys))
+            .insert_header((RENDER_CACHE_HEADER, cache_status.as_str()))
+            .body(resized_image.data));
+    }
+
+    let fallback = gravatar::parse_fallback(
+        query.d.as_deref().or(query.default.as_deref()),
+        query.name.as_deref(),
+        query.initials.as_deref(),
+    );
+    match fallback {
+        Fallback::NotFound => Err(AppError::not_found_error(path.into_inner())),
+        Fallback::Redirect(url) => Ok(HttpResponse::Found()
+            .insert_header((LOCATION, url))
+            .finish()),
+        fallback => {
+            let seed = hash.unwrap_or_default();
+            let (fallback_image, cache_status) = app
+                .render_cache
+                .get_or_render(
+                    &format!("fallback:{:?}:{}:{}", fallback, seed, size),
+                    || {
+                        let (fallback, seed) = (fallback.clone(), seed.clone());
+                        async move {
+                            tokio::task::spawn_blocking(move || {
+                                let _timer = metrics::RENDER_DURATION
+                                    .with_label_values(&["fallback"])
+                                    .start_timer();
+                                gravatar::render_fallback(&fallback, &seed, size)
+                            })
+                            .await
+                            .map_err(|error| AppError::avatat_generation_error(error))?
+                            .map(|data| RenderedImage {
+                                data: data.into(),
+                                mime_type: "image/png",
+                            })
+                        }
+                    },
+                )
+                .await?;
+            Ok(app
+                .domains
+                .apply_cors(&request, project_id, &mut HttpResponse::Ok())
+                .insert_header((VARY, "Origin"))
+                .insert_header((CONTENT_TYPE, fallback_image.mime_type))
+                .insert_header((CACHE_CONTROL, "public, max-age=300"))
+                .insert_header((cdn::SURROGATE_KEY, surrogate_keys))
+                .insert_header((RENDER_CACHE_HEADER, cache_status.as_str()))
+                .body(fallback_image.data))
+        }
+    }
+}
+
+// Gravatar images are always square, animations are served as their static rendition.
+async fn render_avatar(
+    avatar: &Avatar,
+    region: String,
+    size: u32,
+) -> Result<RenderedImage, AppError> {
+    let storage_key = avatar
+        .animation
+        .as_ref()
+        .and_then(|animation| animation.static_storage_key.clone())
+        .unwrap_or_else(|| avatar.key());
+    let original = CloudClient::new(avatar.project.to_string(), region)?
+        .get_object(&storage_key)
+        .await?
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await
+        .map_err(|error| AppError::s3_error(error))?;
+
+    let (output_format, mime_type) = match avatar.mime_type.as_str() {
+        "jpg" | "jpeg" => (ImageOutputFormat::Jpeg(90), "image/jpeg"),
+        _ => (ImageOutputFormat::Png, "image/png"),
+    };
+    let encoded_image = tokio::task::spawn_blocking(move || {
+        let _timer = metrics::RENDER_DURATION
+            .with_label_values(&["gravatar"])
+            .start_timer();
+        let resized_image = image::load_from_memory(&original)
+            .map_err(|error| AppError::avatat_generation_error(error))?
+            .resize_to_fill(size, size, FilterType::Lanczos3);
+        let mut encoded_image = Vec::new();
+        resized_image
+            .write_to(&mut Cursor::new(&mut encoded_image), output_format)
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+        Ok::<_, AppError>(encoded_image)
+    })
+    .await
+    .map_err(|error| AppError::avatat_generation_error(error))??;
+    Ok(RenderedImage {
+        data: encoded_image.into(),
+        mime_type,
+    })
+}
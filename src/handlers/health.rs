@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use actix_web::{web, HttpResponse, Responder};
+use futures::TryFutureExt;
+use mongodb::bson::doc;
+use serde::Serialize;
+
+use crate::{
+    cloud::CloudClient,
+    errors::AppError,
+    health::{self, DependencyState, DependencyStatus},
+    AppState,
+};
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+    status: &'static str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, DependencyStatus>,
+}
+
+pub async fn get_liveness() -> impl Responder {
+    HttpResponse::Ok().json(HealthResponse {
+        status: "ok",
+        checks: BTreeMap::new(),
+    })
+}
+
+pub async fn get_readiness(app: web::Data<AppState>) -> impl Responder {
+    // Fail fast while draining so the orchestrator stops routing before connections close.
+    if app.readiness.is_shutting_down() {
+        return HttpResponse::ServiceUnavailable().json(HealthResponse {
+            status: "shutting_down",
+            checks: BTreeMap::new(),
+        });
+    }
+
+    let timeout = app.readiness.check_timeout;
+    let (mongodb, storage) = futures::join!(
+        health::check(
+            "mongodb",
+            timeout,
+            app.database
+                .run_command(doc! {"ping": 1}, None)
+                .map_err(|error| AppError::db_error(error)),
+        ),
+        health::check("storage", timeout, async {
+            CloudClient::new_application_client()?.check_bucket().await
+        }),
+    );
+    let checks = BTreeMap::from([("mongodb", mongodb), ("storage", storage)]);
+    let dependencies_up = checks
+        .values()
+        .all(|check| check.status == DependencyState::Up);
+    match (dependencies_up, app.readiness.is_migrated()) {
+        (true, true) => HttpResponse::Ok().json(HealthResponse {
+            status: "ready",
+            checks,
+        }),
+        (true, false) => HttpResponse::ServiceUnavailable().json(HealthResponse {
+            status: "migrating",
+            checks,
+        }),
+        (false, _) => HttpResponse::ServiceUnavailable().json(HealthResponse {
+            status: "not_ready",
+            checks,
+        }),
+    }
+}
@@ -0,0 +1,218 @@
+use std::str::FromStr;
+
+use actix_web::{
+    http::header::{ACCEPT, CONTENT_TYPE, VARY},
+    web, HttpRequest, HttpResponse, Responder,
+};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use crate::{
+    caching::{self, Validators},
+    cdn,
+    cloud::CloudClient,
+    errors::AppError,
+    models::{Avatar, AvatarVersion, ModerationStatus, OutputFormat, Visibility},
+    renditions,
+    repositories::{AvatarRepository, AvatarVersionRepository, ProjectRepository},
+    signing, telemetry, AppState,
+};
+
+#[derive(Deserialize)]
+pub struct MediaQuery {
+    #[serde(rename = "static")]
+    still: Option<bool>,
+    v: Option<String>,
+    format: Option<OutputFormat>,
+    version: Option<String>,
+    expires: Option<i64>,
+    signature: Option<String>,
+    review: Option<bool>,
+}
+
+fn version_avatar(avatar: Avatar, version: AvatarVersion) -> Avatar {
+    Avatar {
+        mime_type: version.mime_type,
+        url: version.url,
+        storage_key: Some(version.storage_key),
+        width: version.width,
+        height: version.height,
+        uploader: version.uploader,
+        updated_at: Some(version.created_at),
+        metadata: version.metadata,
+        moderation: version.moderation,
+        placeholder: version.placeholder,
+        content_hash: version.content_hash,
+        fingerprint: version.fingerprint,
+        animation: version.animation,
+        renditions: version.renditions,
+        ..avatar
+    }
+}
+
+pub async fn get_media(
+    app: web::Data<AppState>,
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<MediaQuery>,
+) -> Result<impl Responder, AppError> {
+    let avatar_id = path.into_inner();
+    let avatar_repository = AvatarRepository::new(app.database.clone());
+    // A custom domain serves a single project and lets it address avatars by external id.
+    let host_project = app.domains.request_project(&request);
+    let external_avatar = match host_project {
+        Some(project_id) => {
+            avatar_repository
+                .get_by_external_id(project_id, &avatar_id)
+                .await?
+        }
+        None => None,
+    };
+    let mut avatar = match external_avatar {
+        Some(avatar) => avatar,
+        None => {
+            let avatar_object_id = ObjectId::from_str(&avatar_id)
+                .map_err(|_| AppError::not_found_error(&avatar_id))?;
+            avatar_repository.get(avatar_object_id).await?
+        }
+    };
+    telemetry::record_project_id(avatar.project);
+    if host_project.map_or(false, |project_id| project_id != avatar.project) {
+        return Err(AppError::not_found_error(avatar_id));
+    }
+    let avatar_object_id = avatar._id;
+    let project = ProjectRepository::new(app.database.clone())
+        .get(avatar.project)
+        .await?;
+
+    let version_object_id = match &query.version {
+        Some(version_id) => Some(
+            ObjectId::from_str(version_id).map_err(|_| AppError::not_found_error(version_id))?,
+        ),
+        None => None,
+    };
+    let now = chrono::Utc::now().timestamp();
+    let review = query.review.unwrap_or(false);
+    let private = review || project.settings.access_policy.visibility == Visibility::Private;
+    if private {
+        let signing_key = ProjectRepository::new(app.database.clone())
+            .get_signing_key(avatar.project)
+            .await?;
+        let verify = match review {
+            true => signing::verify_review,
+            false => signing::verify,
+        };
+        let signed = match (query.expires, &query.signature) {
+            (Some(expires), Some(signature)) => verify(
+                &signing_key,
+                avatar_object_id,
+                version_object_id,
+                expires,
+                signature,
+                now,
+            ),
+            _ => false,
+        };
+        if !signed {
+            return Err(AppError::forbidden_error(
+                "The URL signature is missing, invalid or expired.",
+            ));
+        }
+    }
+    if let Some(version_object_id) = version_object_id {
+        let version = AvatarVersionRepository::new(app.database.clone())
+            .get(avatar_object_id, version_object_id)
+            .await?;
+        avatar = version_avatar(avatar, version);
+    }
+    if !review && avatar.moderation.status != ModerationStatus::Approved {
+        return Err(AppError::not_found_error(avatar_id));
+    }
+
+    let accept = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let static_storage_key = avatar
+        .animation
+        .as_ref()
+        .and_then(|animation| animation.static_storage_key.clone())
+        .filter(|_| query.still.unwrap_or(false));
+    // Stored objects are never overwritten, so their keys can stand in for a missing hash.
+    let (storage_key, mime_type, tag) = match static_storage_key {
+        Some(static_storage_key) => {
+            let tag = match &avatar.content_hash {
+                Some(content_hash) => format!("{}-static", content_hash),
+                None => static_storage_key.clone(),
+            };
+            (static_storage_key, "image/png", tag)
+        }
+        None => match query
+            .format
+            .and_then(|format| {
+                avatar
+                    .renditions
+                    .iter()
+                    .find(|rendition| rendition.format == format)
+            })
+            .or_else(|| renditions::negotiate(accept, &avatar.renditions))
+        {
+            Some(rendition) => (
+                rendition.storage_key.clone(),
+                rendition.format.mime_type(),
+                rendition
+                    .content_hash
+                    .clone()
+                    .unwrap_or_else(|| rendition.storage_key.clone()),
+            ),
+            None => (
+                avatar.key(),
+                renditions::mime_type_for_extension(&avatar.mime_type),
+                avatar.content_hash.clone().unwrap_or_else(|| avatar.key()),
+            ),
+        },
+    };
+
+    let validators = Validators::new(
+        &tag,
+        avatar.updated_at.unwrap_or_else(|| avatar._id.timestamp()),
+    );
+    let versioned = query.v.is_some() && query.v == avatar.content_hash;
+    let cache_control = match (private, query.expires) {
+        (true, Some(expires)) => caching::private_cache_control(
+            &project.settings.delivery_policy,
+            (expires - now).clamp(0, u32::MAX as i64) as u32,
+        ),
+        _ => caching::cache_control(&project.settings.delivery_policy, versioned),
+    };
+    if validators.not_modified(&request) {
+        return Ok(app
+            .domains
+            .apply_cors(
+                &request,
+                Some(avatar.project),
+                validators.apply(&mut HttpResponse::NotModified()),
+            )
+            .insert_header((VARY, "Accept, Origin"))
+            .insert_header((cdn::SURROGATE_KEY, cdn::avatar_surrogate_key(avatar._id)))
+            .insert_header(cache_control)
+            .finish());
+    }
+
+    let body = CloudClient::new(avatar.project.to_string(), project.region)?
+        .get_object(&storage_key)
+        .await?;
+    Ok(app
+        .domains
+        .apply_cors(
+            &request,
+            Some(avatar.project),
+            validators.apply(&mut HttpResponse::Ok()),
+        )
+        .insert_header((CONTENT_TYPE, mime_type))
+        .insert_header((VARY, "Accept, Origin"))
+        .insert_header((cdn::SURROGATE_KEY, cdn::avatar_surrogate_key(avatar._id)))
+        .insert_header(cache_control)
+        .streaming(body))
+}
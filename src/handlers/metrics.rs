@@ -0,0 +1,13 @@
+use actix_web::{web, HttpResponse, Responder};
+
+use crate::{errors::AppError, metrics, AppState};
+
+pub async fn get_metrics(app: web::Data<AppState>) -> Result<impl Responder, AppError> {
+    metrics::render(&app.render_cache.stats())
+        .map_err(|error| AppError::metrics_error(error))
+        .map(|body| {
+            HttpResponse::Ok()
+                .content_type("text/plain; version=0.0.4")
+                .body(body)
+        })
+}
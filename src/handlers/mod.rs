@@ -1,9 +1,17 @@
 mod auth;
 mod avatars;
+mod gravatar;
+mod health;
+mod media;
+mod metrics;
 mod projects;
 mod users;
 
 pub use auth::*;
 pub use avatars::*;
+pub use gravatar::*;
+pub use health::*;
+pub use media::*;
+pub use metrics::*;
 pub use projects::*;
 pub use users::*;
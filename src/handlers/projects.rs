@@ -1,15 +1,26 @@
-use std::str::FromStr;
-
 use actix_web::{web, HttpResponse, Responder};
 use mongodb::bson::oid::ObjectId;
 use serde::Deserialize;
+use validator::Validate;
 
 use crate::{
     cloud::CloudClient,
+    domains,
     errors::AppError,
-    models::Project,
-    repositories::{ProjectRepository, UserRepository},
-    utils::{generate_credentials, Claims},
+    mailer::Mail,
+    models::{
+        AccessPolicy, AnimationPolicy, CropPolicy, DeliveryPolicy, DomainPolicy, ModerationPolicy,
+        ModerationStatus, OutputPolicy, Project, ProjectSettings, UploadPolicy, Visibility,
+    },
+    repositories::{
+        AvatarRepository, AvatarVersionRepository, InvitationRepository, ProjectRepository,
+        UserRepository,
+    },
+    telemetry,
+    utils::{
+        generate_credentials, generate_signing_key, generate_verification_token, parse_object_id,
+        Claims,
+    },
     AppState,
 };
 
@@ -26,8 +37,7 @@ pub async fn get_project(
 ) -> Result<impl Responder, AppError> {
     let user_id = claims.unwrap().id;
     let project_id = path.to_string();
-    let project_object_id =
-        ObjectId::from_str(&project_id).map_err(|error| AppError::db_error(error))?;
+    let project_object_id = parse_object_id(&project_id)?;
 
     UserRepository::new(app.database.clone())
         .in_project(user_id, &project_id)
@@ -68,6 +78,7 @@ pub async fn create_project(
 ) -> Result<impl Responder, AppError> {
     let user_id = claims.expect("No user_id").id;
     let project_id = ObjectId::new();
+    telemetry::record_project_id(project_id);
     let region = project.region.clone();
     let (api_key, api_secret) = generate_credentials().await;
     let project = Project {
@@ -75,20 +86,19 @@ pub async fn create_project(
         author: user_id,
         api_key,
         api_secret,
-        avatars: Vec::new(),
+        signing_key: generate_signing_key(),
         invitations: Vec::new(),
         members: vec![user_id],
         region: region.clone(),
         title: project.title.to_string(),
+        settings: ProjectSettings::default(),
     };
     ProjectRepository::new(app.database.clone())
         .create(project.clone())
-        .await
-        .map_err(|error| AppError::db_error(error))?;
+        .await?;
     UserRepository::new(app.database.clone())
         .add_project(user_id, &project_id.to_string())
-        .await
-        .map_err(|error| AppError::db_error(error))?;
+        .await?;
     CloudClient::create_bucket(project_id.to_string(), region)
         .await
         .map_err(|error| error)
@@ -117,9 +127,11 @@ pub async fn get_available_users(
         .map(|users| HttpResponse::Ok().json(users))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 pub struct InviteUserPayload {
-    username: String,
+    username: Option<String>,
+    #[validate(email)]
+    email: Option<String>,
     project: String,
 }
 
@@ -128,52 +140,126 @@ pub async fn invite_user(
     claims: Option<web::ReqData<Claims>>,
     invitation: web::Json<InviteUserPayload>,
 ) -> Result<impl Responder, AppError> {
+    invitation
+        .validate()
+        .map_err(|error| AppError::validation_error(error))?;
+
     let user_id = claims.expect("No user_id").id;
     let project_id = &invitation.project;
-    let project_object_id =
-        ObjectId::from_str(&project_id).map_err(|error| AppError::db_error(error))?;
+    telemetry::record_project_id(project_id);
+    let project_object_id = parse_object_id(&project_id)?;
 
-    UserRepository::new(app.database.clone())
-        .in_project(user_id, &project_id)
-        .await?;
-    UserRepository::new(app.database.clone())
-        .add_invitation(user_id, project_id)
+    let user_repository = UserRepository::new(app.database.clone());
+    user_repository.in_project(user_id, &project_id).await?;
+
+    let invited_user = match (&invitation.username, &invitation.email) {
+        (Some(username), _) => user_repository.get_by_username(username).await?,
+        (None, Some(email)) => {
+            let email = email.trim().to_lowercase();
+            match user_repository.find_by_email(&email).await? {
+                Some(user) => user,
+                None => {
+                    return invite_email(&app, user_id, project_object_id, &email)
+                        .await
+                        .map(|_| HttpResponse::Ok());
+                }
+            }
+        }
+        (None, None) => {
+            return Err(AppError::unvalid_form_error(
+                "A username or an email is required.",
+            ))
+        }
+    };
+
+    user_repository
+        .add_invitation(invited_user.id, project_id)
         .await?;
     ProjectRepository::new(app.database.clone())
-        .add_invitation(project_object_id, invitation.username.to_string().as_str())
+        .add_invitation(project_object_id, invited_user.id.to_string().as_str())
         .await
         .map(|_| HttpResponse::Ok())
-    // TODO: Add invitation to user
+}
+
+async fn invite_email(
+    app: &web::Data<AppState>,
+    author_id: ObjectId,
+    project_id: ObjectId,
+    email: &str,
+) -> Result<(), AppError> {
+    let author = UserRepository::new(app.database.clone())
+        .get(author_id)
+        .await?;
+    let project = ProjectRepository::new(app.database.clone())
+        .get(project_id)
+        .await?;
+
+    // The token proves the invitee reads this mailbox, the address alone is not enough to join.
+    // The invitation is only stored once the mail went out, a failed send leaves nothing behind.
+    let token = generate_verification_token();
+    app.mailer
+        .send(Mail {
+            to: email.to_string(),
+            subject: format!(
+                "{} invited you to join {} on stampa",
+                author.username, project.title
+            ),
+            body: format!(
+                "{} invited you to join the project {} on stampa.\n\n\
+                Enter this invitation code when you create your account or accept the invitation:\n\n\
+                Invitation code: {}",
+                author.username, project.title, token
+            ),
+        })
+        .await?;
+
+    InvitationRepository::new(app.database.clone())
+        .upsert(email, &project_id.to_string(), author_id, &token)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct AcceptInvitationPayload {
+    token: Option<String>,
 }
 
 pub async fn accept_invitation(
     app: web::Data<AppState>,
     claims: Option<web::ReqData<Claims>>,
     path: web::Path<String>,
+    payload: Option<web::Json<AcceptInvitationPayload>>,
 ) -> Result<impl Responder, AppError> {
     let user_id = &claims.expect("No user_id").id;
     let project_id = path.to_string();
-    let project_object_id =
-        ObjectId::from_str(&project_id).map_err(|error| AppError::db_error(error))?;
+    let project_object_id = parse_object_id(&project_id)?;
 
     let user_repository = UserRepository::new(app.database.clone());
     let project_repository = ProjectRepository::new(app.database.clone());
 
+    // Removing the invitation first answers 404 before anything changes when there is none.
+    match payload.and_then(|payload| payload.into_inner().token) {
+        Some(token) => {
+            InvitationRepository::new(app.database.clone())
+                .take(&project_id, &token)
+                .await?;
+        }
+        None => {
+            user_repository
+                .remove_invitation(*user_id, &project_id)
+                .await?;
+            project_repository
+                .remove_invitation(project_object_id, user_id.to_string().as_str())
+                .await?;
+        }
+    }
     user_repository.add_project(*user_id, &project_id).await?;
-    user_repository
-        .remove_invitation(*user_id, &project_id)
-        .await?;
     project_repository
         .add_user(project_object_id, *user_id)
         .await?;
-    project_repository
-        .remove_invitation(project_object_id, user_id.to_string().as_str())
-        .await?;
 
     ProjectRepository::new(app.database.clone())
         .get(project_object_id)
         .await
-        .map_err(|error| AppError::db_error(error))
         .map(|project| HttpResponse::Ok().json(project))
 }
 
@@ -184,8 +270,7 @@ pub async fn deny_invitation(
 ) -> Result<impl Responder, AppError> {
     let user_id = &claims.expect("No user_id").id;
     let project_id = path.to_string();
-    let project_object_id =
-        ObjectId::from_str(&project_id).map_err(|error| AppError::db_error(error))?;
+    let project_object_id = parse_object_id(&project_id)?;
 
     UserRepository::new(app.database.clone())
         .remove_invitation(*user_id, &project_id)
@@ -203,8 +288,7 @@ pub async fn get_project_credentials(
 ) -> Result<impl Responder, AppError> {
     let user_id = &claims.expect("No user_id").id;
     let project_id = path.to_string();
-    let project_object_id =
-        ObjectId::from_str(&project_id).map_err(|error| AppError::db_error(error))?;
+    let project_object_id = parse_object_id(&project_id)?;
     UserRepository::new(app.database.clone())
         .in_project(*user_id, &project_id)
         .await?;
@@ -213,3 +297,283 @@ pub async fn get_project_credentials(
         .await
         .map(|secret| HttpResponse::Ok().json(secret))
 }
+
+#[derive(Deserialize, Validate)]
+pub struct ProjectSettingsPayload {
+    #[validate(range(max = 100))]
+    version_retention: Option<u32>,
+    #[validate]
+    upload_policy: Option<UploadPolicy>,
+    #[validate]
+    crop_policy: Option<CropPolicy>,
+    #[validate]
+    moderation_policy: Option<ModerationPolicy>,
+    #[validate]
+    animation_policy: Option<AnimationPolicy>,
+    #[validate]
+    output_policy: Option<OutputPolicy>,
+    #[validate]
+    delivery_policy: Option<DeliveryPolicy>,
+    #[validate]
+    access_policy: Option<AccessPolicy>,
+    #[validate]
+    domain_policy: Option<DomainPolicy>,
+}
+
+async fn apply_visibility(
+    app: &web::Data<AppState>,
+    project_id: ObjectId,
+    region: String,
+    visibility: Visibility,
+) -> Result<(), AppError> {
+    let cloud_client = CloudClient::new(project_id.to_string(), region)?;
+    let avatars = AvatarRepository::new(app.database.clone())
+        .get_all_project_avatars(project_id)
+        .await?;
+    let version_repository = AvatarVersionRepository::new(app.database.clone());
+
+    // Every key is listed before the first ACL changes so a database error can not stop
+    // the switch halfway.
+    let mut objects = Vec::new();
+    for avatar in avatars {
+        for key in std::iter::once(avatar.key()).chain(avatar.derived_keys()) {
+            objects.push((avatar.moderation.status, key));
+        }
+        for version in version_repository.get_avatar_versions(avatar._id).await? {
+            for key in std::iter::once(version.storage_key.clone()).chain(version.derived_keys()) {
+                objects.push((version.moderation.status, key));
+            }
+        }
+    }
+
+    // Only approved objects are public under a public project, so they are the only ones whose
+    // ACL actually changes and has to be switched back if another object fails.
+    let mut switched_keys = Vec::new();
+    for (status, key) in objects {
+        let switched = match (visibility, status) {
+            (Visibility::Public, ModerationStatus::Approved) => {
+                cloud_client.publish_object(&key).await
+            }
+            (Visibility::Public, _) => continue,
+            (Visibility::Private, _) => cloud_client.unpublish_object(&key).await,
+        };
+        if let Err(error) = switched {
+            for switched_key in switched_keys {
+                let reverted = match visibility {
+                    Visibility::Public => cloud_client.unpublish_object(&switched_key).await,
+                    Visibility::Private => cloud_client.publish_object(&switched_key).await,
+                };
+                if let Err(revert_error) = reverted {
+                    log::error!(
+                        "Could not restore the ACL of {} in project {}: {:?}",
+                        switched_key,
+                        project_id,
+                        revert_error
+                    );
+                }
+            }
+            return Err(error);
+        }
+        if status == ModerationStatus::Approved {
+            switched_keys.push(key);
+        }
+    }
+    log::info!("Project {} objects are now {:?}", project_id, visibility);
+    Ok(())
+}
+
+pub async fn update_project_settings(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+    payload: web::Json<ProjectSettingsPayload>,
+) -> Result<impl Responder, AppError> {
+    payload
+        .validate()
+        .map_err(|error| AppError::validation_error(error))?;
+
+    let user_id = &claims.expect("No user_id").id;
+    let project_id = path.to_string();
+    let project_object_id = parse_object_id(&project_id)?;
+    UserRepository::new(app.database.clone())
+        .in_project(*user_id, &project_id)
+        .await?;
+
+    let repository = ProjectRepository::new(app.database.clone());
+    let project = repository.get(project_object_id).await?;
+    let mut settings = project.settings;
+    if let Some(version_retention) = payload.version_retention {
+        settings.version_retention = version_retention;
+    }
+    if let Some(upload_policy) = &payload.upload_policy {
+        settings.upload_policy = upload_policy.clone();
+    }
+    if let Some(crop_policy) = &payload.crop_policy {
+        settings.crop_policy = crop_policy.clone();
+    }
+    if let Some(moderation_policy) = &payload.moderation_policy {
+        settings.moderation_policy = moderation_policy.clone();
+    }
+    if let Some(animation_policy) = &payload.animation_policy {
+        settings.animation_policy = animation_policy.clone();
+    }
+    if let Some(output_policy) = &payload.output_policy {
+        settings.output_policy = output_policy.clone();
+    }
+    if let Some(delivery_policy) = &payload.delivery_policy {
+        if let Some(url_template) = &delivery_policy.url_template {
+            if !(url_template.starts_with("https://") || url_template.starts_with("http://"))
+                || !url_template.contains("{key}")
+            {
+                return Err(AppError::unvalid_form_error(
+                    "The URL template must be an http(s) URL containing {key}.",
+                ));
+            }
+        }
+        settings.delivery_policy = delivery_policy.clone();
+    }
+    let previous_visibility = settings.access_policy.visibility;
+    if let Some(access_policy) = &payload.access_policy {
+        settings.access_policy = access_policy.clone();
+    }
+    if let Some(domain_policy) = &payload.domain_policy {
+        if !domain_policy
+            .allowed_origins
+            .iter()
+            .all(|origin| domains::valid_origin(origin))
+        {
+            return Err(AppError::unvalid_form_error(
+                "Allowed origins must be http(s) scheme and host pairs without a path.",
+            ));
+        }
+        let custom_domain = domain_policy
+            .custom_domain
+            .as_deref()
+            .map(domains::normalize_host);
+        if let Some(custom_domain) = &custom_domain {
+            if !domains::valid_host(custom_domain) {
+                return Err(AppError::unvalid_form_error(
+                    "The custom domain must be a fully qualified hostname.",
+                ));
+            }
+            if domains::url_host(&app.public_url).as_ref() == Some(custom_domain) {
+                return Err(AppError::unvalid_form_error(
+                    "The custom domain can not be the stampa domain itself.",
+                ));
+            }
+            if repository
+                .custom_domain_taken(project_object_id, custom_domain)
+                .await?
+            {
+                return Err(AppError::unvalid_form_error(
+                    "The custom domain is already used by another project.",
+                ));
+            }
+        }
+        let mut allowed_origins: Vec<String> = domain_policy
+            .allowed_origins
+            .iter()
+            .map(|origin| domains::normalize_origin(origin))
+            .collect();
+        allowed_origins.sort();
+        allowed_origins.dedup();
+        // A new custom domain gets a fresh token and stays unrouted until it is verified.
+        let (custom_domain_token, custom_domain_verified) =
+            match custom_domain == settings.domain_policy.custom_domain {
+                true => (
+                    settings.domain_policy.custom_domain_token.clone(),
+                    settings.domain_policy.custom_domain_verified,
+                ),
+                false => (
+                    custom_domain
+                        .as_ref()
+                        .map(|_| generate_verification_token()),
+                    false,
+                ),
+            };
+        settings.domain_policy = DomainPolicy {
+            allowed_origins,
+            custom_domain,
+            custom_domain_token,
+            custom_domain_verified,
+        };
+    }
+
+    // Objects are switched once every other setting is validated, and switched back if the
+    // settings can not be saved, so stored ACLs always match the saved visibility.
+    let visibility = settings.access_policy.visibility;
+    if visibility != previous_visibility {
+        apply_visibility(&app, project_object_id, project.region.clone(), visibility).await?;
+    }
+    if let Err(error) = repository
+        .update_settings(project_object_id, &settings)
+        .await
+    {
+        if visibility != previous_visibility {
+            if let Err(revert_error) =
+                apply_visibility(&app, project_object_id, project.region, previous_visibility).await
+            {
+                log::error!(
+                    "Could not restore the visibility of project {}: {:?}",
+                    project_object_id,
+                    revert_error
+                );
+            }
+        }
+        return Err(error);
+    }
+    app.domains
+        .update(project_object_id, &settings.domain_policy);
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+pub async fn verify_custom_domain(
+    app: web::Data<AppState>,
+    claims: Option<web::ReqData<Claims>>,
+    path: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    let user_id = &claims.expect("No user_id").id;
+    let project_id = path.to_string();
+    let project_object_id = parse_object_id(&project_id)?;
+    UserRepository::new(app.database.clone())
+        .in_project(*user_id, &project_id)
+        .await?;
+
+    let repository = ProjectRepository::new(app.database.clone());
+    let mut settings = repository.get(project_object_id).await?.settings;
+    let domain_policy = &mut settings.domain_policy;
+    let (custom_domain, token) = match (
+        &domain_policy.custom_domain,
+        &domain_policy.custom_domain_token,
+    ) {
+        (Some(custom_domain), Some(token)) => (custom_domain.clone(), token.clone()),
+        _ => {
+            return Err(AppError::unvalid_form_error(
+                "The project has no custom domain to verify.",
+            ))
+        }
+    };
+    if domain_policy.custom_domain_verified {
+        return Ok(HttpResponse::Ok().json(settings));
+    }
+
+    if !domains::verify_domain(app.domain_verifier.as_ref(), &custom_domain, &token).await {
+        return Err(AppError::unvalid_form_error(format!(
+            "Add a TXT record {} with the value {} and try again.",
+            domains::challenge_name(&custom_domain),
+            domains::challenge_value(&token)
+        )));
+    }
+    domain_policy.custom_domain_verified = true;
+    repository
+        .update_settings(project_object_id, &settings)
+        .await?;
+    app.domains
+        .update(project_object_id, &settings.domain_policy);
+    log::info!(
+        "Custom domain {} of project {} is verified",
+        custom_domain,
+        project_object_id
+    );
+    Ok(HttpResponse::Ok().json(settings))
+}
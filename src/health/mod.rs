@@ -0,0 +1,78 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::errors::AppError;
+
+pub struct Readiness {
+    shutting_down: AtomicBool,
+    migrated: AtomicBool,
+    pub check_timeout: Duration,
+}
+
+impl Readiness {
+    pub fn new(check_timeout: Duration) -> Readiness {
+        Readiness {
+            shutting_down: AtomicBool::new(false),
+            migrated: AtomicBool::new(false),
+            check_timeout,
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn start_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_migrated(&self) -> bool {
+        self.migrated.load(Ordering::SeqCst)
+    }
+
+    pub fn mark_migrated(&self) {
+        self.migrated.store(true, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyState {
+    Up,
+    Down,
+    Timeout,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyStatus {
+    pub status: DependencyState,
+    pub latency_ms: u64,
+}
+
+// Failure causes are logged only, the report is served without authentication.
+pub async fn check<T, F>(name: &str, timeout: Duration, future: F) -> DependencyStatus
+where
+    F: Future<Output = Result<T, AppError>>,
+{
+    let started_at = Instant::now();
+    let status = match tokio::time::timeout(timeout, future).await {
+        Ok(Ok(_)) => DependencyState::Up,
+        Ok(Err(error)) => {
+            tracing::warn!(dependency = name, cause = ?error.cause, "readiness check failed");
+            DependencyState::Down
+        }
+        Err(_) => {
+            tracing::warn!(dependency = name, "readiness check timed out");
+            DependencyState::Timeout
+        }
+    };
+    DependencyStatus {
+        status,
+        latency_ms: started_at.elapsed().as_millis() as u64,
+    }
+}
@@ -1,15 +1,42 @@
 pub mod avatars;
+pub mod caching;
+pub mod cdn;
 pub mod cloud;
 pub mod config;
+pub mod domains;
 pub mod errors;
+pub mod faces;
+pub mod fingerprints;
+pub mod gravatar;
 pub mod handlers;
+pub mod health;
+pub mod mailer;
+pub mod metrics;
 pub mod middlewares;
+pub mod migrations;
 pub mod models;
+pub mod moderation;
+pub mod placeholders;
+pub mod renditions;
 pub mod repositories;
 pub mod routers;
+pub mod signing;
 pub mod startup;
+pub mod telemetry;
 pub mod tests;
+pub mod uploads;
 pub mod utils;
 pub struct AppState {
     pub database: mongodb::Database,
+    pub mailer: Box<dyn mailer::Mailer>,
+    pub face_detector: Box<dyn faces::FaceDetector>,
+    pub moderator: Box<dyn moderation::Moderator>,
+    pub gravatar_project: Option<mongodb::bson::oid::ObjectId>,
+    pub public_url: String,
+    pub render_cache: caching::render_cache::RenderCache,
+    pub purge_hook: std::sync::Arc<dyn cdn::PurgeHook>,
+    pub url_template: Option<String>,
+    pub domains: domains::DomainRegistry,
+    pub domain_verifier: Box<dyn domains::DomainVerifier>,
+    pub readiness: health::Readiness,
 }
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+
+use super::{Mail, Mailer};
+use crate::errors::AppError;
+
+pub struct FileMailer {
+    directory: String,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(directory: &str, from: &str) -> FileMailer {
+        FileMailer {
+            directory: directory.to_string(),
+            from: from.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), AppError> {
+        log::info!("Mail to {}: {}", mail.to, mail.subject);
+
+        let path = format!("{}/{}.eml", self.directory, ObjectId::new());
+        let contents = format!(
+            "Date: {}\r\nFrom: {}\r\nTo: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+            Utc::now().to_rfc2822(),
+            self.from,
+            mail.to,
+            mail.subject,
+            mail.body
+        );
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|error| AppError::fs_error(error))?;
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|error| AppError::fs_error(error))
+    }
+}
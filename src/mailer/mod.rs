@@ -0,0 +1,46 @@
+mod file;
+mod smtp;
+
+pub use file::*;
+pub use smtp::*;
+
+use async_trait::async_trait;
+
+use crate::{config::Config, errors::AppError};
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), AppError>;
+}
+
+pub fn from_config(config: &Config) -> Result<Box<dyn Mailer>, AppError> {
+    match config.mail_transport.as_str() {
+        "smtp" => {
+            let host = config.smtp_host.as_ref().ok_or(AppError::mail_error(
+                "SMTP_HOST is required by the smtp transport.",
+            ))?;
+            Ok(Box::new(SmtpMailer::new(
+                host,
+                config.smtp_port,
+                config.smtp_username.clone(),
+                config.smtp_password.clone(),
+                &config.mail_from,
+            )?))
+        }
+        "file" => Ok(Box::new(FileMailer::new(
+            &config.mail_directory,
+            &config.mail_from,
+        ))),
+        transport => Err(AppError::mail_error(format!(
+            "Unknown mail transport {}.",
+            transport
+        ))),
+    }
+}
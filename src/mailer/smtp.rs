@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use super::{Mail, Mailer};
+use crate::errors::AppError;
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        username: Option<String>,
+        password: Option<String>,
+        from: &str,
+    ) -> Result<SmtpMailer, AppError> {
+        let from = from
+            .parse::<Mailbox>()
+            .map_err(|error| AppError::mail_error(error))?;
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+            .map_err(|error| AppError::mail_error(error))?;
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (username, password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(SmtpMailer {
+            from,
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> Result<(), AppError> {
+        let to = mail
+            .to
+            .parse::<Mailbox>()
+            .map_err(|error| AppError::mail_error(error))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(mail.subject)
+            .body(mail.body)
+            .map_err(|error| AppError::mail_error(error))?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|error| AppError::mail_error(error))
+            .map(|_| ())
+    }
+}
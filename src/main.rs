@@ -1,22 +1,66 @@
 use actix_web::web;
-use env_logger::Env;
 use stampa::startup::run;
 use std::net::TcpListener;
+use std::time::Duration;
 
-use stampa::AppState;
+use stampa::{
+    caching::render_cache::RenderCache,
+    domains::{parse_origins, DnsDomainVerifier, DomainRegistry},
+    health::Readiness,
+    AppState,
+};
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     let app_config = stampa::config::Config::from_env().unwrap();
+    stampa::telemetry::init(&app_config);
 
-    let database = app_config.connect_mongo().await.unwrap();
+    let database = match app_config.connect_mongo().await {
+        Ok(database) => database,
+        Err(error) => {
+            tracing::error!("Invalid MongoDB configuration: {}", error);
+            std::process::exit(1);
+        }
+    };
 
-    let app_state = web::Data::new(AppState { database });
+    let mailer = stampa::mailer::from_config(&app_config).unwrap();
+    let face_detector = stampa::faces::from_config(&app_config).await.unwrap();
+    let moderator = stampa::moderation::from_config(&app_config).await.unwrap();
+    let gravatar_project = app_config.gravatar_project_id().unwrap();
+    let purge_hook = stampa::cdn::from_config(&app_config).unwrap();
+    let domains = DomainRegistry::new(parse_origins(&app_config.cors_allowed_origins));
+
+    let app_state = web::Data::new(AppState {
+        database,
+        mailer,
+        face_detector,
+        moderator,
+        gravatar_project,
+        public_url: app_config.public_url.clone(),
+        render_cache: RenderCache::new(app_config.render_cache_bytes),
+        purge_hook,
+        url_template: app_config.url_template.clone(),
+        domains,
+        domain_verifier: Box::new(DnsDomainVerifier::new().unwrap()),
+        readiness: Readiness::new(Duration::from_millis(app_config.readiness_timeout_ms)),
+    });
 
     let address = format!("{}:{}", app_config.host, app_config.port);
     let listener = TcpListener::bind(address.to_string())?;
 
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-
-    run(listener, app_state)?.await
+    let server = run(listener, app_state.clone())?;
+    tokio::spawn(stampa::startup::migrate_when_available(
+        app_state.clone(),
+        Duration::from_secs(app_config.migration_retry_seconds),
+    ));
+    tokio::spawn(stampa::domains::refresh_periodically(
+        app_state.clone(),
+        Duration::from_secs(app_config.domain_refresh_seconds),
+    ));
+    tokio::spawn(stampa::startup::shutdown_on_signal(
+        server.handle(),
+        app_state,
+        Duration::from_secs(app_config.shutdown_grace_seconds),
+    ));
+    server.await
 }
@@ -0,0 +1,220 @@
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use prometheus::{
+    exponential_buckets, proto::MetricFamily, register_histogram_vec, register_int_counter_vec,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge, Registry, TextEncoder,
+};
+use tracing::{span, Event, Level, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::caching::render_cache::RenderCacheStats;
+
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "stampa_http_requests_total",
+        "HTTP requests by route and status.",
+        &["method", "route", "status"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "stampa_http_request_duration_seconds",
+        "HTTP request latency by route.",
+        &["method", "route"]
+    )
+    .unwrap();
+    pub static ref UPLOAD_SIZE: HistogramVec = register_histogram_vec!(
+        "stampa_upload_size_bytes",
+        "Size of accepted image uploads.",
+        &["format"],
+        exponential_buckets(1024.0, 4.0, 9).unwrap()
+    )
+    .unwrap();
+    pub static ref RENDER_DURATION: HistogramVec = register_histogram_vec!(
+        "stampa_render_duration_seconds",
+        "Time spent encoding and resizing avatars.",
+        &["kind"]
+    )
+    .unwrap();
+    pub static ref STORAGE_OPERATION_DURATION: HistogramVec = register_histogram_vec!(
+        "stampa_storage_operation_duration_seconds",
+        "Object storage operation latency.",
+        &["backend", "operation"]
+    )
+    .unwrap();
+    pub static ref STORAGE_OPERATION_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "stampa_storage_operation_errors_total",
+        "Failed object storage operations.",
+        &["backend", "operation"]
+    )
+    .unwrap();
+    pub static ref DATABASE_OPERATION_DURATION: HistogramVec = register_histogram_vec!(
+        "stampa_mongodb_operation_duration_seconds",
+        "MongoDB operation latency.",
+        &["collection", "operation"]
+    )
+    .unwrap();
+}
+
+pub fn observe_request(method: &str, route: &str, status: u16, elapsed: Duration) {
+    HTTP_REQUESTS
+        .with_label_values(&[method, route, &status.to_string()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[method, route])
+        .observe(elapsed.as_secs_f64());
+}
+
+// Cache statistics live in `RenderCache`, they are copied into a throwaway registry per scrape.
+fn render_cache_families(stats: &RenderCacheStats) -> Vec<MetricFamily> {
+    let registry = Registry::new();
+    let counters = [
+        (
+            "stampa_render_cache_hits_total",
+            "Render cache hits.",
+            stats.hits,
+        ),
+        (
+            "stampa_render_cache_misses_total",
+            "Render cache misses.",
+            stats.misses,
+        ),
+        (
+            "stampa_render_cache_coalesced_total",
+            "Renders shared with an in-flight request.",
+            stats.coalesced,
+        ),
+        (
+            "stampa_render_cache_evictions_total",
+            "Render cache evictions.",
+            stats.evictions,
+        ),
+    ];
+    for (name, help, value) in counters {
+        let counter = IntCounter::new(name, help).unwrap();
+        counter.inc_by(value);
+        registry.register(Box::new(counter)).unwrap();
+    }
+    let gauges = [
+        (
+            "stampa_render_cache_entries",
+            "Cached renders.",
+            stats.entries,
+        ),
+        (
+            "stampa_render_cache_bytes",
+            "Bytes held by the render cache.",
+            stats.bytes,
+        ),
+        (
+            "stampa_render_cache_budget_bytes",
+            "Render cache byte budget.",
+            stats.budget_bytes,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        let gauge = IntGauge::new(name, help).unwrap();
+        gauge.set(value as i64);
+        registry.register(Box::new(gauge)).unwrap();
+    }
+    registry.gather()
+}
+
+pub fn render(render_cache_stats: &RenderCacheStats) -> Result<String, prometheus::Error> {
+    let mut families = prometheus::gather();
+    families.extend(render_cache_families(render_cache_stats));
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&families, &mut buffer)?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+// Database and storage calls are already wrapped in `mongodb.*` and `s3.*` spans,
+// timing those spans avoids instrumenting every repository method a second time.
+enum Operation {
+    Database(String, String),
+    Storage(&'static str, String),
+}
+
+impl Operation {
+    fn parse(span_name: &str) -> Option<Operation> {
+        let mut parts = span_name.split('.');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("mongodb"), Some(collection), Some(operation)) => Some(Operation::Database(
+                collection.to_string(),
+                operation.to_string(),
+            )),
+            (Some("s3"), Some(operation), None) => {
+                Some(Operation::Storage("s3", operation.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Timing {
+    operation: Operation,
+    started_at: Instant,
+}
+
+pub struct MetricsLayer;
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attributes: &span::Attributes<'_>,
+        id: &span::Id,
+        context: Context<'_, S>,
+    ) {
+        if let (Some(operation), Some(span)) = (
+            Operation::parse(attributes.metadata().name()),
+            context.span(id),
+        ) {
+            span.extensions_mut().insert(Timing {
+                operation,
+                started_at: Instant::now(),
+            });
+        }
+    }
+
+    // `#[tracing::instrument(err)]` reports failed storage calls as error events.
+    fn on_event(&self, event: &Event<'_>, context: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        if let Some(span) = context.event_span(event) {
+            if let Some(Timing {
+                operation: Operation::Storage(backend, operation),
+                ..
+            }) = span.extensions().get::<Timing>()
+            {
+                STORAGE_OPERATION_ERRORS
+                    .with_label_values(&[backend, operation])
+                    .inc();
+            }
+        }
+    }
+
+    fn on_close(&self, id: span::Id, context: Context<'_, S>) {
+        let span = match context.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let extensions = span.extensions();
+        let timing = match extensions.get::<Timing>() {
+            Some(timing) => timing,
+            None => return,
+        };
+        let elapsed = timing.started_at.elapsed().as_secs_f64();
+        match &timing.operation {
+            Operation::Database(collection, operation) => DATABASE_OPERATION_DURATION
+                .with_label_values(&[collection, operation])
+                .observe(elapsed),
+            Operation::Storage(backend, operation) => STORAGE_OPERATION_DURATION
+                .with_label_values(&[backend, operation])
+                .observe(elapsed),
+        }
+    }
+}
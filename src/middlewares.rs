@@ -24,6 +24,7 @@ pub async fn validator(
         &Validation::new(Algorithm::HS256),
     ) {
         Ok(_token) => {
+            tracing::Span::current().record("user_id", &tracing::field::display(&_token.claims.id));
             req.extensions_mut().insert(_token.claims);
             Ok(req)
         }
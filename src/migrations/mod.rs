@@ -0,0 +1,147 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Bson, Document},
+    options::ReplaceOptions,
+    Database,
+};
+
+use crate::{
+    errors::AppError,
+    gravatar,
+    repositories::{
+        AvatarRepository, AvatarVersionRepository, InvitationRepository, UserRepository,
+    },
+    utils::generate_signing_key,
+};
+
+pub async fn run(database: &Database) -> Result<(), AppError> {
+    AvatarRepository::new(database.clone())
+        .create_indexes()
+        .await?;
+    AvatarVersionRepository::new(database.clone())
+        .create_indexes()
+        .await?;
+    UserRepository::new(database.clone())
+        .create_indexes()
+        .await?;
+    InvitationRepository::new(database.clone())
+        .create_indexes()
+        .await?;
+    move_embedded_avatars(database).await?;
+    hash_email_external_ids(database).await?;
+    generate_signing_keys(database).await
+}
+
+async fn move_embedded_avatars(database: &Database) -> Result<(), AppError> {
+    let projects = database.collection::<Document>("projects");
+    let avatars = database.collection::<Document>("avatars");
+    let mut cursor = projects
+        .find(doc! {"avatars": {"$exists": true}}, None)
+        .await
+        .map_err(|error| AppError::db_error(error))?;
+
+    while let Some(project) = cursor
+        .try_next()
+        .await
+        .map_err(|error| AppError::db_error(error))?
+    {
+        let project_id = project
+            .get_object_id("_id")
+            .map_err(|error| AppError::db_error(error))?;
+        let embedded_avatars = project.get_array("avatars").cloned().unwrap_or_default();
+
+        for embedded_avatar in embedded_avatars {
+            let mut avatar = match embedded_avatar {
+                Bson::Document(avatar) => avatar,
+                _ => continue,
+            };
+            let avatar_id = avatar
+                .get_object_id("_id")
+                .map_err(|error| AppError::db_error(error))?;
+            avatar.insert("project", project_id);
+            avatars
+                .replace_one(
+                    doc! {"_id": avatar_id},
+                    avatar,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await
+                .map_err(|error| AppError::db_error(error))?;
+        }
+
+        projects
+            .update_one(
+                doc! {"_id": project_id},
+                doc! {"$unset": {"avatars": ""}},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))?;
+        log::info!("Moved embedded avatars of project {}", project_id);
+    }
+    Ok(())
+}
+
+async fn hash_email_external_ids(database: &Database) -> Result<(), AppError> {
+    let avatars = database.collection::<Document>("avatars");
+    let mut cursor = avatars
+        .find(
+            doc! {"external_id": {"$type": "string"}, "email_hash": {"$exists": false}},
+            None,
+        )
+        .await
+        .map_err(|error| AppError::db_error(error))?;
+
+    while let Some(avatar) = cursor
+        .try_next()
+        .await
+        .map_err(|error| AppError::db_error(error))?
+    {
+        let avatar_id = avatar
+            .get_object_id("_id")
+            .map_err(|error| AppError::db_error(error))?;
+        let email_hash = avatar
+            .get_str("external_id")
+            .ok()
+            .and_then(gravatar::email_hash);
+        let email_hash =
+            mongodb::bson::to_bson(&email_hash).map_err(|error| AppError::db_error(error))?;
+        avatars
+            .update_one(
+                doc! {"_id": avatar_id},
+                doc! {"$set": {"email_hash": email_hash}},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))?;
+    }
+    Ok(())
+}
+
+async fn generate_signing_keys(database: &Database) -> Result<(), AppError> {
+    let projects = database.collection::<Document>("projects");
+    let mut cursor = projects
+        .find(doc! {"signing_key": {"$in": [null, ""]}}, None)
+        .await
+        .map_err(|error| AppError::db_error(error))?;
+
+    while let Some(project) = cursor
+        .try_next()
+        .await
+        .map_err(|error| AppError::db_error(error))?
+    {
+        let project_id = project
+            .get_object_id("_id")
+            .map_err(|error| AppError::db_error(error))?;
+        projects
+            .update_one(
+                doc! {"_id": project_id},
+                doc! {"$set": {"signing_key": generate_signing_key()}},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))?;
+        log::info!("Generated a signing key for project {}", project_id);
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
-use mongodb::bson::{self, oid::ObjectId};
+use mongodb::bson::{self, oid::ObjectId, DateTime};
 use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
 
 pub trait Print {
     fn print_informations(&self);
@@ -8,9 +9,221 @@ pub trait Print {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Avatar {
     pub _id: bson::oid::ObjectId,
+    pub project: ObjectId,
+    #[serde(default)]
+    pub external_id: Option<String>,
     pub name: String,
     pub mime_type: String,
     pub url: String,
+    #[serde(default)]
+    pub storage_key: Option<String>,
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    #[serde(default)]
+    pub uploader: Option<ObjectId>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime>,
+    #[serde(default)]
+    pub metadata: Option<MetadataSummary>,
+    #[serde(default)]
+    pub moderation: Moderation,
+    #[serde(default)]
+    pub placeholder: Option<Placeholder>,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    pub fingerprint: Option<Fingerprint>,
+    #[serde(default)]
+    pub animation: Option<AnimationSummary>,
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
+    #[serde(default)]
+    pub email_hash: Option<EmailHash>,
+}
+
+impl Avatar {
+    pub fn key(&self) -> String {
+        self.storage_key
+            .clone()
+            .unwrap_or_else(|| format!("{}.{}", self._id, self.mime_type))
+    }
+
+    pub fn derived_keys(&self) -> Vec<String> {
+        derived_keys(&self.animation, &self.renditions)
+    }
+
+    pub fn object_urls(&self) -> Vec<String> {
+        object_urls(&self.url, &self.animation, &self.renditions)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AvatarVersion {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub avatar: ObjectId,
+    pub project: ObjectId,
+    pub storage_key: String,
+    pub mime_type: String,
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    pub uploader: Option<ObjectId>,
+    pub created_at: DateTime,
+    #[serde(default)]
+    pub metadata: Option<MetadataSummary>,
+    #[serde(default)]
+    pub moderation: Moderation,
+    #[serde(default)]
+    pub placeholder: Option<Placeholder>,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    pub fingerprint: Option<Fingerprint>,
+    #[serde(default)]
+    pub animation: Option<AnimationSummary>,
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
+}
+
+impl AvatarVersion {
+    pub fn from_avatar(avatar: &Avatar) -> AvatarVersion {
+        AvatarVersion {
+            id: ObjectId::new(),
+            avatar: avatar._id,
+            project: avatar.project,
+            storage_key: avatar.key(),
+            mime_type: avatar.mime_type.clone(),
+            url: avatar.url.clone(),
+            width: avatar.width,
+            height: avatar.height,
+            uploader: avatar.uploader,
+            created_at: avatar.updated_at.unwrap_or_else(|| avatar._id.timestamp()),
+            metadata: avatar.metadata.clone(),
+            moderation: avatar.moderation.clone(),
+            placeholder: avatar.placeholder.clone(),
+            content_hash: avatar.content_hash.clone(),
+            fingerprint: avatar.fingerprint.clone(),
+            animation: avatar.animation.clone(),
+            renditions: avatar.renditions.clone(),
+        }
+    }
+
+    pub fn derived_keys(&self) -> Vec<String> {
+        derived_keys(&self.animation, &self.renditions)
+    }
+
+    pub fn object_urls(&self) -> Vec<String> {
+        object_urls(&self.url, &self.animation, &self.renditions)
+    }
+}
+
+fn object_urls(
+    url: &str,
+    animation: &Option<AnimationSummary>,
+    renditions: &[Rendition],
+) -> Vec<String> {
+    std::iter::once(url.to_string())
+        .chain(
+            animation
+                .iter()
+                .filter_map(|animation| animation.static_url.clone()),
+        )
+        .chain(renditions.iter().map(|rendition| rendition.url.clone()))
+        .collect()
+}
+
+fn derived_keys(animation: &Option<AnimationSummary>, renditions: &[Rendition]) -> Vec<String> {
+    animation
+        .iter()
+        .filter_map(|animation| animation.static_storage_key.clone())
+        .chain(
+            renditions
+                .iter()
+                .map(|rendition| rendition.storage_key.clone()),
+        )
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailHash {
+    pub md5: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Rendition {
+    pub format: OutputFormat,
+    pub mime_type: String,
+    pub storage_key: String,
+    pub url: String,
+    pub size: u64,
+    #[serde(default)]
+    pub content_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnimationSummary {
+    pub frame_count: u32,
+    pub duration_ms: u32,
+    pub static_url: Option<String>,
+    pub static_storage_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Fingerprint {
+    pub ahash: String,
+    pub dhash: String,
+    pub phash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Placeholder {
+    pub blurhash: String,
+    pub thumbhash: String,
+    pub dominant_color: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Moderation {
+    pub status: ModerationStatus,
+    pub labels: Vec<ModerationLabel>,
+    pub reviewed_by: Option<ObjectId>,
+    pub reviewed_at: Option<DateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationStatus {
+    Approved,
+    Pending,
+    Rejected,
+}
+
+impl Default for ModerationStatus {
+    fn default() -> Self {
+        ModerationStatus::Approved
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModerationLabel {
+    pub name: String,
+    pub parent: Option<String>,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MetadataSummary {
+    pub orientation: Option<u32>,
+    pub removed_fields: Vec<String>,
+    pub gps: bool,
+    pub xmp: bool,
+    pub icc_profile: bool,
+    pub icc_profile_kept: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,7 +232,13 @@ pub struct User {
     pub id: bson::oid::ObjectId,
     pub username: String,
     pub password: String,
+    #[serde(default)]
+    pub email: Option<String>,
     pub avatar: String,
+    #[serde(default)]
+    pub placeholder: Option<Placeholder>,
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
     pub projects: Vec<String>,
     pub invitations: Vec<String>,
 }
@@ -40,6 +259,17 @@ pub struct Invitation {
     pub title: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailInvitation {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub email: String,
+    pub project: String,
+    pub author: ObjectId,
+    #[serde(default)]
+    pub token_hash: String,
+}
+
 impl Print for User {
     fn print_informations(&self) {
         println!(
@@ -59,10 +289,268 @@ pub struct Project {
     pub title: String,
     pub api_key: String,
     pub api_secret: String,
+    #[serde(default)]
+    pub signing_key: String,
     pub region: String,
     pub members: Vec<ObjectId>,
     pub invitations: Vec<String>,
-    pub avatars: Vec<Avatar>,
+    #[serde(default)]
+    pub settings: ProjectSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProjectSettings {
+    pub version_retention: u32,
+    pub upload_policy: UploadPolicy,
+    pub crop_policy: CropPolicy,
+    pub moderation_policy: ModerationPolicy,
+    pub animation_policy: AnimationPolicy,
+    pub output_policy: OutputPolicy,
+    pub delivery_policy: DeliveryPolicy,
+    pub access_policy: AccessPolicy,
+    pub domain_policy: DomainPolicy,
+}
+
+impl Default for ProjectSettings {
+    fn default() -> Self {
+        ProjectSettings {
+            version_retention: 5,
+            upload_policy: UploadPolicy::default(),
+            crop_policy: CropPolicy::default(),
+            moderation_policy: ModerationPolicy::default(),
+            animation_policy: AnimationPolicy::default(),
+            output_policy: OutputPolicy::default(),
+            delivery_policy: DeliveryPolicy::default(),
+            access_policy: AccessPolicy::default(),
+            domain_policy: DomainPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+#[serde(default)]
+#[validate(schema(function = "validate_upload_dimensions"))]
+pub struct UploadPolicy {
+    #[validate(length(min = 1))]
+    pub allowed_formats: Vec<String>,
+    #[validate(range(min = 1, max = 52428800))]
+    pub max_bytes: u64,
+    #[validate(range(min = 1))]
+    pub min_width: u32,
+    #[validate(range(min = 1))]
+    pub min_height: u32,
+    #[validate(range(min = 1, max = 16384))]
+    pub max_width: u32,
+    #[validate(range(min = 1, max = 16384))]
+    pub max_height: u32,
+    pub keep_icc_profile: bool,
+    pub reject_duplicates: bool,
+}
+
+impl Default for UploadPolicy {
+    fn default() -> Self {
+        UploadPolicy {
+            allowed_formats: vec![
+                "png".to_string(),
+                "jpeg".to_string(),
+                "gif".to_string(),
+                "webp".to_string(),
+            ],
+            max_bytes: 10 * 1024 * 1024,
+            min_width: 16,
+            min_height: 16,
+            max_width: 4096,
+            max_height: 4096,
+            keep_icc_profile: false,
+            reject_duplicates: false,
+        }
+    }
+}
+
+// No upload could pass a policy whose minimum dimensions exceed the maximum ones.
+fn validate_upload_dimensions(policy: &UploadPolicy) -> Result<(), ValidationError> {
+    if policy.min_width > policy.max_width || policy.min_height > policy.max_height {
+        let mut error = ValidationError::new("dimensions");
+        error.message =
+            Some("The minimum dimensions must not exceed the maximum dimensions.".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+#[serde(default)]
+pub struct CropPolicy {
+    pub enabled: bool,
+    #[validate(range(min = 0.0, max = 2.0))]
+    pub padding: f32,
+    pub square: bool,
+    pub face_selection: FaceSelection,
+    pub no_face: NoFaceBehavior,
+}
+
+impl Default for CropPolicy {
+    fn default() -> Self {
+        CropPolicy {
+            enabled: false,
+            padding: 0.5,
+            square: true,
+            face_selection: FaceSelection::Largest,
+            no_face: NoFaceBehavior::Smart,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FaceSelection {
+    Largest,
+    MostConfident,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoFaceBehavior {
+    Reject,
+    Center,
+    Smart,
+    Keep,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+#[serde(default)]
+pub struct ModerationPolicy {
+    pub enabled: bool,
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub min_confidence: f32,
+    pub blocked_labels: Vec<String>,
+}
+
+impl Default for ModerationPolicy {
+    fn default() -> Self {
+        ModerationPolicy {
+            enabled: false,
+            min_confidence: 0.8,
+            blocked_labels: vec![
+                "Explicit Nudity".to_string(),
+                "Suggestive".to_string(),
+                "Violence".to_string(),
+                "Visually Disturbing".to_string(),
+                "Hate Symbols".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+#[serde(default)]
+pub struct AnimationPolicy {
+    pub allow: bool,
+    #[validate(range(min = 2, max = 1000))]
+    pub max_frames: u32,
+    #[validate(range(min = 1, max = 60000))]
+    pub max_duration_ms: u32,
+    #[validate(range(min = 16, max = 2048))]
+    pub max_size: u32,
+}
+
+impl Default for AnimationPolicy {
+    fn default() -> Self {
+        AnimationPolicy {
+            allow: true,
+            max_frames: 300,
+            max_duration_ms: 20000,
+            max_size: 512,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+#[serde(default)]
+pub struct OutputPolicy {
+    pub formats: Vec<OutputFormat>,
+    #[validate(range(min = 1, max = 100))]
+    pub webp_quality: u8,
+    pub webp_lossless: bool,
+    #[validate(range(min = 1, max = 100))]
+    pub avif_quality: u8,
+    #[validate(range(min = 1, max = 10))]
+    pub avif_speed: u8,
+}
+
+impl Default for OutputPolicy {
+    fn default() -> Self {
+        OutputPolicy {
+            formats: vec![OutputFormat::Webp, OutputFormat::Avif],
+            webp_quality: 80,
+            webp_lossless: false,
+            avif_quality: 70,
+            avif_speed: 6,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+#[serde(default)]
+pub struct DeliveryPolicy {
+    #[validate(range(max = 31536000))]
+    pub max_age: u32,
+    #[validate(range(max = 31536000))]
+    pub versioned_max_age: u32,
+    pub url_template: Option<String>,
+}
+
+impl Default for DeliveryPolicy {
+    fn default() -> Self {
+        DeliveryPolicy {
+            max_age: 3600,
+            versioned_max_age: 31536000,
+            url_template: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Validate)]
+#[serde(default)]
+pub struct AccessPolicy {
+    pub visibility: Visibility,
+    #[validate(range(min = 60, max = 604800))]
+    pub url_ttl: u32,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        AccessPolicy {
+            visibility: Visibility::Public,
+            url_ttl: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Validate)]
+#[serde(default)]
+pub struct DomainPolicy {
+    #[validate(length(max = 50))]
+    pub allowed_origins: Vec<String>,
+    pub custom_domain: Option<String>,
+    // Both are managed by stampa: the token goes into a TXT record of the custom domain.
+    pub custom_domain_token: Option<String>,
+    pub custom_domain_verified: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Webp,
+    Avif,
 }
 
 impl Print for Project {
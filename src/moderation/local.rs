@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use image::{imageops::FilterType, DynamicImage};
+
+use super::Moderator;
+use crate::{errors::AppError, models::ModerationLabel};
+
+const SAMPLE_SIZE: u32 = 128;
+
+// Skin ratio alone can not tell a close-up face from nudity, so the label has no parent category
+// and is only acted upon by projects that list "Exposed Skin" in their blocked labels.
+pub struct LocalModerator {
+    pub skin_label: String,
+    pub skin_parent: Option<String>,
+}
+
+impl Default for LocalModerator {
+    fn default() -> Self {
+        LocalModerator {
+            skin_label: "Exposed Skin".to_string(),
+            skin_parent: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Moderator for LocalModerator {
+    async fn moderate(&self, image: &DynamicImage) -> Result<Vec<ModerationLabel>, AppError> {
+        let sample = image
+            .resize(SAMPLE_SIZE, SAMPLE_SIZE, FilterType::Triangle)
+            .to_rgb8();
+        let skin_pixels = sample
+            .pixels()
+            .filter(|pixel| is_skin(pixel.0[0], pixel.0[1], pixel.0[2]))
+            .count();
+        let skin_ratio = skin_pixels as f32 / (sample.width() * sample.height()).max(1) as f32;
+
+        Ok(vec![ModerationLabel {
+            name: self.skin_label.clone(),
+            parent: self.skin_parent.clone(),
+            confidence: skin_ratio,
+        }])
+    }
+}
+
+// Kovac et al. skin color rule under uniform daylight.
+fn is_skin(red: u8, green: u8, blue: u8) -> bool {
+    let max = red.max(green).max(blue);
+    let min = red.min(green).min(blue);
+    red > 95
+        && green > 40
+        && blue > 20
+        && max - min > 15
+        && (red as i16 - green as i16).abs() > 15
+        && red > green
+        && red > blue
+}
@@ -0,0 +1,106 @@
+mod local;
+mod rekognition;
+
+pub use local::*;
+pub use rekognition::*;
+
+use async_trait::async_trait;
+use image::DynamicImage;
+use mongodb::bson::DateTime;
+
+use crate::{
+    config::Config,
+    errors::AppError,
+    models::{Moderation, ModerationLabel, ModerationPolicy, ModerationStatus},
+};
+
+pub const MAX_REVIEWED_FRAMES: usize = 10;
+
+#[async_trait]
+pub trait Moderator: Send + Sync {
+    async fn moderate(&self, image: &DynamicImage) -> Result<Vec<ModerationLabel>, AppError>;
+}
+
+pub async fn from_config(config: &Config) -> Result<Box<dyn Moderator>, AppError> {
+    match config.moderator.as_str() {
+        "rekognition" => Ok(Box::new(RekognitionModerator::from_env().await)),
+        "local" => Ok(Box::new(LocalModerator::default())),
+        moderator => Err(AppError::moderation_error(format!(
+            "Unknown moderator {}.",
+            moderator
+        ))),
+    }
+}
+
+pub async fn review(
+    moderator: &dyn Moderator,
+    image: &DynamicImage,
+    policy: &ModerationPolicy,
+) -> Moderation {
+    review_frames(moderator, std::slice::from_ref(image), policy).await
+}
+
+// Every frame is moderated on its own, a clean first frame must not carry the animation through.
+pub async fn review_frames(
+    moderator: &dyn Moderator,
+    frames: &[DynamicImage],
+    policy: &ModerationPolicy,
+) -> Moderation {
+    if !policy.enabled {
+        return Moderation::default();
+    }
+
+    let mut labels: Vec<ModerationLabel> = Vec::new();
+    for frame in frames {
+        match moderator.moderate(frame).await {
+            Ok(frame_labels) => merge_labels(&mut labels, frame_labels),
+            Err(error) => {
+                log::warn!("Moderation failed, holding avatar for review: {:?}", error);
+                return Moderation {
+                    status: ModerationStatus::Pending,
+                    labels: Vec::new(),
+                    reviewed_by: None,
+                    reviewed_at: None,
+                };
+            }
+        }
+    }
+    let flagged = labels.iter().any(|label| {
+        label.confidence >= policy.min_confidence
+            && policy.blocked_labels.iter().any(|blocked_label| {
+                blocked_label == &label.name || Some(blocked_label) == label.parent.as_ref()
+            })
+    });
+    Moderation {
+        status: match flagged {
+            true => ModerationStatus::Pending,
+            false => ModerationStatus::Approved,
+        },
+        labels,
+        reviewed_by: None,
+        reviewed_at: Some(DateTime::now()),
+    }
+}
+
+// Keeps the highest confidence seen for each label across frames.
+fn merge_labels(labels: &mut Vec<ModerationLabel>, frame_labels: Vec<ModerationLabel>) {
+    for frame_label in frame_labels {
+        match labels
+            .iter_mut()
+            .find(|label| label.name == frame_label.name)
+        {
+            Some(label) => label.confidence = label.confidence.max(frame_label.confidence),
+            None => labels.push(frame_label),
+        }
+    }
+}
+
+// Evenly spaced frames, always including the first and the last one.
+pub fn sample_frames<T>(frames: &[T], max_frames: usize) -> Vec<&T> {
+    if frames.len() <= max_frames || max_frames < 2 {
+        return frames.iter().take(max_frames.max(1)).collect();
+    }
+    (0..max_frames)
+        .map(|index| &frames[index * (frames.len() - 1) / (max_frames - 1)])
+        .collect()
+}
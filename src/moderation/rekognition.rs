@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use aws_sdk_rekognition::{model::Image, types::Blob, Client};
+use image::DynamicImage;
+
+use super::Moderator;
+use crate::{errors::AppError, faces::rekognition_image, models::ModerationLabel};
+
+pub struct RekognitionModerator {
+    client: Client,
+}
+
+impl RekognitionModerator {
+    pub async fn from_env() -> RekognitionModerator {
+        let shared_config = aws_config::load_from_env().await;
+        RekognitionModerator {
+            client: Client::new(&shared_config),
+        }
+    }
+}
+
+#[async_trait]
+impl Moderator for RekognitionModerator {
+    async fn moderate(&self, image: &DynamicImage) -> Result<Vec<ModerationLabel>, AppError> {
+        let encoded_image =
+            rekognition_image(image).map_err(|error| AppError::moderation_error(error))?;
+
+        let moderation_labels = self
+            .client
+            .detect_moderation_labels()
+            .image(Image::builder().bytes(Blob::new(encoded_image)).build())
+            .send()
+            .await
+            .map_err(|error| AppError::moderation_error(error))?
+            .moderation_labels
+            .unwrap_or_default();
+
+        Ok(moderation_labels
+            .into_iter()
+            .filter_map(|label| {
+                Some(ModerationLabel {
+                    name: label.name?,
+                    parent: label.parent_name.filter(|parent| !parent.is_empty()),
+                    confidence: label.confidence.unwrap_or_default() / 100.0,
+                })
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use image::{imageops::FilterType, DynamicImage};
+
+use crate::models::Placeholder;
+
+const BLURHASH_SAMPLE_SIZE: u32 = 32;
+const BLURHASH_COMPONENTS_X: u32 = 4;
+const BLURHASH_COMPONENTS_Y: u32 = 3;
+const THUMBHASH_SAMPLE_SIZE: u32 = 100;
+const COLOR_SAMPLE_SIZE: u32 = 64;
+
+pub fn compute(image: &DynamicImage) -> Placeholder {
+    Placeholder {
+        blurhash: blurhash(image),
+        thumbhash: thumbhash(image),
+        dominant_color: dominant_color(image),
+    }
+}
+
+pub fn blurhash(image: &DynamicImage) -> String {
+    let sample = image
+        .resize(
+            BLURHASH_SAMPLE_SIZE,
+            BLURHASH_SAMPLE_SIZE,
+            FilterType::Triangle,
+        )
+        .to_rgba8();
+    blurhash::encode(
+        BLURHASH_COMPONENTS_X,
+        BLURHASH_COMPONENTS_Y,
+        sample.width(),
+        sample.height(),
+        sample.as_raw(),
+    )
+}
+
+pub fn thumbhash(image: &DynamicImage) -> String {
+    let sample = image
+        .resize(
+            THUMBHASH_SAMPLE_SIZE,
+            THUMBHASH_SAMPLE_SIZE,
+            FilterType::Triangle,
+        )
+        .to_rgba8();
+    base64::encode(thumbhash::rgba_to_thumb_hash(
+        sample.width() as usize,
+        sample.height() as usize,
+        sample.as_raw(),
+    ))
+}
+
+pub fn dominant_color(image: &DynamicImage) -> String {
+    let sample = image
+        .resize(COLOR_SAMPLE_SIZE, COLOR_SAMPLE_SIZE, FilterType::Triangle)
+        .to_rgba8();
+
+    // Pixels are grouped in 4 bits per channel buckets, the most populated bucket wins
+    // and its average color is returned.
+    let mut buckets: HashMap<(u8, u8, u8), (u32, [u32; 3])> = HashMap::new();
+    for pixel in sample.pixels().filter(|pixel| pixel.0[3] >= 128) {
+        let [red, green, blue, _] = pixel.0;
+        let bucket = buckets
+            .entry((red >> 4, green >> 4, blue >> 4))
+            .or_insert((0, [0; 3]));
+        bucket.0 += 1;
+        bucket.1[0] += red as u32;
+        bucket.1[1] += green as u32;
+        bucket.1[2] += blue as u32;
+    }
+
+    buckets
+        .values()
+        .max_by_key(|(count, _)| *count)
+        .map(|(count, sums)| {
+            format!(
+                "#{:02x}{:02x}{:02x}",
+                sums[0] / count,
+                sums[1] / count,
+                sums[2] / count
+            )
+        })
+        .unwrap_or_else(|| "#000000".to_string())
+}
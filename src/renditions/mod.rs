@@ -0,0 +1,146 @@
+use image::{codecs::avif::AvifEncoder, ColorType, DynamicImage, ImageEncoder, ImageFormat};
+
+use crate::{
+    errors::AppError,
+    metrics,
+    models::{OutputFormat, OutputPolicy, Rendition},
+    uploads::TemporaryImage,
+};
+
+pub struct EncodedRendition {
+    pub format: OutputFormat,
+    pub data: Vec<u8>,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Webp => "webp",
+            OutputFormat::Avif => "avif",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Avif => "image/avif",
+        }
+    }
+}
+
+pub fn encode_all(
+    image: &DynamicImage,
+    policy: &OutputPolicy,
+) -> Result<Vec<EncodedRendition>, AppError> {
+    policy
+        .formats
+        .iter()
+        .map(|format| {
+            encode(image, *format, policy).map(|data| EncodedRendition {
+                format: *format,
+                data,
+            })
+        })
+        .collect()
+}
+
+pub fn encode(
+    image: &DynamicImage,
+    format: OutputFormat,
+    policy: &OutputPolicy,
+) -> Result<Vec<u8>, AppError> {
+    let _timer = metrics::RENDER_DURATION
+        .with_label_values(&[format.extension()])
+        .start_timer();
+    let rgba_image = image.to_rgba8();
+    let (width, height) = rgba_image.dimensions();
+    match format {
+        OutputFormat::Webp => {
+            let encoder = webp::Encoder::from_rgba(&rgba_image, width, height);
+            let encoded_image = match policy.webp_lossless {
+                true => encoder.encode_lossless(),
+                false => encoder.encode(policy.webp_quality as f32),
+            };
+            Ok(encoded_image.to_vec())
+        }
+        OutputFormat::Avif => {
+            let mut encoded_image = Vec::new();
+            AvifEncoder::new_with_speed_quality(
+                &mut encoded_image,
+                policy.avif_speed,
+                policy.avif_quality,
+            )
+            .write_image(&rgba_image, width, height, ColorType::Rgba8)
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+            Ok(encoded_image)
+        }
+    }
+}
+
+pub fn mime_type_for_extension(extension: &str) -> &'static str {
+    match extension {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "tif" | "tiff" => "image/tiff",
+        "ico" => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+// Only explicit media types count: wildcards would hand new formats to clients that
+// never asked for them.
+fn accept_quality(accept: &str, mime_type: &str) -> f32 {
+    accept
+        .split(',')
+        .filter_map(|media_range| {
+            let mut parameters = media_range.split(';').map(str::trim);
+            let range = parameters.next()?;
+            if !range.eq_ignore_ascii_case(mime_type) {
+                return None;
+            }
+            Some(
+                parameters
+                    .find_map(|parameter| parameter.strip_prefix("q="))
+                    .and_then(|quality| quality.parse::<f32>().ok())
+                    .unwrap_or(1.0),
+            )
+        })
+        .fold(0.0, f32::max)
+}
+
+pub fn negotiate<'a>(accept: &str, renditions: &'a [Rendition]) -> Option<&'a Rendition> {
+    renditions
+        .iter()
+        .map(|rendition| {
+            (
+                rendition,
+                accept_quality(accept, rendition.format.mime_type()),
+            )
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.size.cmp(&a.0.size)))
+        .map(|(rendition, _)| rendition)
+}
+
+impl TemporaryImage {
+    pub fn rendition(&self, rendition: &EncodedRendition) -> Result<TemporaryImage, AppError> {
+        write(&self.path, rendition)
+    }
+}
+
+pub fn write(source_path: &str, rendition: &EncodedRendition) -> Result<TemporaryImage, AppError> {
+    let path = format!("{}-{}", source_path, rendition.format.extension());
+    std::fs::write(&path, &rendition.data).map_err(|error| AppError::fs_error(error))?;
+    Ok(TemporaryImage {
+        path,
+        format: match rendition.format {
+            OutputFormat::Webp => ImageFormat::WebP,
+            OutputFormat::Avif => ImageFormat::Avif,
+        },
+        size: rendition.data.len() as u64,
+    })
+}
@@ -0,0 +1,81 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::FindOptions,
+    Collection, Database, IndexModel,
+};
+
+use crate::{errors::AppError, models::*};
+
+pub struct AvatarVersionRepository {
+    pub database: Database,
+    pub collection: Collection<AvatarVersion>,
+}
+
+impl AvatarVersionRepository {
+    pub fn new(database: Database) -> Self {
+        let collection = database.collection::<AvatarVersion>("avatar_versions");
+        Self {
+            database,
+            collection,
+        }
+    }
+
+    #[tracing::instrument(name = "mongodb.avatar_versions.create_indexes", skip_all)]
+    pub async fn create_indexes(&self) -> Result<(), AppError> {
+        let avatar_index = IndexModel::builder()
+            .keys(doc! {"avatar": 1, "created_at": -1})
+            .build();
+        self.collection
+            .create_index(avatar_index, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+
+    #[tracing::instrument(name = "mongodb.avatar_versions.create", skip_all)]
+    pub async fn create(&self, version: AvatarVersion) -> Result<(), AppError> {
+        self.collection
+            .insert_one(version, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+
+    #[tracing::instrument(name = "mongodb.avatar_versions.get", skip_all)]
+    pub async fn get(
+        &self,
+        avatar_id: ObjectId,
+        version_id: ObjectId,
+    ) -> Result<AvatarVersion, AppError> {
+        self.collection
+            .find_one(doc! {"_id": version_id, "avatar": avatar_id}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .ok_or(AppError::not_found_error(version_id.to_string()))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatar_versions.get_avatar_versions", skip_all)]
+    pub async fn get_avatar_versions(
+        &self,
+        avatar_id: ObjectId,
+    ) -> Result<Vec<AvatarVersion>, AppError> {
+        let options = FindOptions::builder().sort(doc! {"created_at": -1}).build();
+        self.collection
+            .find(doc! {"avatar": avatar_id}, options)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatar_versions.delete", skip_all)]
+    pub async fn delete(&self, version_id: ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_one(doc! {"_id": version_id}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,351 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId},
+    options::{FindOptions, IndexOptions},
+    Collection, Database, IndexModel,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::is_duplicate_key;
+use crate::{errors::AppError, models::*};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FingerprintProjection {
+    pub _id: ObjectId,
+    pub fingerprint: Fingerprint,
+}
+
+pub struct AvatarRepository {
+    pub database: Database,
+    pub collection: Collection<Avatar>,
+}
+
+impl AvatarRepository {
+    pub fn new(database: Database) -> Self {
+        let collection = database.collection::<Avatar>("avatars");
+        Self {
+            database,
+            collection,
+        }
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.create_indexes", skip_all)]
+    pub async fn create_indexes(&self) -> Result<(), AppError> {
+        let project_index = IndexModel::builder()
+            .keys(doc! {"project": 1, "_id": 1})
+            .build();
+        let external_id_index = IndexModel::builder()
+            .keys(doc! {"project": 1, "external_id": 1})
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! {"external_id": {"$type": "string"}})
+                    .build(),
+            )
+            .build();
+        let moderation_index = IndexModel::builder()
+            .keys(doc! {"project": 1, "moderation.status": 1})
+            .build();
+        let content_hash_index = IndexModel::builder()
+            .keys(doc! {"project": 1, "content_hash": 1})
+            .build();
+        let fingerprint_index = IndexModel::builder()
+            .keys(doc! {"project": 1, "fingerprint.phash": 1})
+            .build();
+        let email_md5_index = IndexModel::builder()
+            .keys(doc! {"project": 1, "email_hash.md5": 1})
+            .build();
+        let email_sha256_index = IndexModel::builder()
+            .keys(doc! {"project": 1, "email_hash.sha256": 1})
+            .build();
+        self.collection
+            .create_indexes(
+                vec![
+                    project_index,
+                    external_id_index,
+                    moderation_index,
+                    content_hash_index,
+                    fingerprint_index,
+                    email_md5_index,
+                    email_sha256_index,
+                ],
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.create", skip_all)]
+    pub async fn create(&self, avatar: Avatar) -> Result<ObjectId, AppError> {
+        self.collection
+            .insert_one(avatar, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|insert_result| {
+                insert_result
+                    .inserted_id
+                    .as_object_id()
+                    .ok_or(AppError::db_error("Error while parsing ObjectId."))
+            })?
+    }
+
+    // Returns false when another avatar of the project already holds the external id.
+    #[tracing::instrument(name = "mongodb.avatars.create_if_absent", skip_all)]
+    pub async fn create_if_absent(&self, avatar: &Avatar) -> Result<bool, AppError> {
+        match self.collection.insert_one(avatar, None).await {
+            Ok(_) => Ok(true),
+            Err(error) if is_duplicate_key(&error) => Ok(false),
+            Err(error) => Err(AppError::db_error(error)),
+        }
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get", skip_all)]
+    pub async fn get(&self, avatar_id: ObjectId) -> Result<Avatar, AppError> {
+        self.collection
+            .find_one(doc! {"_id": avatar_id}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .ok_or(AppError::not_found_error(avatar_id.to_string()))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_by_external_id", skip_all)]
+    pub async fn get_by_external_id(
+        &self,
+        project_id: ObjectId,
+        external_id: &str,
+    ) -> Result<Option<Avatar>, AppError> {
+        self.collection
+            .find_one(
+                doc! {"project": project_id, "external_id": external_id},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_project_avatars", skip_all)]
+    pub async fn get_project_avatars(
+        &self,
+        project_id: ObjectId,
+        skip: u64,
+        limit: u64,
+    ) -> Result<(Vec<Avatar>, u64), AppError> {
+        let filter = doc! {"project": project_id};
+        let options = FindOptions::builder()
+            .sort(doc! {"_id": 1})
+            .skip(skip)
+            .limit(limit as i64)
+            .build();
+        let total = self
+            .collection
+            .count_documents(filter.clone(), None)
+            .await
+            .map_err(|error| AppError::db_error(error))?;
+        self.collection
+            .find(filter, options)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|avatars| (avatars, total))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_all_project_avatars", skip_all)]
+    pub async fn get_all_project_avatars(
+        &self,
+        project_id: ObjectId,
+    ) -> Result<Vec<Avatar>, AppError> {
+        self.collection
+            .find(doc! {"project": project_id}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_by_content_hash", skip_all)]
+    pub async fn get_by_content_hash(
+        &self,
+        project_id: ObjectId,
+        content_hash: &str,
+    ) -> Result<Option<Avatar>, AppError> {
+        self.collection
+            .find_one(
+                doc! {"project": project_id, "content_hash": content_hash},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_by_email_hash", skip_all)]
+    pub async fn get_by_email_hash(
+        &self,
+        project_id: ObjectId,
+        email_hash: &str,
+    ) -> Result<Option<Avatar>, AppError> {
+        let field = match email_hash.len() {
+            32 => "email_hash.md5",
+            _ => "email_hash.sha256",
+        };
+        self.collection
+            .find_one(doc! {"project": project_id, field: email_hash}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_fingerprints", skip_all)]
+    pub async fn get_fingerprints(
+        &self,
+        project_id: ObjectId,
+        after: Option<ObjectId>,
+        until: Option<ObjectId>,
+        limit: u64,
+    ) -> Result<Vec<FingerprintProjection>, AppError> {
+        let mut filter = doc! {"project": project_id, "fingerprint": {"$type": "object"}};
+        let mut id_range = doc! {};
+        if let Some(after) = after {
+            id_range.insert("$gt", after);
+        }
+        if let Some(until) = until {
+            id_range.insert("$lte", until);
+        }
+        if !id_range.is_empty() {
+            filter.insert("_id", id_range);
+        }
+        let options = FindOptions::builder()
+            .sort(doc! {"_id": 1})
+            .limit(limit as i64)
+            .projection(doc! {"_id": 1, "fingerprint": 1})
+            .build();
+        self.collection
+            .clone_with_type::<FingerprintProjection>()
+            .find(filter, options)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_many", skip_all)]
+    pub async fn get_many(
+        &self,
+        project_id: ObjectId,
+        avatar_ids: Vec<ObjectId>,
+    ) -> Result<Vec<Avatar>, AppError> {
+        let options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+        self.collection
+            .find(
+                doc! {"project": project_id, "_id": {"$in": avatar_ids}},
+                options,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.get_pending_avatars", skip_all)]
+    pub async fn get_pending_avatars(&self, project_id: ObjectId) -> Result<Vec<Avatar>, AppError> {
+        let options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+        self.collection
+            .find(
+                doc! {"project": project_id, "moderation.status": "pending"},
+                options,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.set_moderation", skip_all)]
+    pub async fn set_moderation(
+        &self,
+        avatar_id: ObjectId,
+        moderation: &Moderation,
+    ) -> Result<(), AppError> {
+        let moderation = bson::to_bson(moderation).map_err(|error| AppError::db_error(error))?;
+        let result = self
+            .collection
+            .update_one(
+                doc! {"_id": avatar_id},
+                doc! {"$set": {"moderation": moderation}},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|update_result| update_result.matched_count)?;
+        match result {
+            1 => Ok(()),
+            _ => Err(AppError::not_found_error(avatar_id.to_string())),
+        }
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.rename", skip_all)]
+    pub async fn rename(&self, avatar_id: ObjectId, name: &str) -> Result<(), AppError> {
+        let result = self
+            .collection
+            .update_one(
+                doc! {
+                    "_id": avatar_id
+                },
+                doc! {
+                    "$set": { "name": name }
+                },
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|update_result| update_result.matched_count)?;
+        match result {
+            1 => Ok(()),
+            _ => Err(AppError::not_found_error(avatar_id.to_string())),
+        }
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.replace", skip_all)]
+    pub async fn replace(&self, avatar: &Avatar) -> Result<(), AppError> {
+        let result = self
+            .collection
+            .replace_one(doc! {"_id": avatar._id}, avatar, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|update_result| update_result.matched_count)?;
+        match result {
+            1 => Ok(()),
+            _ => Err(AppError::not_found_error(avatar._id.to_string())),
+        }
+    }
+
+    // Returns the replaced avatar, concurrent replacements each get a different one.
+    #[tracing::instrument(name = "mongodb.avatars.swap", skip_all)]
+    pub async fn swap(&self, avatar: &Avatar) -> Result<Avatar, AppError> {
+        self.collection
+            .find_one_and_replace(doc! {"_id": avatar._id}, avatar, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .ok_or(AppError::not_found_error(avatar._id.to_string()))
+    }
+
+    #[tracing::instrument(name = "mongodb.avatars.delete", skip_all)]
+    pub async fn delete(&self, avatar_id: ObjectId) -> Result<(), AppError> {
+        let result = self
+            .collection
+            .delete_one(doc! {"_id": avatar_id}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|delete_result| delete_result.deleted_count)?;
+        match result {
+            1 => Ok(()),
+            _ => Err(AppError::not_found_error(avatar_id.to_string())),
+        }
+    }
+}
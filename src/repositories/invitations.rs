@@ -0,0 +1,109 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::{IndexOptions, UpdateOptions},
+    Collection, Database, IndexModel,
+};
+
+use crate::{errors::AppError, models::*, utils::hash_token};
+
+pub struct InvitationRepository {
+    pub database: Database,
+    pub collection: Collection<EmailInvitation>,
+}
+
+impl InvitationRepository {
+    pub fn new(database: Database) -> Self {
+        let collection = database.collection::<EmailInvitation>("email_invitations");
+        Self {
+            database,
+            collection,
+        }
+    }
+
+    #[tracing::instrument(name = "mongodb.email_invitations.create_indexes", skip_all)]
+    pub async fn create_indexes(&self) -> Result<(), AppError> {
+        let token_index = IndexModel::builder()
+            .keys(doc! {"token_hash": 1})
+            .options(
+                IndexOptions::builder()
+                    .partial_filter_expression(doc! {"token_hash": {"$type": "string"}})
+                    .build(),
+            )
+            .build();
+        self.collection
+            .create_index(token_index, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+
+    // Inviting the same address again replaces the token, older mails can no longer be redeemed.
+    #[tracing::instrument(name = "mongodb.email_invitations.upsert", skip_all)]
+    pub async fn upsert(
+        &self,
+        email: &str,
+        project_id: &str,
+        author: ObjectId,
+        token: &str,
+    ) -> Result<(), AppError> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.collection
+            .update_one(
+                doc! {
+                    "email": email,
+                    "project": project_id
+                },
+                doc! {
+                    "$set": { "author": author, "token_hash": hash_token(token) },
+                    "$setOnInsert": { "_id": ObjectId::new() }
+                },
+                options,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+
+    #[tracing::instrument(name = "mongodb.email_invitations.get_by_email", skip_all)]
+    pub async fn get_by_email(&self, email: &str) -> Result<Vec<EmailInvitation>, AppError> {
+        self.collection
+            .find(doc! {"email": email}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.email_invitations.get_by_token", skip_all)]
+    pub async fn get_by_token(&self, token: &str) -> Result<EmailInvitation, AppError> {
+        self.collection
+            .find_one(doc! {"token_hash": hash_token(token)}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .ok_or(AppError::not_found_error("invitation"))
+    }
+
+    // Tokens are single use, the invitation is gone once it has been redeemed.
+    #[tracing::instrument(name = "mongodb.email_invitations.take", skip_all)]
+    pub async fn take(&self, project_id: &str, token: &str) -> Result<EmailInvitation, AppError> {
+        self.collection
+            .find_one_and_delete(
+                doc! {"project": project_id, "token_hash": hash_token(token)},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .ok_or(AppError::not_found_error("invitation"))
+    }
+
+    #[tracing::instrument(name = "mongodb.email_invitations.delete", skip_all)]
+    pub async fn delete(&self, invitation_id: ObjectId) -> Result<(), AppError> {
+        self.collection
+            .delete_one(doc! {"_id": invitation_id}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+}
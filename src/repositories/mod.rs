@@ -1,5 +1,25 @@
+pub mod avatar_versions;
+pub mod avatars;
+pub mod invitations;
 pub mod projects;
 pub mod users;
 
+pub use avatar_versions::*;
+pub use avatars::*;
+pub use invitations::*;
 pub use projects::*;
 pub use users::*;
+
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+pub fn is_duplicate_key(error: &Error) -> bool {
+    match &*error.kind {
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) => {
+            write_error.code == DUPLICATE_KEY_CODE
+        }
+        ErrorKind::Command(command_error) => command_error.code == DUPLICATE_KEY_CODE,
+        _ => false,
+    }
+}
@@ -24,13 +24,18 @@ pub struct ProjectProjection {
     pub api_key: String,
     pub members: Vec<MemberProjection>,
     pub invitations: Vec<String>,
-    pub avatars: Vec<Avatar>,
+    #[serde(default)]
+    pub settings: ProjectSettings,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MemberProjection {
     pub username: String,
     pub _id: ObjectId,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub placeholder: Option<Placeholder>,
 }
 
 pub struct ProjectRepository {
@@ -47,6 +52,7 @@ impl ProjectRepository {
         }
     }
 
+    #[tracing::instrument(name = "mongodb.projects.create", skip_all)]
     pub async fn create(&self, project: Project) -> Result<ObjectId, AppError> {
         self.collection
             .insert_one(project, None)
@@ -60,6 +66,7 @@ impl ProjectRepository {
             })?
     }
 
+    #[tracing::instrument(name = "mongodb.projects.get", skip_all)]
     pub async fn get(&self, project_id: ObjectId) -> Result<ProjectProjection, AppError> {
         let pipeline = vec![
             doc! {
@@ -84,10 +91,12 @@ impl ProjectRepository {
                     "region": 1,
                     "members": {
                         "_id": 1,
-                        "username": 1
+                        "username": 1,
+                        "avatar": 1,
+                        "placeholder": 1
                     },
                     "invitations": 1,
-                    "avatars": 1
+                    "settings": 1
                 }
             },
             doc! {
@@ -111,6 +120,7 @@ impl ProjectRepository {
             .map_err(|error| AppError::db_error(error))
     }
 
+    #[tracing::instrument(name = "mongodb.projects.add_user", skip_all)]
     pub async fn add_user(&self, project_id: ObjectId, user_id: ObjectId) -> Result<(), AppError> {
         let result = self
             .collection
@@ -125,14 +135,53 @@ impl ProjectRepository {
             )
             .await
             .map_err(|error| AppError::db_error(error))
-            .map(|update_result| update_result.modified_count)?;
+            .map(|update_result| update_result.matched_count)?;
         match result {
             1 => Ok(()),
-            _ => Err(AppError::db_error("Internal error.")),
+            _ => Err(AppError::not_found_error(project_id.to_string())),
         }
     }
 
-    pub async fn add_avatar(&self, project_id: ObjectId, avatar: Avatar) -> Result<(), AppError> {
+    #[tracing::instrument(name = "mongodb.projects.get_domain_policies", skip_all)]
+    pub async fn get_domain_policies(&self) -> Result<Vec<(ObjectId, DomainPolicy)>, AppError> {
+        let filter = doc! {"$or": [
+            {"settings.domain_policy.allowed_origins.0": {"$exists": true}},
+            {"settings.domain_policy.custom_domain": {"$type": "string"}},
+        ]};
+        self.collection
+            .find(filter, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .map_ok(|project| (project.id, project.settings.domain_policy))
+            .try_collect()
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.projects.custom_domain_taken", skip_all)]
+    pub async fn custom_domain_taken(
+        &self,
+        project_id: ObjectId,
+        custom_domain: &str,
+    ) -> Result<bool, AppError> {
+        self.collection
+            .count_documents(
+                doc! {"_id": {"$ne": project_id}, "settings.domain_policy.custom_domain": custom_domain},
+                None,
+            )
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|count| count > 0)
+    }
+
+    #[tracing::instrument(name = "mongodb.projects.update_settings", skip_all)]
+    pub async fn update_settings(
+        &self,
+        project_id: ObjectId,
+        settings: &ProjectSettings,
+    ) -> Result<(), AppError> {
+        let settings_document =
+            bson::to_document(settings).map_err(|error| AppError::db_error(error))?;
         let result = self
             .collection
             .update_one(
@@ -140,24 +189,20 @@ impl ProjectRepository {
                     "_id": project_id
                 },
                 doc! {
-                    "$push": { "avatars": doc!{
-                        "_id": avatar._id,
-                        "name": avatar.name,
-                         "mime_type": avatar.mime_type,
-                         "url": avatar.url
-                    } }
+                    "$set": { "settings": settings_document }
                 },
                 None,
             )
             .await
             .map_err(|error| AppError::db_error(error))
-            .map(|update_result| update_result.modified_count)?;
+            .map(|update_result| update_result.matched_count)?;
         match result {
             1 => Ok(()),
-            _ => Err(AppError::db_error("Internal error.")),
+            _ => Err(AppError::not_found_error(project_id.to_string())),
         }
     }
 
+    #[tracing::instrument(name = "mongodb.projects.get_user_projects", skip_all)]
     pub async fn get_user_projects(&self, user_id: ObjectId) -> Result<Vec<Project>, AppError> {
         let result = self
             .collection
@@ -171,6 +216,7 @@ impl ProjectRepository {
             .map(|projects| projects)
     }
 
+    #[tracing::instrument(name = "mongodb.projects.get_user_invitations", skip_all)]
     pub async fn get_user_invitations(
         &self,
         user_id: ObjectId,
@@ -213,6 +259,7 @@ impl ProjectRepository {
         Ok(results)
     }
 
+    #[tracing::instrument(name = "mongodb.projects.add_invitation", skip_all)]
     pub async fn add_invitation(self, project_id: ObjectId, user_id: &str) -> Result<(), AppError> {
         let result = self
             .collection
@@ -227,13 +274,14 @@ impl ProjectRepository {
             )
             .await
             .map_err(|error| AppError::db_error(error))
-            .map(|update_result| update_result.modified_count)?;
+            .map(|update_result| update_result.matched_count)?;
         match result {
             1 => Ok(()),
-            _ => Err(AppError::db_error("Internal error.")),
+            _ => Err(AppError::not_found_error(project_id.to_string())),
         }
     }
 
+    #[tracing::instrument(name = "mongodb.projects.remove_invitation", skip_all)]
     pub async fn remove_invitation(
         self,
         project_id: ObjectId,
@@ -243,7 +291,8 @@ impl ProjectRepository {
             .collection
             .update_one(
                 doc! {
-                    "_id": project_id
+                    "_id": project_id,
+                    "invitations": user_id
                 },
                 doc! {
                     "$pull": { "invitations": user_id }
@@ -252,13 +301,14 @@ impl ProjectRepository {
             )
             .await
             .map_err(|error| AppError::db_error(error))
-            .map(|update_result| update_result.modified_count)?;
+            .map(|update_result| update_result.matched_count)?;
         match result {
             1 => Ok(()),
-            _ => Err(AppError::db_error("Internal error.")),
+            _ => Err(AppError::not_found_error(user_id)),
         }
     }
 
+    #[tracing::instrument(name = "mongodb.projects.get_secret", skip_all)]
     pub async fn get_secret(self, project_id: ObjectId) -> Result<String, AppError> {
         let filter = doc! {"_id": project_id};
         self.collection
@@ -266,7 +316,18 @@ impl ProjectRepository {
             .await
             .map(|project| project)
             .map_err(|error| AppError::db_error(error))?
-            .ok_or(AppError::db_error("xd"))
+            .ok_or(AppError::not_found_error(project_id.to_string()))
             .map(|project| project.api_secret)
     }
+
+    #[tracing::instrument(name = "mongodb.projects.get_signing_key", skip_all)]
+    pub async fn get_signing_key(&self, project_id: ObjectId) -> Result<String, AppError> {
+        self.collection
+            .find_one(doc! {"_id": project_id}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))?
+            .map(|project| project.signing_key)
+            .filter(|signing_key| !signing_key.is_empty())
+            .ok_or_else(|| AppError::not_found_error(project_id))
+    }
 }
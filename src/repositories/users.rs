@@ -1,11 +1,12 @@
 use futures::TryStreamExt;
 use mongodb::{
     bson::{doc, oid::ObjectId, Bson, Regex},
-    options::FindOptions,
-    Collection, Database,
+    options::{FindOptions, IndexOptions},
+    Collection, Database, IndexModel,
 };
 use std::str::FromStr;
 
+use super::is_duplicate_key;
 use crate::{errors::AppError, models::*};
 
 pub async fn get_project(
@@ -13,7 +14,7 @@ pub async fn get_project(
     project_id: String,
 ) -> Result<Project, Box<dyn std::error::Error>> {
     let user_collection = database.collection::<Project>("projects");
-    let filter = doc! {"_id": ObjectId::from_str(project_id.as_str()).unwrap()};
+    let filter = doc! {"_id": ObjectId::from_str(project_id.as_str())?};
     let user = user_collection.find_one(filter, None).await?;
 
     match user {
@@ -37,14 +38,39 @@ impl UserRepository {
         }
     }
 
+    #[tracing::instrument(name = "mongodb.users.create_indexes", skip_all)]
+    pub async fn create_indexes(&self) -> Result<(), AppError> {
+        let email_index = IndexModel::builder()
+            .keys(doc! {"email": 1})
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! {"email": {"$type": "string"}})
+                    .build(),
+            )
+            .build();
+        self.collection
+            .create_index(email_index, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+            .map(|_| ())
+    }
+
+    #[tracing::instrument(name = "mongodb.users.create", skip_all)]
     pub async fn create(&self, user: User) -> Result<Bson, AppError> {
+        // `email_exist` runs before the insert, the unique index settles concurrent registrations.
+        let email = user.email.clone().unwrap_or_default();
         self.collection
             .insert_one(user, None)
             .await
-            .map_err(|error| AppError::db_error(error))
+            .map_err(|error| match is_duplicate_key(&error) {
+                true => AppError::user_exist_error(email),
+                false => AppError::db_error(error),
+            })
             .map(|update_result| update_result.inserted_id)
     }
 
+    #[tracing::instrument(name = "mongodb.users.get", skip_all)]
     pub async fn get(&self, user_id: ObjectId) -> Result<User, AppError> {
         self.collection
             .find_one(doc! {"_id": user_id}, None)
@@ -53,6 +79,7 @@ impl UserRepository {
             .ok_or(AppError::not_found_error(user_id.to_string()))
     }
 
+    #[tracing::instrument(name = "mongodb.users.get_by_username", skip_all)]
     pub async fn get_by_username(&self, username: &String) -> Result<User, AppError> {
         self.collection
             .find_one(doc! {"username": username}, None)
@@ -61,6 +88,7 @@ impl UserRepository {
             .ok_or(AppError::not_found_error(username))
     }
 
+    #[tracing::instrument(name = "mongodb.users.exist", skip_all)]
     pub async fn exist(&self, username: &str) -> Result<(), AppError> {
         let user = self
             .collection
@@ -73,6 +101,23 @@ impl UserRepository {
         }
     }
 
+    #[tracing::instrument(name = "mongodb.users.find_by_email", skip_all)]
+    pub async fn find_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        self.collection
+            .find_one(doc! {"email": email}, None)
+            .await
+            .map_err(|error| AppError::db_error(error))
+    }
+
+    #[tracing::instrument(name = "mongodb.users.email_exist", skip_all)]
+    pub async fn email_exist(&self, email: &str) -> Result<(), AppError> {
+        match self.find_by_email(email).await? {
+            Some(_) => Err(AppError::user_exist_error(email)),
+            None => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "mongodb.users.in_project", skip_all)]
     pub async fn in_project(&self, user_id: ObjectId, project_id: &str) -> Result<(), AppError> {
         self.collection
             .find_one(doc! {"_id": user_id, "projects": project_id}, None)
@@ -84,6 +129,7 @@ impl UserRepository {
             )
     }
 
+    #[tracing::instrument(name = "mongodb.users.add_project", skip_all)]
     pub async fn add_project(&self, user_id: ObjectId, project_id: &str) -> Result<(), AppError> {
         let result = self
             .collection
@@ -98,13 +144,14 @@ impl UserRepository {
             )
             .await
             .map_err(|error| AppError::db_error(error))
-            .map(|update_result| update_result.modified_count)?;
+            .map(|update_result| update_result.matched_count)?;
         match result {
             1 => Ok(()),
-            _ => Err(AppError::db_error("Internal error.")),
+            _ => Err(AppError::not_found_error(user_id.to_string())),
         }
     }
 
+    #[tracing::instrument(name = "mongodb.users.get_available_users", skip_all)]
     pub async fn get_available_users(
         self,
         project_id: &str,
@@ -133,6 +180,7 @@ impl UserRepository {
             .map_err(|error| AppError::db_error(error))
     }
 
+    #[tracing::instrument(name = "mongodb.users.add_invitation", skip_all)]
     pub async fn add_invitation(self, user_id: ObjectId, project_id: &str) -> Result<(), AppError> {
         let result = self
             .collection
@@ -147,13 +195,14 @@ impl UserRepository {
             )
             .await
             .map_err(|error| AppError::db_error(error))
-            .map(|update_result| update_result.modified_count)?;
+            .map(|update_result| update_result.matched_count)?;
         match result {
             1 => Ok(()),
-            _ => Err(AppError::db_error("Internal error.")),
+            _ => Err(AppError::not_found_error(user_id.to_string())),
         }
     }
 
+    #[tracing::instrument(name = "mongodb.users.remove_invitation", skip_all)]
     pub async fn remove_invitation(
         self,
         user_id: ObjectId,
@@ -163,7 +212,8 @@ impl UserRepository {
             .collection
             .update_one(
                 doc! {
-                    "_id": user_id
+                    "_id": user_id,
+                    "invitations": project_id
                 },
                 doc! {
                     "$pull": { "invitations": project_id }
@@ -172,10 +222,10 @@ impl UserRepository {
             )
             .await
             .map_err(|error| AppError::db_error(error))
-            .map(|update_result| update_result.modified_count)?;
+            .map(|update_result| update_result.matched_count)?;
         match result {
             1 => Ok(()),
-            _ => Err(AppError::db_error("Internal error.")),
+            _ => Err(AppError::not_found_error(project_id)),
         }
     }
 }
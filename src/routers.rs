@@ -1,9 +1,18 @@
 use crate::handlers::{
-    accept_invitation, create_avatar, create_project, deny_invitation, get_available_users,
-    get_invitations, get_project, get_project_credentials, get_projects, invite_user, login, me,
-    register,
+    accept_invitation, approve_avatar, create_avatar, create_project, delete_avatar,
+    delete_external_avatar, deny_invitation, get_available_users, get_avatar, get_avatar_versions,
+    get_avatars, get_duplicate_avatars, get_external_avatar, get_gravatar, get_invitations,
+    get_liveness, get_media, get_metrics, get_moderation_queue, get_project,
+    get_project_credentials, get_projects, get_readiness, invite_user, login, me, register,
+    reject_avatar, rename_avatar, rename_external_avatar, replace_avatar, replace_avatar_raw,
+    replace_external_avatar, replace_external_avatar_raw, rollback_avatar, update_project_settings,
+    upload_avatar_multipart, upload_avatar_raw, verify_custom_domain,
+};
+use crate::uploads::{multipart_guard, raw_image_guard};
+use actix_web::{
+    guard,
+    web::{self, ServiceConfig},
 };
-use actix_web::web::{self, ServiceConfig};
 
 pub fn project_router(cfg: &mut ServiceConfig) {
     cfg.service(
@@ -23,6 +32,16 @@ pub fn project_router(cfg: &mut ServiceConfig) {
             .route(
                 "/{project_id}/available_users",
                 web::get().to(get_available_users),
+            )
+            // Update specific project settings
+            .route(
+                "/{project_id}/settings",
+                web::patch().to(update_project_settings),
+            )
+            // Verify the TXT record of specific project custom domain
+            .route(
+                "/{project_id}/domain/verify",
+                web::post().to(verify_custom_domain),
             ),
     );
 }
@@ -49,8 +68,117 @@ pub fn user_router(cfg: &mut ServiceConfig) {
 pub fn avatar_router(cfg: &mut ServiceConfig) {
     cfg.service(
         web::scope("/avatar")
+            // Add an avatar from a multipart form
+            .route(
+                "",
+                web::post()
+                    .guard(guard::fn_guard(multipart_guard))
+                    .to(upload_avatar_multipart),
+            )
+            // Add an avatar from a raw image body
+            .route(
+                "",
+                web::post()
+                    .guard(guard::fn_guard(raw_image_guard))
+                    .to(upload_avatar_raw),
+            )
             // Add an avatar (Image)
-            .route("", web::post().to(create_avatar)), // Generate an avatar (2 letters)
+            .route("", web::post().to(create_avatar)) // Generate an avatar (2 letters)
+            // Get the avatars of a project
+            .route("", web::get().to(get_avatars))
+            // Get clusters of near-duplicate avatars of a project
+            .route("/duplicates", web::get().to(get_duplicate_avatars))
+            // Get the avatars of a project waiting for review
+            .route("/moderation", web::get().to(get_moderation_queue))
+            // Get specific avatar
+            .route("/{avatar_id}", web::get().to(get_avatar))
+            // Rename specific avatar
+            .route("/{avatar_id}", web::patch().to(rename_avatar))
+            // Replace specific avatar image from a raw image body
+            .route(
+                "/{avatar_id}",
+                web::put()
+                    .guard(guard::fn_guard(raw_image_guard))
+                    .to(replace_avatar_raw),
+            )
+            // Replace specific avatar image
+            .route("/{avatar_id}", web::put().to(replace_avatar))
+            // Delete specific avatar
+            .route("/{avatar_id}", web::delete().to(delete_avatar))
+            // Get specific avatar previous versions
+            .route("/{avatar_id}/versions", web::get().to(get_avatar_versions))
+            // Rollback specific avatar to a previous version
+            .route(
+                "/{avatar_id}/versions/{version_id}/rollback",
+                web::post().to(rollback_avatar),
+            )
+            // Approve specific avatar after review
+            .route(
+                "/{avatar_id}/moderation/approve",
+                web::post().to(approve_avatar),
+            )
+            // Reject specific avatar after review
+            .route(
+                "/{avatar_id}/moderation/reject",
+                web::post().to(reject_avatar),
+            )
+            // Get specific avatar by its external identifier
+            .route(
+                "/external/{project_id}/{external_id}",
+                web::get().to(get_external_avatar),
+            )
+            // Rename specific avatar by its external identifier
+            .route(
+                "/external/{project_id}/{external_id}",
+                web::patch().to(rename_external_avatar),
+            )
+            // Replace specific avatar image by its external identifier from a raw image body
+            .route(
+                "/external/{project_id}/{external_id}",
+                web::put()
+                    .guard(guard::fn_guard(raw_image_guard))
+                    .to(replace_external_avatar_raw),
+            )
+            // Replace specific avatar image by its external identifier
+            .route(
+                "/external/{project_id}/{external_id}",
+                web::put().to(replace_external_avatar),
+            )
+            // Delete specific avatar by its external identifier
+            .route(
+                "/external/{project_id}/{external_id}",
+                web::delete().to(delete_external_avatar),
+            ),
+    );
+}
+
+pub fn media_router(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/media")
+            // Deliver an approved avatar in the best format the client accepts
+            .route("/{avatar_id}", web::get().to(get_media)),
+    );
+}
+
+pub fn gravatar_router(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/avatar")
+            // Resolve an email hash the way Gravatar does
+            .route("/{hash}", web::get().to(get_gravatar)),
+    );
+}
+
+pub fn health_router(cfg: &mut ServiceConfig) {
+    // Liveness only proves the process answers, readiness checks the dependencies
+    cfg.route("/healthz", web::get().to(get_liveness))
+        .route("/readyz", web::get().to(get_readiness));
+}
+
+pub fn metrics_router(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/metrics")
+            // Expose Prometheus metrics
+            .route("", web::get().to(get_metrics)),
     );
 }
 
@@ -0,0 +1,188 @@
+use hmac::{Hmac, Mac};
+use mongodb::bson::oid::ObjectId;
+use sha2::Sha256;
+
+use crate::models::{Avatar, AvatarVersion, OutputFormat};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct UrlSigner {
+    pub public_url: String,
+    pub signing_key: String,
+    pub ttl: u32,
+    // Review URLs also serve avatars that are not approved yet, to the project author only.
+    pub review: bool,
+}
+
+impl UrlSigner {
+    pub fn sign_avatar(&self, mut avatar: Avatar) -> Avatar {
+        let expires = self.expires();
+        avatar.url = self.url(avatar._id, None, expires, "");
+        if let Some(animation) = avatar.animation.as_mut() {
+            animation.static_url = Some(self.url(avatar._id, None, expires, "&static=true"));
+        }
+        for rendition in avatar.renditions.iter_mut() {
+            rendition.url = self.url(avatar._id, None, expires, &format_query(rendition.format));
+        }
+        avatar
+    }
+
+    pub fn sign_version(&self, mut version: AvatarVersion) -> AvatarVersion {
+        let expires = self.expires();
+        version.url = self.url(version.avatar, Some(version.id), expires, "");
+        if let Some(animation) = version.animation.as_mut() {
+            animation.static_url =
+                Some(self.url(version.avatar, Some(version.id), expires, "&static=true"));
+        }
+        for rendition in version.renditions.iter_mut() {
+            rendition.url = self.url(
+                version.avatar,
+                Some(version.id),
+                expires,
+                &format_query(rendition.format),
+            );
+        }
+        version
+    }
+
+    // Expiry is rounded up to the next minute so repeated API calls hand out the same URL,
+    // which keeps browser caches useful.
+    fn expires(&self) -> i64 {
+        let expires = chrono::Utc::now().timestamp() + self.ttl as i64;
+        expires + (60 - expires % 60) % 60
+    }
+
+    fn url(
+        &self,
+        avatar_id: ObjectId,
+        version_id: Option<ObjectId>,
+        expires: i64,
+        extra_query: &str,
+    ) -> String {
+        let signature = match self.review {
+            true => sign_review(&self.signing_key, avatar_id, version_id, expires),
+            false => sign(&self.signing_key, avatar_id, version_id, expires),
+        };
+        let version_query = version_id
+            .map(|version_id| format!("&version={}", version_id))
+            .unwrap_or_default();
+        let review_query = match self.review {
+            true => "&review=true",
+            false => "",
+        };
+        format!(
+            "{}/media/{}?expires={}&signature={}{}{}{}",
+            self.public_url.trim_end_matches('/'),
+            avatar_id,
+            expires,
+            signature,
+            version_query,
+            review_query,
+            extra_query
+        )
+    }
+}
+
+fn format_query(format: OutputFormat) -> String {
+    format!("&format={}", format.extension())
+}
+
+fn mac(
+    secret: &str,
+    avatar_id: ObjectId,
+    version_id: Option<ObjectId>,
+    expires: i64,
+    review: bool,
+) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    let version_id = version_id
+        .map(|version_id| version_id.to_string())
+        .unwrap_or_default();
+    let scope = match review {
+        true => ":review",
+        false => "",
+    };
+    mac.update(format!("{}:{}:{}{}", avatar_id, version_id, expires, scope).as_bytes());
+    mac
+}
+
+fn signature(
+    secret: &str,
+    avatar_id: ObjectId,
+    version_id: Option<ObjectId>,
+    expires: i64,
+    review: bool,
+) -> String {
+    mac(secret, avatar_id, version_id, expires, review)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn verify_signature(
+    secret: &str,
+    avatar_id: ObjectId,
+    version_id: Option<ObjectId>,
+    expires: i64,
+    review: bool,
+    signature: &str,
+    now: i64,
+) -> bool {
+    if expires < now || signature.len() != 64 {
+        return false;
+    }
+    let signature: Option<Vec<u8>> = (0..32)
+        .map(|index| u8::from_str_radix(signature.get(index * 2..index * 2 + 2)?, 16).ok())
+        .collect();
+    match signature {
+        Some(signature) => mac(secret, avatar_id, version_id, expires, review)
+            .verify_slice(&signature)
+            .is_ok(),
+        None => false,
+    }
+}
+
+pub fn sign(
+    secret: &str,
+    avatar_id: ObjectId,
+    version_id: Option<ObjectId>,
+    expires: i64,
+) -> String {
+    signature(secret, avatar_id, version_id, expires, false)
+}
+
+pub fn sign_review(
+    secret: &str,
+    avatar_id: ObjectId,
+    version_id: Option<ObjectId>,
+    expires: i64,
+) -> String {
+    signature(secret, avatar_id, version_id, expires, true)
+}
+
+pub fn verify(
+    secret: &str,
+    avatar_id: ObjectId,
+    version_id: Option<ObjectId>,
+    expires: i64,
+    signature: &str,
+    now: i64,
+) -> bool {
+    verify_signature(
+        secret, avatar_id, version_id, expires, false, signature, now,
+    )
+}
+
+pub fn verify_review(
+    secret: &str,
+    avatar_id: ObjectId,
+    version_id: Option<ObjectId>,
+    expires: i64,
+    signature: &str,
+    now: i64,
+) -> bool {
+    verify_signature(secret, avatar_id, version_id, expires, true, signature, now)
+}
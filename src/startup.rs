@@ -1,35 +1,97 @@
-use actix_web::dev::Server;
-use actix_web::middleware::Logger;
+use actix_cors::Cors;
+use actix_web::dev::{Server, ServerHandle};
+use actix_web::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use actix_web::web::Data;
 use actix_web::{web, App, HttpServer};
 use actix_web_httpauth::middleware::HttpAuthentication;
 use std::net::TcpListener;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
 
 use crate::middlewares::validator;
 use crate::routers::{
-    avatar_router, invitation_router, project_router, public_router, user_router,
+    avatar_router, gravatar_router, health_router, invitation_router, media_router, metrics_router,
+    project_router, public_router, user_router,
 };
+use crate::telemetry::RequestTracing;
 use crate::AppState;
 
+// API routes only allow the global origins. Delivery routes set their own per-project CORS
+// headers, see `DomainRegistry::apply_cors`.
+fn cors(app_state: &Data<AppState>) -> Cors {
+    let app_state = app_state.clone();
+    Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origin
+                .to_str()
+                .map_or(false, |origin| app_state.domains.origin_allowed(origin))
+        })
+        .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+        .allowed_headers(vec![AUTHORIZATION, CONTENT_TYPE, ACCEPT])
+        .max_age(3600)
+}
+
 pub fn run(listener: TcpListener, app_state: Data<AppState>) -> Result<Server, std::io::Error> {
     let server = HttpServer::new(move || {
         let auth = HttpAuthentication::bearer(validator);
         App::new()
-            .wrap(Logger::default())
-            .wrap(actix_cors::Cors::permissive())
+            .wrap(RequestTracing)
             .app_data(app_state.clone())
             .service(
                 web::scope("/api")
                     .wrap(auth)
-                    .wrap(actix_cors::Cors::permissive())
+                    .wrap(cors(&app_state))
                     .configure(user_router)
                     .configure(project_router)
                     .configure(invitation_router)
                     .configure(avatar_router),
             )
-            .configure(public_router)
+            .configure(media_router)
+            .configure(gravatar_router)
+            .configure(metrics_router)
+            .configure(health_router)
+            .service(
+                web::scope("")
+                    .wrap(cors(&app_state))
+                    .configure(public_router),
+            )
     })
     .listen(listener)?
+    .disable_signals()
     .run();
     Ok(server)
 }
+
+// Migrations and the initial domain load wait for Mongo so the server can bind and report
+// `mongodb: down` on /readyz instead of crashing at boot.
+pub async fn migrate_when_available(app_state: Data<AppState>, retry: Duration) {
+    loop {
+        let result = match crate::migrations::run(&app_state.database).await {
+            Ok(()) => app_state.domains.load(&app_state.database).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => break,
+            Err(error) => {
+                tracing::warn!(cause = ?error.cause, "Migrations failed, retrying in {:?}", retry);
+                tokio::time::sleep(retry).await;
+            }
+        }
+    }
+    tracing::info!("Migrations complete");
+    app_state.readiness.mark_migrated();
+}
+
+// Readiness fails first so load balancers stop sending traffic, then in-flight
+// requests are drained before the workers stop.
+pub async fn shutdown_on_signal(server: ServerHandle, app_state: Data<AppState>, grace: Duration) {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+    tracing::info!("Shutdown requested, draining for {:?}", grace);
+    app_state.readiness.start_shutdown();
+    tokio::time::sleep(grace).await;
+    server.stop(true).await;
+}
@@ -0,0 +1,118 @@
+use std::{
+    future::{ready, Ready},
+    time::Instant,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error,
+};
+use futures::future::LocalBoxFuture;
+use tracing::{field::Empty, Instrument};
+use uuid::Uuid;
+
+use super::{valid_request_id, REQUEST_ID, REQUEST_ID_HEADER};
+use crate::metrics;
+
+pub struct RequestTracing;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequestTracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTracingMiddleware { service }))
+    }
+}
+
+pub struct RequestTracingMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        // Upstream proxies may already have assigned an id, keep it so logs can be joined.
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|request_id| valid_request_id(request_id))
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            method = %request.method(),
+            path = %request.path(),
+            route = Empty,
+            user_id = Empty,
+            project_id = Empty,
+            avatar_id = Empty,
+            status = Empty,
+        );
+        let method = request.method().to_string();
+        let http_request = request.request().clone();
+        let started_at = Instant::now();
+        let response = span.in_scope(|| self.service.call(request));
+
+        let request_span = span.clone();
+        Box::pin(
+            REQUEST_ID.scope(
+                request_id.clone(),
+                async move {
+                    let mut response = match response.await {
+                        Ok(response) => response.map_into_left_body(),
+                        Err(error) => ServiceResponse::new(http_request, error.error_response())
+                            .map_into_right_body(),
+                    };
+                    let request = response.request();
+                    // Unmatched paths share a label so scanners can not blow up the series count.
+                    let route = request
+                        .match_pattern()
+                        .unwrap_or_else(|| "unmatched".to_string());
+                    request_span.record("route", &route.as_str());
+                    for field in ["project_id", "avatar_id"] {
+                        if let Some(value) = request.match_info().get(field) {
+                            request_span.record(field, &value);
+                        }
+                    }
+                    let status = response.status().as_u16();
+                    let elapsed = started_at.elapsed();
+                    request_span.record("status", &status);
+                    metrics::observe_request(&method, &route, status, elapsed);
+                    tracing::info!(latency_ms = elapsed.as_millis() as u64, "request completed");
+
+                    if let Ok(value) = HeaderValue::from_str(&request_id) {
+                        response
+                            .headers_mut()
+                            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                    }
+                    Ok(response)
+                }
+                .instrument(span),
+            ),
+        )
+    }
+}
@@ -0,0 +1,49 @@
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::{config::Config, metrics::MetricsLayer};
+
+mod middleware;
+
+pub use middleware::RequestTracing;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// Only set while a request is being handled, see `RequestTracing`.
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+// Most handlers learn the project from a body, a query or an avatar rather than from the path.
+pub fn record_project_id(project_id: impl std::fmt::Display) {
+    tracing::Span::current().record("project_id", &tracing::field::display(project_id));
+}
+
+pub fn valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= 128
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+// Existing `log` records are forwarded to the subscriber by the `tracing-log` bridge.
+// The filter only applies to log output, metrics see every span whatever the log level.
+pub fn init(config: &Config) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry().with(MetricsLayer);
+    match config.log_format.as_str() {
+        "json" => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_filter(filter),
+            )
+            .init(),
+        _ => registry.with(fmt::layer().with_filter(filter)).init(),
+    }
+}
@@ -0,0 +1,155 @@
+use std::io::Cursor;
+
+use super::temporary_image;
+use crate::{
+    faces::CropRegion,
+    models::AnimationPolicy,
+    uploads::animation::{crop_frames, frame_duration_ms, resize_frames},
+};
+use image::{
+    codecs::gif::{GifDecoder, GifEncoder, Repeat},
+    AnimationDecoder, Delay, DynamicImage, Frame, ImageFormat, ImageOutputFormat, Rgba, RgbaImage,
+};
+
+fn frames(count: u8, width: u32, height: u32, delay_ms: u32) -> Vec<Frame> {
+    (0..count)
+        .map(|index| {
+            Frame::from_parts(
+                RgbaImage::from_pixel(width, height, Rgba([index * 60, 0, 255 - index * 60, 255])),
+                0,
+                0,
+                Delay::from_numer_denom_ms(delay_ms, 1),
+            )
+        })
+        .collect()
+}
+
+fn encode_gif(frames: Vec<Frame>) -> Vec<u8> {
+    let mut data = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut data);
+        encoder.set_repeat(Repeat::Infinite).unwrap();
+        encoder.encode_frames(frames).unwrap();
+    }
+    data
+}
+
+#[test]
+fn decodes_animated_gif() {
+    let image = temporary_image(&encode_gif(frames(3, 16, 8, 100)), ImageFormat::Gif);
+
+    let frames = image
+        .decode_frames(&AnimationPolicy::default())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].buffer().dimensions(), (16, 8));
+    assert!(frames.iter().all(|frame| frame_duration_ms(frame) == 100));
+}
+
+#[test]
+fn still_images_are_not_animations() {
+    let single_frame = temporary_image(&encode_gif(frames(1, 8, 8, 100)), ImageFormat::Gif);
+    assert!(single_frame
+        .decode_frames(&AnimationPolicy::default())
+        .unwrap()
+        .is_none());
+
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(RgbaImage::new(8, 8))
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .unwrap();
+    let png = temporary_image(&png, ImageFormat::Png);
+    assert!(png
+        .decode_frames(&AnimationPolicy::default())
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn rejects_animations_over_the_limits() {
+    let image = temporary_image(&encode_gif(frames(3, 8, 8, 100)), ImageFormat::Gif);
+
+    let too_many_frames = AnimationPolicy {
+        max_frames: 2,
+        ..AnimationPolicy::default()
+    };
+    let error = image.decode_frames(&too_many_frames).unwrap_err();
+    assert_eq!(error.code(), "invalid_animation");
+
+    let too_long = AnimationPolicy {
+        max_duration_ms: 250,
+        ..AnimationPolicy::default()
+    };
+    let error = image.decode_frames(&too_long).unwrap_err();
+    assert_eq!(error.code(), "invalid_animation");
+
+    let exact = AnimationPolicy {
+        max_frames: 3,
+        max_duration_ms: 300,
+        ..AnimationPolicy::default()
+    };
+    assert_eq!(image.decode_frames(&exact).unwrap().unwrap().len(), 3);
+}
+
+#[test]
+fn sanitized_animations_are_gif() {
+    let image = temporary_image(&encode_gif(frames(3, 16, 8, 100)), ImageFormat::Gif);
+    let metadata = image.read_metadata().unwrap();
+    let frames = image
+        .decode_frames(&AnimationPolicy::default())
+        .unwrap()
+        .unwrap();
+
+    let (sanitized, animation) = image.sanitize_frames(frames, metadata).unwrap();
+
+    assert_eq!(sanitized.image.format, ImageFormat::Gif);
+    assert_eq!((sanitized.width, sanitized.height), (16, 8));
+    assert_eq!(animation.frame_count, 3);
+    assert_eq!(animation.duration_ms, 300);
+
+    let data = std::fs::read(&sanitized.image.path).unwrap();
+    assert_eq!(data.len() as u64, sanitized.image.size);
+    let decoded = GifDecoder::new(Cursor::new(data))
+        .unwrap()
+        .into_frames()
+        .collect_frames()
+        .unwrap();
+    assert_eq!(decoded.len(), 3);
+}
+
+#[test]
+fn crops_every_frame() {
+    let region = CropRegion {
+        left: 4,
+        top: 2,
+        width: 6,
+        height: 5,
+    };
+
+    let cropped = crop_frames(frames(3, 16, 8, 40), region);
+
+    assert_eq!(cropped.len(), 3);
+    for frame in &cropped {
+        assert_eq!(frame.buffer().dimensions(), (6, 5));
+        assert_eq!(frame_duration_ms(frame), 40);
+    }
+    assert_eq!(
+        cropped[1].buffer().get_pixel(0, 0),
+        &Rgba([60, 0, 195, 255])
+    );
+}
+
+#[test]
+fn resizes_every_frame_within_bounds() {
+    let resized = resize_frames(frames(2, 64, 32, 40), 16);
+    assert_eq!(resized.len(), 2);
+    for frame in &resized {
+        assert_eq!(frame.buffer().dimensions(), (16, 8));
+        assert_eq!(frame_duration_ms(frame), 40);
+    }
+
+    let untouched = resize_frames(frames(2, 12, 10, 40), 16);
+    assert_eq!(untouched[0].buffer().dimensions(), (12, 10));
+}
@@ -0,0 +1,316 @@
+use std::{collections::HashMap, io::Cursor};
+
+use futures::future::join_all;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use super::{avatar, bearer_token, member_project, spawn_app, storage::storage, user, TestApp};
+use crate::{
+    models::{Avatar, ProjectSettings},
+    repositories::{AvatarRepository, AvatarVersionRepository, UserRepository},
+};
+
+#[derive(Deserialize)]
+struct AvatarPage {
+    avatars: Vec<Avatar>,
+    page: u64,
+    limit: u64,
+    total: u64,
+}
+
+async fn outsider(app: &TestApp) -> String {
+    let outsider = user(None);
+    let token = bearer_token(outsider.id);
+    UserRepository::new(app.database.clone())
+        .create(outsider)
+        .await
+        .unwrap();
+    token
+}
+
+async fn stored_avatar(app: &TestApp, project_id: ObjectId) -> Avatar {
+    let avatar = avatar(project_id);
+    storage().put(&project_id.to_string(), &avatar.key(), png());
+    AvatarRepository::new(app.database.clone())
+        .create(avatar.clone())
+        .await
+        .unwrap();
+    avatar
+}
+
+async fn upload(
+    app: &TestApp,
+    token: &str,
+    project_id: ObjectId,
+    external_id: &str,
+    data: Vec<u8>,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(&format!("{}/api/avatar", app.address))
+        .bearer_auth(token)
+        .json(&HashMap::from([
+            ("name", "avatar".to_string()),
+            ("project", project_id.to_string()),
+            ("external_id", external_id.to_string()),
+            (
+                "image",
+                format!("data:image/png;base64,{}", base64::encode(data)),
+            ),
+        ]))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+fn png() -> Vec<u8> {
+    colored_png(Rgb([30, 90, 200]))
+}
+
+fn colored_png(color: Rgb<u8>) -> Vec<u8> {
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, color))
+        .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+        .unwrap();
+    data
+}
+
+#[tokio::test]
+async fn avatars_are_listed_by_page() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let mut avatars = Vec::new();
+    for _ in 0..5 {
+        avatars.push(stored_avatar(&app, project_id).await);
+    }
+
+    let response = reqwest::Client::new()
+        .get(&format!(
+            "{}/api/avatar?project={}&page=2&limit=2",
+            app.address, project_id
+        ))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.json::<AvatarPage>().await.unwrap();
+    assert_eq!((page.page, page.limit, page.total), (2, 2, 5));
+    assert_eq!(
+        page.avatars
+            .iter()
+            .map(|avatar| avatar._id)
+            .collect::<Vec<ObjectId>>(),
+        vec![avatars[2]._id, avatars[3]._id]
+    );
+}
+
+#[tokio::test]
+async fn avatars_are_read_and_renamed() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let avatar = stored_avatar(&app, project_id).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(&format!("{}/api/avatar/{}", app.address, avatar._id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.json::<Avatar>().await.unwrap().name, "avatar");
+
+    let response = client
+        .patch(&format!("{}/api/avatar/{}", app.address, avatar._id))
+        .bearer_auth(&token)
+        .json(&HashMap::from([("name", "renamed")]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.json::<Avatar>().await.unwrap().name, "renamed");
+    let renamed = AvatarRepository::new(app.database.clone())
+        .get(avatar._id)
+        .await
+        .unwrap();
+    assert_eq!(renamed.name, "renamed");
+}
+
+#[tokio::test]
+async fn replaced_avatars_point_to_the_new_image() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let avatar = stored_avatar(&app, project_id).await;
+
+    let response = reqwest::Client::new()
+        .put(&format!("{}/api/avatar/{}", app.address, avatar._id))
+        .bearer_auth(&token)
+        .json(&HashMap::from([(
+            "image",
+            format!("data:image/png;base64,{}", base64::encode(png())),
+        )]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    let replaced = AvatarRepository::new(app.database.clone())
+        .get(avatar._id)
+        .await
+        .unwrap();
+    assert_ne!(replaced.key(), avatar.key());
+    assert_eq!((replaced.width, replaced.height), (32, 32));
+    assert!(storage()
+        .get(&project_id.to_string(), &replaced.key())
+        .is_some());
+}
+
+#[tokio::test]
+async fn deleted_avatars_and_their_objects_are_gone() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let avatar = stored_avatar(&app, project_id).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .delete(&format!("{}/api/avatar/{}", app.address, avatar._id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 204);
+    assert!(storage()
+        .get(&project_id.to_string(), &avatar.key())
+        .is_none());
+
+    let response = client
+        .get(&format!("{}/api/avatar/{}", app.address, avatar._id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn avatars_of_other_projects_are_forbidden() {
+    let app = spawn_app().await;
+    let (_, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let avatar = stored_avatar(&app, project_id).await;
+    let token = outsider(&app).await;
+    let client = reqwest::Client::new();
+    let avatar_url = format!("{}/api/avatar/{}", app.address, avatar._id);
+
+    let requests = vec![
+        client.get(&format!(
+            "{}/api/avatar?project={}",
+            app.address, project_id
+        )),
+        client.get(&avatar_url),
+        client
+            .patch(&avatar_url)
+            .json(&HashMap::from([("name", "renamed")])),
+        client.delete(&avatar_url),
+    ];
+    for request in requests {
+        let response = request
+            .bearer_auth(&token)
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(response.status().as_u16(), 403);
+    }
+    assert!(storage()
+        .get(&project_id.to_string(), &avatar.key())
+        .is_some());
+}
+
+#[tokio::test]
+async fn unknown_avatars_are_not_found() {
+    let app = spawn_app().await;
+    let (token, _) = member_project(&app, ProjectSettings::default()).await;
+
+    let response = reqwest::Client::new()
+        .get(&format!("{}/api/avatar/{}", app.address, ObjectId::new()))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn uploads_of_a_known_external_id_replace_its_avatar() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+
+    let first = upload(&app, &token, project_id, "user-42", png()).await;
+    assert_eq!(first.status().as_u16(), 200);
+    let first = first.json::<Avatar>().await.unwrap();
+    let second = upload(
+        &app,
+        &token,
+        project_id,
+        "user-42",
+        colored_png(Rgb([0, 0, 0])),
+    )
+    .await;
+    assert_eq!(second.status().as_u16(), 200);
+    let second = second.json::<Avatar>().await.unwrap();
+
+    assert_eq!(second._id, first._id);
+    assert_ne!(second.key(), first.key());
+    let versions = AvatarVersionRepository::new(app.database.clone())
+        .get_avatar_versions(first._id)
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0].storage_key, first.key());
+}
+
+#[tokio::test]
+async fn concurrent_uploads_of_an_external_id_share_one_avatar() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [0, 0, 0]];
+
+    let responses = join_all(colors.iter().map(|color| {
+        upload(
+            &app,
+            &token,
+            project_id,
+            "user-42",
+            colored_png(Rgb(*color)),
+        )
+    }))
+    .await;
+    assert!(responses
+        .iter()
+        .all(|response| response.status().as_u16() == 200));
+
+    let avatar = AvatarRepository::new(app.database.clone())
+        .get_by_external_id(project_id, "user-42")
+        .await
+        .unwrap()
+        .unwrap();
+    let (avatars, total) = AvatarRepository::new(app.database.clone())
+        .get_project_avatars(project_id, 0, 10)
+        .await
+        .unwrap();
+    assert_eq!((avatars.len(), total), (1, 1));
+    let versions = AvatarVersionRepository::new(app.database.clone())
+        .get_avatar_versions(avatar._id)
+        .await
+        .unwrap();
+    assert_eq!(versions.len(), colors.len() - 1);
+
+    // Every stored object belongs to the avatar or one of its versions.
+    let mut referenced_keys = std::iter::once(avatar.key())
+        .chain(avatar.derived_keys())
+        .chain(versions.iter().flat_map(|version| {
+            std::iter::once(version.storage_key.clone()).chain(version.derived_keys())
+        }))
+        .collect::<Vec<String>>();
+    referenced_keys.sort();
+    assert_eq!(storage().keys(&project_id.to_string()), referenced_keys);
+}
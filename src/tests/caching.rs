@@ -0,0 +1,52 @@
+use actix_web::{
+    http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH},
+    test::TestRequest,
+};
+use mongodb::bson::DateTime;
+
+use crate::caching::Validators;
+
+fn validators() -> Validators {
+    Validators::new("abc123", DateTime::from_millis(1_650_000_000_500))
+}
+
+#[test]
+fn matching_etag_is_not_modified() {
+    let request = TestRequest::default()
+        .insert_header((IF_NONE_MATCH, "\"other\", \"abc123\""))
+        .to_http_request();
+    assert!(validators().not_modified(&request));
+
+    let request = TestRequest::default()
+        .insert_header((IF_NONE_MATCH, "W/\"abc123\""))
+        .to_http_request();
+    assert!(validators().not_modified(&request));
+}
+
+#[test]
+fn changed_etag_wins_over_modified_since() {
+    let request = TestRequest::default()
+        .insert_header((IF_NONE_MATCH, "\"other\""))
+        .insert_header((IF_MODIFIED_SINCE, "Fri, 15 Apr 2022 05:20:00 GMT"))
+        .to_http_request();
+    assert!(!validators().not_modified(&request));
+}
+
+#[test]
+fn modified_since_uses_whole_seconds() {
+    let request = TestRequest::default()
+        .insert_header((IF_MODIFIED_SINCE, "Fri, 15 Apr 2022 05:20:00 GMT"))
+        .to_http_request();
+    assert!(validators().not_modified(&request));
+
+    let request = TestRequest::default()
+        .insert_header((IF_MODIFIED_SINCE, "Fri, 15 Apr 2022 05:19:59 GMT"))
+        .to_http_request();
+    assert!(!validators().not_modified(&request));
+}
+
+#[test]
+fn no_validators_means_full_response() {
+    let request = TestRequest::default().to_http_request();
+    assert!(!validators().not_modified(&request));
+}
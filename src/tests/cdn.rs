@@ -0,0 +1,107 @@
+use std::{
+    net::TcpListener,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::{http::header::AUTHORIZATION, web, App, HttpRequest, HttpResponse, HttpServer};
+use serde::Deserialize;
+
+use crate::cdn::{public_url, NoopPurgeHook, Purge, PurgeHook, WebhookPurgeHook};
+
+#[derive(Deserialize)]
+struct ReceivedPurge {
+    urls: Vec<String>,
+    surrogate_keys: Vec<String>,
+}
+
+type Received = Arc<Mutex<Vec<(Option<String>, ReceivedPurge)>>>;
+
+// Starts a CDN webhook stub answering every purge with the given status.
+fn spawn_webhook(status: u16) -> (String, Received) {
+    let received: Received = Arc::new(Mutex::new(Vec::new()));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!(
+        "http://127.0.0.1:{}/purge",
+        listener.local_addr().unwrap().port()
+    );
+    let state = web::Data::new(received.clone());
+    let server = HttpServer::new(move || {
+        App::new().app_data(state.clone()).route(
+            "/purge",
+            web::post().to(
+                move |request: HttpRequest,
+                      purge: web::Json<ReceivedPurge>,
+                      received: web::Data<Received>| async move {
+                    let authorization = request
+                        .headers()
+                        .get(AUTHORIZATION)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string);
+                    received
+                        .lock()
+                        .unwrap()
+                        .push((authorization, purge.into_inner()));
+                    HttpResponse::build(actix_web::http::StatusCode::from_u16(status).unwrap())
+                        .finish()
+                },
+            ),
+        )
+    })
+    .listen(listener)
+    .unwrap()
+    .workers(1)
+    .run();
+    tokio::spawn(server);
+    (address, received)
+}
+
+fn purge() -> Purge {
+    Purge {
+        urls: vec!["https://cdn.example.com/media/1".to_string()],
+        surrogate_keys: vec!["avatar-1".to_string()],
+    }
+}
+
+#[test]
+fn url_template_placeholders_are_replaced() {
+    assert_eq!(
+        public_url(
+            "https://cdn.example.com/{bucket}/{key}?region={region}",
+            "project",
+            "eu-west-3",
+            "avatar.png"
+        ),
+        "https://cdn.example.com/project/avatar.png?region=eu-west-3"
+    );
+}
+
+#[tokio::test]
+async fn noop_purge_hook_succeeds() {
+    assert!(NoopPurgeHook.purge(&purge()).await.is_ok());
+}
+
+#[tokio::test]
+async fn webhook_purge_hook_posts_urls_and_surrogate_keys() {
+    let (address, received) = spawn_webhook(200);
+    let hook = WebhookPurgeHook::new(&address, Some("token".to_string())).unwrap();
+
+    hook.purge(&purge()).await.unwrap();
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    let (authorization, purge) = &received[0];
+    assert_eq!(authorization.as_deref(), Some("Bearer token"));
+    assert_eq!(purge.urls, vec!["https://cdn.example.com/media/1"]);
+    assert_eq!(purge.surrogate_keys, vec!["avatar-1"]);
+}
+
+#[tokio::test]
+async fn webhook_purge_hook_reports_failures() {
+    let (address, received) = spawn_webhook(503);
+    let hook = WebhookPurgeHook::new(&address, None).unwrap();
+
+    let error = hook.purge(&purge()).await.unwrap_err();
+
+    assert_eq!(error.code(), "purge_error");
+    assert_eq!(received.lock().unwrap()[0].0, None);
+}
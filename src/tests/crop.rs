@@ -0,0 +1,250 @@
+use actix_web::ResponseError;
+use async_trait::async_trait;
+use image::{DynamicImage, GrayImage, ImageFormat, Luma};
+
+use crate::{
+    errors::AppError,
+    faces::{
+        crop_image, crop_region, detect_crop_region, face_region, rekognition_image, select_face,
+        CropRegion, FaceBox, FaceDetector, LocalFaceDetector, MAX_REKOGNITION_SIDE,
+    },
+    models::{CropPolicy, FaceSelection, NoFaceBehavior},
+};
+
+struct StubDetector(Result<Vec<FaceBox>, String>);
+
+#[async_trait]
+impl FaceDetector for StubDetector {
+    async fn detect(&self, _image: &DynamicImage) -> Result<Vec<FaceBox>, AppError> {
+        self.0.clone().map_err(AppError::face_detection_error)
+    }
+}
+
+// Flat on one side and noisy on the other, so the smart crop has a single best offset.
+fn half_noisy(width: u32, height: u32, noisy_end: bool) -> DynamicImage {
+    DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+        let position = if width > height { x } else { y };
+        let noisy = (position >= width.max(height) / 2) == noisy_end;
+        match noisy {
+            true => Luma([((x * 37 + y * 91) % 256) as u8]),
+            false => Luma([128]),
+        }
+    }))
+}
+
+fn face(left: u32, top: u32, width: u32, height: u32, confidence: f32) -> FaceBox {
+    FaceBox {
+        left,
+        top,
+        width,
+        height,
+        confidence,
+    }
+}
+
+#[test]
+fn relative_bounding_box_is_clamped_to_image() {
+    let face = FaceBox::from_relative(-0.1, 0.8, 0.5, 0.4, 0.99, 200, 100).unwrap();
+
+    assert_eq!(face.left, 0);
+    assert_eq!(face.top, 80);
+    assert_eq!(face.width, 80);
+    assert_eq!(face.height, 20);
+}
+
+#[test]
+fn bounding_box_outside_image_is_dropped() {
+    assert!(FaceBox::from_relative(1.2, 0.2, 0.3, 0.3, 0.99, 200, 100).is_none());
+    assert!(FaceBox::from_relative(0.2, -0.6, 0.3, 0.3, 0.99, 200, 100).is_none());
+    assert!(FaceBox::from_relative(f32::NAN, 0.2, 0.3, 0.3, 0.99, 200, 100).is_none());
+}
+
+#[test]
+fn selects_largest_or_most_confident_face() {
+    let faces = vec![face(0, 0, 10, 10, 0.99), face(50, 50, 40, 40, 0.6)];
+
+    assert_eq!(
+        select_face(&faces, FaceSelection::Largest).unwrap().left,
+        50
+    );
+    assert_eq!(
+        select_face(&faces, FaceSelection::MostConfident)
+            .unwrap()
+            .left,
+        0
+    );
+    assert!(select_face(&[], FaceSelection::Largest).is_none());
+}
+
+#[test]
+fn square_region_is_padded_around_face() {
+    let policy = CropPolicy {
+        padding: 0.5,
+        ..CropPolicy::default()
+    };
+
+    let region = face_region(400, 300, &face(150, 100, 40, 60, 0.9), &policy);
+
+    assert_eq!(
+        region,
+        CropRegion {
+            left: 110,
+            top: 70,
+            width: 120,
+            height: 120,
+        }
+    );
+}
+
+#[test]
+fn region_is_shifted_inside_image_bounds() {
+    let policy = CropPolicy {
+        padding: 1.0,
+        ..CropPolicy::default()
+    };
+
+    let region = face_region(400, 300, &face(370, 0, 30, 30, 0.9), &policy);
+
+    assert_eq!(
+        region,
+        CropRegion {
+            left: 310,
+            top: 0,
+            width: 90,
+            height: 90,
+        }
+    );
+}
+
+#[test]
+fn region_never_exceeds_image() {
+    let policy = CropPolicy {
+        padding: 2.0,
+        ..CropPolicy::default()
+    };
+
+    let region = face_region(400, 300, &face(100, 50, 200, 200, 0.9), &policy);
+
+    assert_eq!(region.width, 300);
+    assert_eq!(region.height, 300);
+    assert!(region.left + region.width <= 400);
+    assert_eq!(region.top, 0);
+}
+
+#[test]
+fn rectangular_region_keeps_face_ratio() {
+    let policy = CropPolicy {
+        padding: 0.25,
+        square: false,
+        ..CropPolicy::default()
+    };
+
+    let region = face_region(400, 300, &face(100, 100, 40, 80, 0.9), &policy);
+
+    assert_eq!(region.width, 60);
+    assert_eq!(region.height, 120);
+}
+
+#[test]
+fn no_face_behaviors() {
+    let image = DynamicImage::new_rgb8(300, 200);
+    let policy = |no_face| CropPolicy {
+        no_face,
+        ..CropPolicy::default()
+    };
+
+    assert!(crop_image(image.clone(), &[], &policy(NoFaceBehavior::Reject)).is_err());
+
+    let kept = crop_image(image.clone(), &[], &policy(NoFaceBehavior::Keep)).unwrap();
+    assert_eq!((kept.width(), kept.height()), (300, 200));
+
+    let centered = crop_image(image.clone(), &[], &policy(NoFaceBehavior::Center)).unwrap();
+    assert_eq!((centered.width(), centered.height()), (200, 200));
+
+    let smart = crop_image(image, &[], &policy(NoFaceBehavior::Smart)).unwrap();
+    assert_eq!((smart.width(), smart.height()), (200, 200));
+}
+
+#[test]
+fn smart_crop_follows_the_detail() {
+    let policy = CropPolicy {
+        no_face: NoFaceBehavior::Smart,
+        ..CropPolicy::default()
+    };
+
+    let region = crop_region(&half_noisy(300, 100, true), &[], &policy)
+        .unwrap()
+        .unwrap();
+    assert_eq!((region.top, region.width, region.height), (0, 100, 100));
+    assert!(region.left >= 150);
+
+    let region = crop_region(&half_noisy(100, 300, false), &[], &policy)
+        .unwrap()
+        .unwrap();
+    assert_eq!((region.left, region.width, region.height), (0, 100, 100));
+    assert!(region.top + region.height <= 150);
+}
+
+#[tokio::test]
+async fn detected_faces_drive_the_crop() {
+    let detector = StubDetector(Ok(vec![face(150, 100, 40, 60, 0.9)]));
+    let policy = CropPolicy {
+        padding: 0.5,
+        ..CropPolicy::default()
+    };
+
+    let region = detect_crop_region(&detector, &DynamicImage::new_rgb8(400, 300), &policy)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        region,
+        Some(CropRegion {
+            left: 110,
+            top: 70,
+            width: 120,
+            height: 120,
+        })
+    );
+}
+
+#[tokio::test]
+async fn detector_failures_are_not_treated_as_missing_faces() {
+    let detector = StubDetector(Err("Rekognition is unavailable.".to_string()));
+    let policy = CropPolicy {
+        no_face: NoFaceBehavior::Keep,
+        ..CropPolicy::default()
+    };
+
+    let error = detect_crop_region(&detector, &DynamicImage::new_rgb8(400, 300), &policy)
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.problem_details().code, "face_detection_error");
+    assert!(error.status_code().is_server_error());
+}
+
+#[test]
+fn local_detector_requires_a_readable_model() {
+    assert!(LocalFaceDetector::new("./tmp/missing-face-model.bin").is_err());
+
+    let model_path = format!("./tmp/{}", mongodb::bson::oid::ObjectId::new());
+    std::fs::write(&model_path, b"not a seeta model").unwrap();
+    assert!(LocalFaceDetector::new(&model_path).is_err());
+    std::fs::remove_file(model_path).unwrap();
+}
+
+#[test]
+fn rekognition_images_are_bounded_jpegs() {
+    let encoded = rekognition_image(&DynamicImage::new_rgba8(3000, 1500)).unwrap();
+    let decoded = image::load_from_memory(&encoded).unwrap();
+    assert_eq!(image::guess_format(&encoded).unwrap(), ImageFormat::Jpeg);
+    assert_eq!(
+        (decoded.width(), decoded.height()),
+        (MAX_REKOGNITION_SIDE, MAX_REKOGNITION_SIDE / 2)
+    );
+
+    let encoded = rekognition_image(&DynamicImage::new_rgb8(200, 100)).unwrap();
+    let decoded = image::load_from_memory(&encoded).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (200, 100));
+}
@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+
+use super::spawn_app;
+use crate::{
+    domains::{
+        challenge_name, normalize_host, parse_origins, url_host, valid_host, valid_origin,
+        verify_domain, DomainRegistry, DomainVerifier,
+    },
+    models::{DomainPolicy, Project, ProjectSettings},
+    repositories::ProjectRepository,
+};
+
+struct StaticVerifier(Vec<(&'static str, &'static str)>);
+
+#[async_trait]
+impl DomainVerifier for StaticVerifier {
+    async fn txt_records(&self, name: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(record_name, _)| *record_name == name)
+            .map(|(_, value)| value.to_string())
+            .collect()
+    }
+}
+
+#[test]
+fn origins_are_parsed_and_validated() {
+    assert_eq!(
+        parse_origins(" https://app.example.com, ,http://localhost:3000"),
+        vec!["https://app.example.com", "http://localhost:3000"]
+    );
+    assert!(valid_origin("https://App.Example.com/"));
+    assert!(!valid_origin("https://app.example.com/path"));
+    assert!(!valid_origin("ftp://app.example.com"));
+    assert!(!valid_origin("*"));
+}
+
+#[test]
+fn hosts_are_normalized() {
+    assert_eq!(
+        normalize_host("Avatars.Example.com:8080"),
+        "avatars.example.com"
+    );
+    assert!(valid_host("avatars.example.com"));
+    assert!(!valid_host("localhost"));
+    assert!(!valid_host("-bad.example.com"));
+}
+
+#[test]
+fn registry_scopes_origins_to_projects() {
+    let registry = DomainRegistry::new(vec!["https://dashboard.example.com".to_string()]);
+    let project = ObjectId::new();
+    let other_project = ObjectId::new();
+    registry.update(
+        project,
+        &DomainPolicy {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            custom_domain: Some("avatars.example.com".to_string()),
+            custom_domain_token: Some("token".to_string()),
+            custom_domain_verified: true,
+        },
+    );
+
+    assert!(registry.origin_allowed("https://Dashboard.example.com/"));
+    assert!(!registry.origin_allowed("https://app.example.com"));
+    assert!(!registry.origin_allowed("https://evil.example.com"));
+    assert!(registry.project_origin_allowed(project, "https://app.example.com"));
+    assert!(!registry.project_origin_allowed(other_project, "https://app.example.com"));
+    assert!(registry.project_origin_allowed(other_project, "https://dashboard.example.com"));
+    assert_eq!(
+        registry.project_for_host("avatars.example.com:443"),
+        Some(project)
+    );
+
+    registry.update(project, &DomainPolicy::default());
+    assert_eq!(registry.project_for_host("avatars.example.com"), None);
+    assert!(!registry.project_origin_allowed(project, "https://app.example.com"));
+}
+
+#[test]
+fn url_host_is_extracted() {
+    assert_eq!(
+        url_host("https://Avatars.Example.com:8443/media?x=1").as_deref(),
+        Some("avatars.example.com")
+    );
+    assert_eq!(
+        url_host("http://localhost:8080").as_deref(),
+        Some("localhost")
+    );
+    assert_eq!(url_host("avatars.example.com"), None);
+}
+
+#[test]
+fn unverified_custom_domains_are_not_routed() {
+    let registry = DomainRegistry::new(Vec::new());
+    let project = ObjectId::new();
+    registry.update(
+        project,
+        &DomainPolicy {
+            custom_domain: Some("avatars.example.com".to_string()),
+            custom_domain_token: Some("token".to_string()),
+            ..DomainPolicy::default()
+        },
+    );
+
+    assert_eq!(registry.project_for_host("avatars.example.com"), None);
+}
+
+#[tokio::test]
+async fn custom_domains_are_verified_with_a_txt_record() {
+    assert_eq!(
+        challenge_name("avatars.example.com"),
+        "_stampa-challenge.avatars.example.com"
+    );
+    let verifier = StaticVerifier(vec![
+        (
+            "_stampa-challenge.avatars.example.com",
+            "stampa-verification=token",
+        ),
+        (
+            "_stampa-challenge.other.example.com",
+            "stampa-verification=other",
+        ),
+    ]);
+
+    assert!(verify_domain(&verifier, "avatars.example.com", "token").await);
+    assert!(!verify_domain(&verifier, "avatars.example.com", "other").await);
+    assert!(!verify_domain(&verifier, "other.example.com", "token").await);
+    assert!(!verify_domain(&verifier, "missing.example.com", "token").await);
+}
+
+#[tokio::test]
+async fn reloading_forgets_policies_removed_elsewhere() {
+    let app = spawn_app().await;
+    let project_id = ObjectId::new();
+    let custom_domain = format!("{}.example.com", ObjectId::new());
+    let mut settings = ProjectSettings::default();
+    settings.domain_policy = DomainPolicy {
+        allowed_origins: vec!["https://app.example.com".to_string()],
+        custom_domain: Some(custom_domain.clone()),
+        custom_domain_token: Some("token".to_string()),
+        custom_domain_verified: true,
+    };
+    let repository = ProjectRepository::new(app.database.clone());
+    repository
+        .create(Project {
+            id: project_id,
+            author: ObjectId::new(),
+            title: "Domains".to_string(),
+            api_key: "key".to_string(),
+            api_secret: "secret".to_string(),
+            signing_key: "signing-key".to_string(),
+            region: "eu-west-3".to_string(),
+            members: Vec::new(),
+            invitations: Vec::new(),
+            settings,
+        })
+        .await
+        .unwrap();
+    let registry = DomainRegistry::new(Vec::new());
+
+    registry.load(&app.database).await.unwrap();
+    assert_eq!(registry.project_for_host(&custom_domain), Some(project_id));
+
+    // Another instance clears the policy, this one only sees it through a reload.
+    repository
+        .update_settings(project_id, &ProjectSettings::default())
+        .await
+        .unwrap();
+    registry.load(&app.database).await.unwrap();
+    assert_eq!(registry.project_for_host(&custom_domain), None);
+    assert!(!registry.project_origin_allowed(project_id, "https://app.example.com"));
+}
@@ -0,0 +1,110 @@
+use actix_web::{http::StatusCode, ResponseError};
+use validator::Validate;
+
+use crate::errors::{AppError, FieldError};
+
+#[derive(Validate)]
+struct Payload {
+    #[validate(length(min = 3))]
+    username: String,
+    #[validate]
+    policy: Policy,
+}
+
+#[derive(Validate)]
+struct Policy {
+    #[validate(range(max = 10))]
+    limit: u32,
+}
+
+#[test]
+fn errors_have_codes_and_statuses() {
+    let cases = vec![
+        (
+            AppError::not_found_error("id"),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        ),
+        (
+            AppError::s3_error("boom"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "storage_error",
+        ),
+        (
+            AppError::fs_error("boom"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "filesystem_error",
+        ),
+        (
+            AppError::avatat_generation_error("boom"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "image_processing_error",
+        ),
+        (
+            AppError::user_exist_error("bob"),
+            StatusCode::CONFLICT,
+            "user_exists",
+        ),
+        (
+            AppError::unvalid_form_error("bad"),
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+        ),
+        (
+            AppError::not_in_project_error("bob"),
+            StatusCode::FORBIDDEN,
+            "not_in_project",
+        ),
+    ];
+
+    for (error, status, code) in cases {
+        assert_eq!(error.status_code(), status);
+        assert_eq!(error.problem_details().code, code);
+    }
+}
+
+#[test]
+fn internal_causes_are_not_exposed() {
+    let problem = AppError::db_error("connection refused to 10.0.0.3").problem_details();
+
+    assert_eq!(problem.status, 500);
+    assert_eq!(problem.title, "Internal Server Error");
+    assert_eq!(problem.detail, "An internal error occurred");
+}
+
+#[test]
+fn validation_errors_list_fields() {
+    let payload = Payload {
+        username: "ab".to_string(),
+        policy: Policy { limit: 11 },
+    };
+
+    let problem = AppError::validation_error(payload.validate().unwrap_err()).problem_details();
+
+    assert_eq!(problem.status, 400);
+    assert_eq!(
+        problem.errors,
+        vec![
+            FieldError {
+                field: "policy.limit".to_string(),
+                code: "range".to_string(),
+                message: None,
+            },
+            FieldError {
+                field: "username".to_string(),
+                code: "length".to_string(),
+                message: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn responses_are_problem_json() {
+    let response = AppError::not_found_error("id").error_response();
+
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/problem+json"
+    );
+}
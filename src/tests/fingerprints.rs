@@ -0,0 +1,142 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage, Luma};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use super::{avatar, member_project, spawn_app, TestApp};
+use crate::{
+    fingerprints::{
+        average_hash, cluster, difference_hash, from_hex, hamming_distance, near_any,
+        perceptual_hash, to_hex,
+    },
+    models::{Avatar, Fingerprint, ProjectSettings},
+    repositories::AvatarRepository,
+};
+
+#[derive(Deserialize)]
+struct DuplicateClusters {
+    clusters: Vec<Vec<Avatar>>,
+    next: Option<String>,
+}
+
+// 8x8 grid of pseudo random gray blocks.
+fn blocks(size: u32) -> DynamicImage {
+    let mut state: u32 = 42;
+    let values: Vec<u8> = (0..64)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    let block_size = size / 8;
+    DynamicImage::ImageLuma8(GrayImage::from_fn(size, size, |x, y| {
+        Luma([values[((y / block_size) * 8 + x / block_size) as usize]])
+    }))
+}
+
+#[test]
+fn hex_round_trip() {
+    assert_eq!(to_hex(0xdead_beef), "00000000deadbeef");
+    assert_eq!(from_hex("00000000deadbeef"), Some(0xdead_beef));
+    assert_eq!(from_hex("not a hash"), None);
+}
+
+#[test]
+fn resized_image_keeps_its_hashes() {
+    let image = blocks(256);
+    let resized = image.resize_exact(128, 128, FilterType::Triangle);
+
+    assert!(hamming_distance(average_hash(&image), average_hash(&resized)) <= 4);
+    assert!(hamming_distance(difference_hash(&image), difference_hash(&resized)) <= 4);
+    assert!(hamming_distance(perceptual_hash(&image), perceptual_hash(&resized)) <= 4);
+}
+
+#[test]
+fn different_images_are_far_apart() {
+    let image = blocks(256);
+    let flipped = image.fliph();
+
+    assert!(hamming_distance(perceptual_hash(&image), perceptual_hash(&flipped)) > 10);
+}
+
+#[test]
+fn clusters_near_duplicates() {
+    let hashes = vec![
+        0b0000,
+        0b0001,
+        0xffff_0000,
+        0b0011,
+        0xffff_0001,
+        0xf0f0_f0f0,
+    ];
+
+    let clusters = cluster(&hashes, 1);
+
+    assert_eq!(clusters, vec![vec![0, 1, 3], vec![2, 4]]);
+}
+
+#[test]
+fn hashes_near_any_target_are_selected() {
+    let hashes = vec![0b0000, 0xffff_0000, 0b0111, 0xffff_0001];
+
+    assert_eq!(near_any(&hashes, &[0b0001, 0xffff_0000], 1), vec![0, 1, 3]);
+    assert!(near_any(&hashes, &[], 1).is_empty());
+}
+
+async fn fingerprinted_avatar(app: &TestApp, project_id: ObjectId, phash: &str) -> ObjectId {
+    let mut avatar = avatar(project_id);
+    avatar.fingerprint = Some(Fingerprint {
+        ahash: phash.to_string(),
+        dhash: phash.to_string(),
+        phash: phash.to_string(),
+    });
+    AvatarRepository::new(app.database.clone())
+        .create(avatar)
+        .await
+        .unwrap()
+}
+
+async fn duplicates(
+    app: &TestApp,
+    token: &str,
+    project_id: ObjectId,
+    after: Option<&str>,
+) -> DuplicateClusters {
+    let mut url = format!(
+        "{}/api/avatar/duplicates?project={}&threshold=1&limit=2",
+        app.address, project_id
+    );
+    if let Some(after) = after {
+        url.push_str(&format!("&after={}", after));
+    }
+    let response = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    response.json::<DuplicateClusters>().await.unwrap()
+}
+
+#[tokio::test]
+async fn duplicates_are_found_across_scan_windows() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let original = fingerprinted_avatar(&app, project_id, "00000000000000ff").await;
+    fingerprinted_avatar(&app, project_id, "ffffffff00000000").await;
+    fingerprinted_avatar(&app, project_id, "not a hash").await;
+    let duplicate = fingerprinted_avatar(&app, project_id, "00000000000000fe").await;
+
+    let first_window = duplicates(&app, &token, project_id, None).await;
+    assert!(first_window.clusters.is_empty());
+    let second_window = duplicates(&app, &token, project_id, first_window.next.as_deref()).await;
+
+    assert_eq!(second_window.clusters.len(), 1);
+    assert_eq!(
+        second_window.clusters[0]
+            .iter()
+            .map(|avatar| avatar._id)
+            .collect::<Vec<ObjectId>>(),
+        vec![original, duplicate]
+    );
+}
@@ -0,0 +1,99 @@
+use mongodb::bson::oid::ObjectId;
+
+use super::{avatar, project, spawn_app};
+use crate::{
+    gravatar::{
+        avatar_rating, email_hash, normalize_hash, parse_fallback, parse_size, Fallback, Rating,
+    },
+    models::{Moderation, ModerationLabel, ProjectSettings, Visibility},
+    repositories::{AvatarRepository, ProjectRepository},
+};
+
+#[test]
+fn email_is_trimmed_and_lowercased_before_hashing() {
+    let email_hash = email_hash(" MyEmailAddress@example.com ").unwrap();
+    assert_eq!(email_hash.md5, "0bc83cb571cd1c50ba6f3e8a78ef1346");
+    assert_eq!(email_hash.sha256.len(), 64);
+}
+
+#[test]
+fn external_ids_that_are_not_emails_are_not_hashed() {
+    assert!(email_hash("user-42").is_none());
+}
+
+#[test]
+fn hash_accepts_extension_and_uppercase() {
+    assert_eq!(
+        normalize_hash("0BC83CB571CD1C50BA6F3E8A78EF1346.jpg").as_deref(),
+        Some("0bc83cb571cd1c50ba6f3e8a78ef1346")
+    );
+    assert!(normalize_hash("not-a-hash").is_none());
+}
+
+#[test]
+fn size_is_snapped_to_a_bucket() {
+    assert_eq!(parse_size(None), 80);
+    assert_eq!(parse_size(Some(0)), 16);
+    assert_eq!(parse_size(Some(80)), 80);
+    assert_eq!(parse_size(Some(81)), 96);
+    assert_eq!(parse_size(Some(10000)), 2048);
+}
+
+#[test]
+fn fallbacks_are_parsed() {
+    assert_eq!(parse_fallback(Some("404"), None, None), Fallback::NotFound);
+    assert_eq!(parse_fallback(None, None, None), Fallback::MysteryPerson);
+    assert_eq!(
+        parse_fallback(Some("initials"), Some("jane doe"), None),
+        Fallback::Initials("JD".to_string())
+    );
+    assert_eq!(
+        parse_fallback(Some("https://example.com/a.png"), None, None),
+        Fallback::Redirect("https://example.com/a.png".to_string())
+    );
+}
+
+#[test]
+fn moderation_labels_raise_the_rating() {
+    let mut moderation = Moderation::default();
+    assert_eq!(avatar_rating(&moderation), Rating::G);
+    moderation.labels.push(ModerationLabel {
+        name: "Exposed Skin".to_string(),
+        parent: Some("Suggestive".to_string()),
+        confidence: 0.6,
+    });
+    assert_eq!(avatar_rating(&moderation), Rating::R);
+}
+
+#[tokio::test]
+async fn private_avatars_are_not_served_by_email_hash() {
+    let app = spawn_app().await;
+    let custom_domain = format!("{}.example.com", ObjectId::new());
+    let mut settings = ProjectSettings::default();
+    settings.access_policy.visibility = Visibility::Private;
+    settings.domain_policy.custom_domain = Some(custom_domain.clone());
+    settings.domain_policy.custom_domain_verified = true;
+    let project = project(ObjectId::new(), settings);
+    let project_id = project.id;
+    ProjectRepository::new(app.database.clone())
+        .create(project)
+        .await
+        .unwrap();
+    let email_hash = email_hash("private@example.com").unwrap();
+    let mut avatar = avatar(project_id);
+    avatar.external_id = Some("private@example.com".to_string());
+    avatar.email_hash = Some(email_hash.clone());
+    AvatarRepository::new(app.database.clone())
+        .create(avatar)
+        .await
+        .unwrap();
+    app.app_state.domains.load(&app.database).await.unwrap();
+
+    let response = reqwest::Client::new()
+        .get(&format!("{}/avatar/{}?d=404", app.address, email_hash.md5))
+        .header("Host", custom_domain.as_str())
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 404);
+}
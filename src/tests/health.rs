@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use super::spawn_app;
+use crate::{
+    errors::AppError,
+    health::{check, DependencyState, Readiness},
+};
+
+#[tokio::test]
+async fn liveness_answers_without_dependencies() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(&format!("{}/healthz", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn reports_dependency_states() {
+    let timeout = Duration::from_millis(50);
+
+    let up = check("up", timeout, async { Ok::<_, AppError>(()) }).await;
+    let down = check("down", timeout, async {
+        Err::<(), _>(AppError::db_error("connection refused"))
+    })
+    .await;
+    let slow = check("slow", timeout, async {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok::<_, AppError>(())
+    })
+    .await;
+
+    assert_eq!(up.status, DependencyState::Up);
+    assert_eq!(down.status, DependencyState::Down);
+    assert_eq!(slow.status, DependencyState::Timeout);
+    assert!(slow.latency_ms >= 50);
+}
+
+#[test]
+fn readiness_flips_on_shutdown() {
+    let readiness = Readiness::new(Duration::from_secs(1));
+
+    assert!(!readiness.is_shutting_down());
+    readiness.start_shutdown();
+    assert!(readiness.is_shutting_down());
+}
+
+#[test]
+fn readiness_waits_for_migrations() {
+    let readiness = Readiness::new(Duration::from_secs(1));
+
+    assert!(!readiness.is_migrated());
+    readiness.mark_migrated();
+    assert!(readiness.is_migrated());
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use uuid::Uuid;
+
+use super::{bearer_token, project, spawn_app, user, TestApp};
+use crate::{
+    models::ProjectSettings,
+    repositories::{InvitationRepository, ProjectRepository, UserRepository},
+};
+
+async fn invite(app: &TestApp) -> (ObjectId, String) {
+    let users = UserRepository::new(app.database.clone());
+    let author = user(None);
+    let author_id = author.id;
+    let project = project(author_id, ProjectSettings::default());
+    let project_id = project.id;
+    users.create(author).await.unwrap();
+    users
+        .add_project(author_id, &project_id.to_string())
+        .await
+        .unwrap();
+    ProjectRepository::new(app.database.clone())
+        .create(project)
+        .await
+        .unwrap();
+    let email = format!("{}@example.com", Uuid::new_v4());
+
+    let response = reqwest::Client::new()
+        .post(&format!("{}/api/invitation", app.address))
+        .bearer_auth(bearer_token(author_id))
+        .json(&HashMap::from([
+            ("email", email.clone()),
+            ("project", project_id.to_string()),
+        ]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    (project_id, email)
+}
+
+fn mailed_token(app: &TestApp, email: &str) -> String {
+    std::fs::read_dir(&app.mail_directory)
+        .unwrap()
+        .filter_map(|entry| std::fs::read_to_string(entry.unwrap().path()).ok())
+        .filter(|contents| contents.contains(&format!("To: {}", email)))
+        .find_map(|contents| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix("Invitation code: "))
+                .map(|token| token.trim().to_string())
+        })
+        .expect("No invitation mail")
+}
+
+#[tokio::test]
+async fn email_invitations_are_accepted_with_their_token() {
+    let app = spawn_app().await;
+    let (project_id, email) = invite(&app).await;
+    let token = mailed_token(&app, &email);
+    let users = UserRepository::new(app.database.clone());
+    let invited = user(None);
+    let invited_id = invited.id;
+    users.create(invited).await.unwrap();
+    let client = reqwest::Client::new();
+    let accept_url = format!("{}/api/invitation/{}/accept", app.address, project_id);
+
+    for payload in [
+        HashMap::new(),
+        HashMap::from([("token", "not-the-token".to_string())]),
+    ] {
+        let response = client
+            .post(&accept_url)
+            .bearer_auth(bearer_token(invited_id))
+            .json(&payload)
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_eq!(response.status().as_u16(), 404, "{:?}", payload);
+    }
+
+    let response = client
+        .post(&accept_url)
+        .bearer_auth(bearer_token(invited_id))
+        .json(&HashMap::from([("token", token.clone())]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        users.get(invited_id).await.unwrap().projects,
+        vec![project_id.to_string()]
+    );
+
+    // Tokens are single use.
+    let other = user(None);
+    let other_id = other.id;
+    users.create(other).await.unwrap();
+    let response = client
+        .post(&accept_url)
+        .bearer_auth(bearer_token(other_id))
+        .json(&HashMap::from([("token", token.clone())]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn registering_with_the_invited_email_needs_the_token() {
+    let app = spawn_app().await;
+    let (project_id, email) = invite(&app).await;
+    let token = mailed_token(&app, &email);
+    let client = reqwest::Client::new();
+    let users = UserRepository::new(app.database.clone());
+
+    let username = Uuid::new_v4().to_string();
+    let response = client
+        .post(&format!("{}/register", app.address))
+        .json(&HashMap::from([
+            ("username", username.clone()),
+            ("password", "test-password".to_string()),
+            ("email", email.clone()),
+        ]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(users
+        .get_by_username(&username)
+        .await
+        .unwrap()
+        .invitations
+        .is_empty());
+
+    let username = Uuid::new_v4().to_string();
+    let response = client
+        .post(&format!("{}/register", app.address))
+        .json(&HashMap::from([
+            ("username", username.clone()),
+            ("password", "test-password".to_string()),
+            ("email", format!("{}@example.com", Uuid::new_v4())),
+            ("invitation_token", token),
+        ]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        users.get_by_username(&username).await.unwrap().invitations,
+        vec![project_id.to_string()]
+    );
+    assert!(InvitationRepository::new(app.database.clone())
+        .get_by_email(&email)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn emails_are_unique() {
+    let app = spawn_app().await;
+    let users = UserRepository::new(app.database.clone());
+    let email = format!("{}@example.com", Uuid::new_v4());
+
+    users.create(user(Some(email.clone()))).await.unwrap();
+    let error = users.create(user(Some(email))).await.unwrap_err();
+
+    assert_eq!(error.problem_details().code, "user_exists");
+}
@@ -0,0 +1,230 @@
+use std::io::Cursor;
+
+use super::temporary_image;
+use crate::uploads::metadata::apply_orientation;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat, Rgb, RgbImage};
+
+const PNG_SIGNATURE: &[u8] = &[137, 80, 78, 71, 13, 10, 26, 10];
+const ICC_PROFILE: &[u8] = b"ICC_PROFILE\0\x01\x01fake profile";
+
+fn sample_image() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(8, 8, |x, y| {
+        Rgb([(x * 30) as u8, (y * 30) as u8, 128])
+    }))
+}
+
+fn encode(image: &DynamicImage, format: ImageOutputFormat) -> Vec<u8> {
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+    data
+}
+
+fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+    [
+        &[0xFF, marker][..],
+        &((payload.len() + 2) as u16).to_be_bytes(),
+        payload,
+    ]
+    .concat()
+}
+
+fn png_chunk(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+    [
+        &(data.len() as u32).to_be_bytes()[..],
+        chunk_type,
+        data,
+        &[0, 0, 0, 0],
+    ]
+    .concat()
+}
+
+// Little endian TIFF holding a single Orientation entry.
+fn exif_payload(orientation: u16) -> Vec<u8> {
+    [
+        &b"Exif\0\0II\x2a\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0"[..],
+        &orientation.to_le_bytes(),
+        &[0, 0, 0, 0, 0, 0],
+    ]
+    .concat()
+}
+
+fn jpeg_with_segments(segments: &[Vec<u8>]) -> Vec<u8> {
+    let encoded_image = encode(&sample_image(), ImageOutputFormat::Jpeg(90));
+    [&encoded_image[..2], &segments.concat(), &encoded_image[2..]].concat()
+}
+
+fn contains(data: &[u8], pattern: &[u8]) -> bool {
+    data.windows(pattern.len()).any(|window| window == pattern)
+}
+
+#[test]
+fn jpeg_metadata_is_read_and_stripped() {
+    let data = jpeg_with_segments(&[
+        jpeg_segment(0xE1, &exif_payload(6)),
+        jpeg_segment(0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"),
+        jpeg_segment(0xE2, ICC_PROFILE),
+        jpeg_segment(0xFE, b"made with a camera"),
+    ]);
+    let image = temporary_image(&data, ImageFormat::Jpeg);
+
+    let metadata = image.read_metadata().unwrap();
+    assert_eq!(metadata.summary.orientation, Some(6));
+    assert!(metadata.summary.xmp);
+    assert!(metadata.summary.icc_profile);
+    assert!(metadata
+        .summary
+        .removed_fields
+        .contains(&"jpeg:Comment".to_string()));
+    assert!(metadata
+        .summary
+        .removed_fields
+        .iter()
+        .any(|field| field.starts_with("exif:Orientation")));
+
+    let sanitized = image.sanitize(&sample_image(), metadata, false).unwrap();
+    let output = std::fs::read(&sanitized.image.path).unwrap();
+    assert!(!contains(&output, b"Exif\0\0"));
+    assert!(!contains(&output, b"<x:xmpmeta"));
+    assert!(!contains(&output, b"made with a camera"));
+    assert!(!contains(&output, b"ICC_PROFILE"));
+    assert!(!sanitized.metadata.icc_profile_kept);
+}
+
+#[test]
+fn jpeg_icc_profile_is_kept_when_asked() {
+    let data = jpeg_with_segments(&[jpeg_segment(0xE2, ICC_PROFILE)]);
+    let image = temporary_image(&data, ImageFormat::Jpeg);
+    let metadata = image.read_metadata().unwrap();
+
+    let sanitized = image.sanitize(&sample_image(), metadata, true).unwrap();
+    let output = std::fs::read(&sanitized.image.path).unwrap();
+
+    assert!(sanitized.metadata.icc_profile_kept);
+    assert!(contains(&output, ICC_PROFILE));
+    assert!(image::load_from_memory(&output).is_ok());
+}
+
+#[test]
+fn png_text_chunks_are_removed_and_icc_profile_kept() {
+    let encoded_image = encode(&sample_image(), ImageOutputFormat::Png);
+    // Signature and IHDR first, then the metadata chunks.
+    let data = [
+        &encoded_image[..33],
+        &png_chunk(b"tEXt", b"Comment\0made with a camera"),
+        &png_chunk(b"iTXt", b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta/>"),
+        &png_chunk(b"iCCP", b"profile\0\0fake profile"),
+        &encoded_image[33..],
+    ]
+    .concat();
+    let image = temporary_image(&data, ImageFormat::Png);
+
+    let metadata = image.read_metadata().unwrap();
+    assert!(metadata.summary.xmp);
+    assert!(metadata.summary.icc_profile);
+    assert_eq!(metadata.summary.removed_fields, vec!["png:Comment"]);
+
+    let sanitized = image.sanitize(&sample_image(), metadata, true).unwrap();
+    let output = std::fs::read(&sanitized.image.path).unwrap();
+    assert!(!contains(&output, b"tEXt"));
+    assert!(!contains(&output, b"iTXt"));
+    assert_eq!(&output[37..41], b"iCCP");
+    assert!(sanitized.metadata.icc_profile_kept);
+}
+
+#[test]
+fn truncated_and_empty_segments_are_tolerated() {
+    let jpeg = [
+        &[0xFF, 0xD8][..],
+        &[0xFF, 0xFE, 0x00, 0x00],
+        &[0xFF, 0xE2, 0xFF, 0xFF],
+        ICC_PROFILE,
+    ]
+    .concat();
+    let png = [
+        PNG_SIGNATURE,
+        &png_chunk(b"tEXt", b""),
+        &[0xFF, 0xFF, 0xFF, 0xFF],
+        b"iCCP",
+        b"cut",
+    ]
+    .concat();
+    let webp = [
+        &b"RIFF\0\0\0\0WEBP"[..],
+        b"XMP ",
+        &0u32.to_le_bytes(),
+        b"ICCP",
+        &u32::MAX.to_le_bytes(),
+        b"cut",
+    ]
+    .concat();
+
+    let jpeg_metadata = temporary_image(&jpeg, ImageFormat::Jpeg)
+        .read_metadata()
+        .unwrap();
+    assert!(jpeg_metadata.summary.icc_profile);
+    assert_eq!(jpeg_metadata.summary.removed_fields, vec!["jpeg:Comment"]);
+
+    let png_metadata = temporary_image(&png, ImageFormat::Png)
+        .read_metadata()
+        .unwrap();
+    assert!(png_metadata.summary.icc_profile);
+    assert_eq!(png_metadata.summary.removed_fields, vec!["png:"]);
+
+    let webp_metadata = temporary_image(&webp, ImageFormat::WebP)
+        .read_metadata()
+        .unwrap();
+    assert!(webp_metadata.summary.xmp);
+    assert!(webp_metadata.summary.icc_profile);
+
+    for data in [&b""[..], &[0xFF, 0xD8, 0xFF][..]] {
+        assert!(temporary_image(data, ImageFormat::Jpeg)
+            .read_metadata()
+            .is_ok());
+    }
+}
+
+#[test]
+fn webp_uploads_stay_webp() {
+    let data = encode(&sample_image(), ImageOutputFormat::Png);
+    let image = temporary_image(&data, ImageFormat::WebP);
+
+    let sanitized = image
+        .sanitize(&sample_image(), image.read_metadata().unwrap(), true)
+        .unwrap();
+    let output = std::fs::read(&sanitized.image.path).unwrap();
+
+    assert_eq!(sanitized.image.format, ImageFormat::WebP);
+    assert_eq!(&output[..4], b"RIFF");
+    assert_eq!(&output[8..12], b"WEBP");
+}
+
+#[test]
+fn every_orientation_is_applied() {
+    // 3x2 image with a single white pixel in the top left corner.
+    let image = DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| match (x, y) {
+        (0, 0) => Rgb([255, 255, 255]),
+        _ => Rgb([0, 0, 0]),
+    }));
+    let expected = [
+        (1, (3, 2), (0, 0)),
+        (2, (3, 2), (2, 0)),
+        (3, (3, 2), (2, 1)),
+        (4, (3, 2), (0, 1)),
+        (5, (2, 3), (0, 0)),
+        (6, (2, 3), (1, 0)),
+        (7, (2, 3), (1, 2)),
+        (8, (2, 3), (0, 2)),
+        (9, (3, 2), (0, 0)),
+    ];
+
+    for (orientation, (width, height), (x, y)) in expected {
+        let oriented = apply_orientation(image.clone(), orientation).to_rgb8();
+        assert_eq!(oriented.dimensions(), (width, height), "{}", orientation);
+        assert_eq!(
+            oriented.get_pixel(x, y),
+            &Rgb([255, 255, 255]),
+            "{}",
+            orientation
+        );
+    }
+}
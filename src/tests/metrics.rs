@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    caching::render_cache::RenderCacheStats,
+    metrics::{
+        observe_request, render, MetricsLayer, DATABASE_OPERATION_DURATION,
+        STORAGE_OPERATION_DURATION, STORAGE_OPERATION_ERRORS,
+    },
+};
+
+#[test]
+fn exposes_request_and_render_cache_metrics() {
+    observe_request("GET", "/media/{avatar_id}", 200, Duration::from_millis(5));
+
+    let body = render(&RenderCacheStats {
+        hits: 3,
+        misses: 1,
+        coalesced: 0,
+        evictions: 0,
+        entries: 1,
+        bytes: 2048,
+        budget_bytes: 4096,
+    })
+    .unwrap();
+
+    assert!(body.contains(
+        r#"stampa_http_requests_total{method="GET",route="/media/{avatar_id}",status="200"}"#
+    ));
+    assert!(body.contains("stampa_render_cache_hits_total 3"));
+    assert!(body.contains("stampa_render_cache_bytes 2048"));
+}
+
+#[test]
+fn times_database_and_storage_spans() {
+    let subscriber = tracing_subscriber::registry().with(MetricsLayer);
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("mongodb.metrics_test.find").in_scope(|| {});
+        tracing::info_span!("s3.metrics_test").in_scope(|| {
+            tracing::error!(error = "access denied");
+        });
+        tracing::info_span!("unrelated.span").in_scope(|| {});
+    });
+
+    assert_eq!(
+        DATABASE_OPERATION_DURATION
+            .with_label_values(&["metrics_test", "find"])
+            .get_sample_count(),
+        1
+    );
+    assert_eq!(
+        STORAGE_OPERATION_DURATION
+            .with_label_values(&["s3", "metrics_test"])
+            .get_sample_count(),
+        1
+    );
+    assert_eq!(
+        STORAGE_OPERATION_ERRORS
+            .with_label_values(&["s3", "metrics_test"])
+            .get(),
+        1
+    );
+}
@@ -0,0 +1,94 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    options::FindOptions,
+    Database,
+};
+
+use super::spawn_app;
+use crate::migrations;
+
+async fn project_avatars(database: &Database, project_id: ObjectId) -> Vec<Document> {
+    database
+        .collection::<Document>("avatars")
+        .find(
+            doc! {"project": project_id},
+            FindOptions::builder().sort(doc! {"_id": 1}).build(),
+        )
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap()
+}
+
+async fn project(database: &Database, project_id: ObjectId) -> Document {
+    database
+        .collection::<Document>("projects")
+        .find_one(doc! {"_id": project_id}, None)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn embedded_avatars_are_moved_once() {
+    let app = spawn_app().await;
+    let project_id = ObjectId::new();
+    let avatar_ids = vec![ObjectId::new(), ObjectId::new()];
+    app.database
+        .collection::<Document>("projects")
+        .insert_one(
+            doc! {
+                "_id": project_id,
+                "author": ObjectId::new(),
+                "title": "Legacy",
+                "api_key": "key",
+                "api_secret": "secret",
+                "region": "eu-west-3",
+                "members": [],
+                "invitations": [],
+                "avatars": [
+                    {
+                        "_id": avatar_ids[0],
+                        "name": "first",
+                        "mime_type": "png",
+                        "url": "https://example.com/first.png",
+                    },
+                    {
+                        "_id": avatar_ids[1],
+                        "name": "second",
+                        "mime_type": "png",
+                        "url": "https://example.com/second.png",
+                        "external_id": "Jane@Example.com",
+                    },
+                ],
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+    migrations::run(&app.database).await.unwrap();
+    let moved_avatars = project_avatars(&app.database, project_id).await;
+    let migrated_project = project(&app.database, project_id).await;
+    assert_eq!(
+        moved_avatars
+            .iter()
+            .map(|avatar| avatar.get_object_id("_id").unwrap())
+            .collect::<Vec<ObjectId>>(),
+        avatar_ids
+    );
+    assert!(moved_avatars
+        .iter()
+        .all(|avatar| avatar.get_object_id("project").unwrap() == project_id));
+    assert!(!migrated_project.contains_key("avatars"));
+    assert!(!migrated_project.get_str("signing_key").unwrap().is_empty());
+
+    migrations::run(&app.database).await.unwrap();
+    assert_eq!(
+        project_avatars(&app.database, project_id).await,
+        moved_avatars
+    );
+    assert_eq!(project(&app.database, project_id).await, migrated_project);
+}
@@ -1,32 +1,214 @@
 use std::net::TcpListener;
 
 use actix_web::web::Data;
-use mongodb::Database;
+use image::ImageFormat;
+use mongodb::{bson::oid::ObjectId, Database};
 use uuid::Uuid;
 
-use crate::{startup::run, AppState};
+use crate::{
+    models::{Avatar, Moderation, Project, ProjectSettings, User},
+    repositories::{ProjectRepository, UserRepository},
+    startup::run,
+    uploads::TemporaryImage,
+    utils::{encode_jwt, Claims},
+    AppState,
+};
+
+#[cfg(test)]
+mod animation;
+#[cfg(test)]
+mod avatars;
+#[cfg(test)]
+mod caching;
+#[cfg(test)]
+mod cdn;
+#[cfg(test)]
+mod crop;
+#[cfg(test)]
+mod domains;
+#[cfg(test)]
+mod errors;
+#[cfg(test)]
+mod fingerprints;
+#[cfg(test)]
+mod gravatar;
+#[cfg(test)]
+mod health;
+#[cfg(test)]
+mod invitations;
+#[cfg(test)]
+mod metadata;
+#[cfg(test)]
+mod metrics;
+#[cfg(test)]
+mod migrations;
+#[cfg(test)]
+mod moderation;
+#[cfg(test)]
+mod placeholders;
+#[cfg(test)]
+mod render_cache;
+#[cfg(test)]
+mod renditions;
+#[cfg(test)]
+mod signing;
+#[cfg(test)]
+mod storage;
+#[cfg(test)]
+mod telemetry;
+#[cfg(test)]
+mod uploads;
+#[cfg(test)]
+mod versions;
 
 pub struct TestApp {
     pub address: String,
     pub database: Database,
+    pub app_state: Data<AppState>,
+    pub mail_directory: String,
 }
 
 pub async fn spawn_app() -> TestApp {
+    #[cfg(test)]
+    storage::storage();
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
     let port = listener.local_addr().unwrap().port();
     let address = format!("http://127.0.0.1:{}", port);
     let mut configuration = crate::config::Config::load_test_configuration().unwrap();
     configuration.database_name = Uuid::new_v4().to_string();
     let database = configuration.connect_mongo().await.unwrap();
+    crate::migrations::run(&database).await.unwrap();
+    let mailer = crate::mailer::from_config(&configuration).unwrap();
+    let face_detector = crate::faces::from_config(&configuration).await.unwrap();
+    let moderator = crate::moderation::from_config(&configuration)
+        .await
+        .unwrap();
+    let gravatar_project = configuration.gravatar_project_id().unwrap();
+    let purge_hook = crate::cdn::from_config(&configuration).unwrap();
+    let domains = crate::domains::DomainRegistry::new(crate::domains::parse_origins(
+        &configuration.cors_allowed_origins,
+    ));
+    domains.load(&database).await.unwrap();
     let app_state = Data::new(AppState {
         database: database.clone(),
+        mailer,
+        face_detector,
+        moderator,
+        gravatar_project,
+        public_url: address.clone(),
+        render_cache: crate::caching::render_cache::RenderCache::new(
+            configuration.render_cache_bytes,
+        ),
+        purge_hook,
+        url_template: configuration.url_template.clone(),
+        domains,
+        domain_verifier: Box::new(crate::domains::DnsDomainVerifier::new().unwrap()),
+        readiness: crate::health::Readiness::new(std::time::Duration::from_millis(
+            configuration.readiness_timeout_ms,
+        )),
     });
 
-    let server = run(listener, app_state).expect("Failed to bind address");
+    app_state.readiness.mark_migrated();
+    let server = run(listener, app_state.clone()).expect("Failed to bind address");
 
     let _ = tokio::spawn(server);
 
-    TestApp { address, database }
+    TestApp {
+        address,
+        database,
+        app_state,
+        mail_directory: configuration.mail_directory.clone(),
+    }
+}
+
+pub fn temporary_image(data: &[u8], format: ImageFormat) -> TemporaryImage {
+    let path = format!("./tmp/{}", Uuid::new_v4());
+    std::fs::write(&path, data).unwrap();
+    TemporaryImage {
+        path,
+        format,
+        size: data.len() as u64,
+    }
+}
+
+pub fn user(email: Option<String>) -> User {
+    User {
+        id: ObjectId::new(),
+        username: Uuid::new_v4().to_string(),
+        password: "hashed-password".to_string(),
+        email,
+        avatar: "https://example.com/avatar.png".to_string(),
+        placeholder: None,
+        renditions: Vec::new(),
+        projects: Vec::new(),
+        invitations: Vec::new(),
+    }
+}
+
+pub fn bearer_token(user_id: ObjectId) -> String {
+    encode_jwt(Claims {
+        exp: (chrono::Utc::now() + chrono::Duration::days(1)).timestamp() as usize,
+        sub: user_id.to_string(),
+        id: user_id,
+    })
+    .unwrap()
+}
+
+pub fn project(author: ObjectId, settings: ProjectSettings) -> Project {
+    Project {
+        id: ObjectId::new(),
+        author,
+        title: "Project".to_string(),
+        api_key: "key".to_string(),
+        api_secret: "secret".to_string(),
+        signing_key: "signing-key".to_string(),
+        region: "eu-west-3".to_string(),
+        members: vec![author],
+        invitations: Vec::new(),
+        settings,
+    }
+}
+
+pub async fn member_project(app: &TestApp, settings: ProjectSettings) -> (String, ObjectId) {
+    let mut member = user(None);
+    let project = project(member.id, settings);
+    let project_id = project.id;
+    member.projects = vec![project_id.to_string()];
+    let token = bearer_token(member.id);
+    UserRepository::new(app.database.clone())
+        .create(member)
+        .await
+        .unwrap();
+    ProjectRepository::new(app.database.clone())
+        .create(project)
+        .await
+        .unwrap();
+    (token, project_id)
+}
+
+pub fn avatar(project_id: ObjectId) -> Avatar {
+    let avatar_id = ObjectId::new();
+    Avatar {
+        _id: avatar_id,
+        project: project_id,
+        external_id: None,
+        name: "avatar".to_string(),
+        mime_type: "png".to_string(),
+        url: format!("https://example.com/{}.png", avatar_id),
+        storage_key: None,
+        width: 64,
+        height: 64,
+        uploader: None,
+        updated_at: None,
+        metadata: None,
+        moderation: Moderation::default(),
+        placeholder: None,
+        content_hash: None,
+        fingerprint: None,
+        animation: None,
+        renditions: Vec::new(),
+        email_hash: None,
+    }
 }
 
 #[tokio::test]
@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::{
+    errors::AppError,
+    models::{ModerationLabel, ModerationPolicy, ModerationStatus},
+    moderation::{review, review_frames, sample_frames, LocalModerator, Moderator},
+};
+
+struct StaticModerator(Result<Vec<ModerationLabel>, ()>);
+
+#[async_trait]
+impl Moderator for StaticModerator {
+    async fn moderate(&self, _image: &DynamicImage) -> Result<Vec<ModerationLabel>, AppError> {
+        self.0
+            .clone()
+            .map_err(|_| AppError::moderation_error("service unavailable"))
+    }
+}
+
+// Flags frames whose first pixel is pure red.
+struct RedFrameModerator;
+
+#[async_trait]
+impl Moderator for RedFrameModerator {
+    async fn moderate(&self, image: &DynamicImage) -> Result<Vec<ModerationLabel>, AppError> {
+        match image.to_rgb8().get_pixel(0, 0) {
+            Rgb([255, 0, 0]) => Ok(vec![label("Explicit Nudity", None, 0.99)]),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+fn label(name: &str, parent: Option<&str>, confidence: f32) -> ModerationLabel {
+    ModerationLabel {
+        name: name.to_string(),
+        parent: parent.map(str::to_string),
+        confidence,
+    }
+}
+
+fn enabled_policy() -> ModerationPolicy {
+    ModerationPolicy {
+        enabled: true,
+        ..ModerationPolicy::default()
+    }
+}
+
+fn image() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, Rgb([224, 172, 140])))
+}
+
+#[tokio::test]
+async fn moderation_is_off_by_default() {
+    let moderator = StaticModerator(Ok(vec![label("Explicit Nudity", None, 0.99)]));
+
+    let moderation = review(&moderator, &image(), &ModerationPolicy::default()).await;
+
+    assert_eq!(moderation.status, ModerationStatus::Approved);
+    assert!(moderation.labels.is_empty());
+}
+
+#[tokio::test]
+async fn blocked_labels_are_held_for_review() {
+    let cases = vec![
+        (
+            vec![label("Explicit Nudity", None, 0.9)],
+            ModerationStatus::Pending,
+        ),
+        (
+            vec![label("Revealing Clothes", Some("Suggestive"), 0.9)],
+            ModerationStatus::Pending,
+        ),
+        (
+            vec![label("Explicit Nudity", None, 0.5)],
+            ModerationStatus::Approved,
+        ),
+        (
+            vec![label("Smiling", None, 0.99)],
+            ModerationStatus::Approved,
+        ),
+        (Vec::new(), ModerationStatus::Approved),
+    ];
+
+    for (labels, status) in cases {
+        let moderator = StaticModerator(Ok(labels.clone()));
+        let moderation = review(&moderator, &image(), &enabled_policy()).await;
+        assert_eq!(moderation.status, status, "{:?}", labels);
+        assert_eq!(moderation.labels.len(), labels.len());
+        assert!(moderation.reviewed_at.is_some());
+    }
+}
+
+#[tokio::test]
+async fn moderator_failures_hold_the_avatar() {
+    let moderation = review(&StaticModerator(Err(())), &image(), &enabled_policy()).await;
+
+    assert_eq!(moderation.status, ModerationStatus::Pending);
+    assert!(moderation.reviewed_at.is_none());
+}
+
+#[tokio::test]
+async fn skin_tones_alone_are_not_flagged() {
+    let moderator = LocalModerator::default();
+
+    let moderation = review(&moderator, &image(), &enabled_policy()).await;
+
+    assert_eq!(moderation.status, ModerationStatus::Approved);
+    assert!(moderation.labels[0].confidence > 0.9);
+
+    let policy = ModerationPolicy {
+        blocked_labels: vec!["Exposed Skin".to_string()],
+        ..enabled_policy()
+    };
+    let moderation = review(&moderator, &image(), &policy).await;
+    assert_eq!(moderation.status, ModerationStatus::Pending);
+}
+
+#[tokio::test]
+async fn every_sampled_frame_is_moderated() {
+    let clean = image();
+    let explicit = DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, Rgb([255, 0, 0])));
+
+    let moderation = review_frames(
+        &RedFrameModerator,
+        &[clean.clone(), clean.clone(), explicit],
+        &enabled_policy(),
+    )
+    .await;
+    assert_eq!(moderation.status, ModerationStatus::Pending);
+    assert_eq!(moderation.labels.len(), 1);
+
+    let moderation = review_frames(
+        &RedFrameModerator,
+        &[clean.clone(), clean],
+        &enabled_policy(),
+    )
+    .await;
+    assert_eq!(moderation.status, ModerationStatus::Approved);
+}
+
+#[test]
+fn sampled_frames_include_the_first_and_last() {
+    let frames = (0..100).collect::<Vec<u32>>();
+
+    let sampled = sample_frames(&frames, 10);
+    assert_eq!(sampled.len(), 10);
+    assert_eq!(*sampled[0], 0);
+    assert_eq!(*sampled[9], 99);
+
+    assert_eq!(sample_frames(&frames[..3], 10).len(), 3);
+}
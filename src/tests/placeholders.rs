@@ -0,0 +1,76 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::placeholders::{blurhash, compute, dominant_color, thumbhash};
+
+const RED: Rgba<u8> = Rgba([200, 30, 30, 255]);
+const BLUE: Rgba<u8> = Rgba([20, 40, 220, 255]);
+
+// Left three quarters red, right quarter blue.
+fn split(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, _| {
+        match x < width * 3 / 4 {
+            true => RED,
+            false => BLUE,
+        }
+    }))
+}
+
+fn solid(color: Rgba<u8>) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_pixel(48, 48, color))
+}
+
+#[test]
+fn blurhash_is_deterministic() {
+    let hash = blurhash(&split(64, 64));
+
+    // 4x3 components: size flag "L", quantised maximum, 4 chars of DC and 11 AC pairs.
+    assert_eq!(hash.len(), 28);
+    assert!(hash.starts_with('L'));
+    assert_eq!(hash, blurhash(&split(64, 64)));
+    assert_ne!(hash, blurhash(&solid(RED)));
+    assert_eq!(blurhash(&solid(RED)), blurhash(&solid(RED)));
+}
+
+#[test]
+fn thumbhash_keeps_average_color_and_aspect_ratio() {
+    let hash = thumbhash(&solid(RED));
+    assert_eq!(hash, thumbhash(&solid(RED)));
+
+    let bytes = base64::decode(&hash).unwrap();
+    let (red, green, blue, alpha) = thumbhash::thumb_hash_to_average_rgba(&bytes).unwrap();
+    assert!((red - 200.0 / 255.0).abs() < 0.05);
+    assert!((green - 30.0 / 255.0).abs() < 0.05);
+    assert!((blue - 30.0 / 255.0).abs() < 0.05);
+    assert!((alpha - 1.0).abs() < 0.01);
+
+    let wide = base64::decode(thumbhash(&split(200, 100))).unwrap();
+    let ratio = thumbhash::thumb_hash_to_approximate_aspect_ratio(&wide).unwrap();
+    assert!((ratio - 2.0).abs() < 0.2);
+}
+
+#[test]
+fn dominant_color_picks_the_largest_bucket() {
+    assert_eq!(dominant_color(&split(64, 64)), "#c81e1e");
+    assert_eq!(dominant_color(&solid(BLUE)), "#1428dc");
+}
+
+#[test]
+fn dominant_color_ignores_transparent_pixels() {
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, _| match x < 48 {
+        true => Rgba([200, 30, 30, 0]),
+        false => BLUE,
+    }));
+    assert_eq!(dominant_color(&image), "#1428dc");
+
+    assert_eq!(dominant_color(&solid(Rgba([0, 0, 0, 0]))), "#000000");
+}
+
+#[test]
+fn compute_fills_every_placeholder() {
+    let image = split(64, 64);
+    let placeholder = compute(&image);
+
+    assert_eq!(placeholder.blurhash, blurhash(&image));
+    assert_eq!(placeholder.thumbhash, thumbhash(&image));
+    assert_eq!(placeholder.dominant_color, "#c81e1e");
+}
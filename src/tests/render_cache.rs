@@ -0,0 +1,110 @@
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use actix_web::web::Bytes;
+
+use crate::{
+    caching::render_cache::{CacheStatus, RenderCache, RenderedImage},
+    errors::AppError,
+};
+
+fn image(size: usize) -> RenderedImage {
+    RenderedImage {
+        data: Bytes::from(vec![0; size]),
+        mime_type: "image/png",
+    }
+}
+
+#[tokio::test]
+async fn second_request_is_a_hit() {
+    let cache = RenderCache::new(1024);
+    let (_, status) = cache
+        .get_or_render("a", || async { Ok(image(10)) })
+        .await
+        .unwrap();
+    assert_eq!(status, CacheStatus::Miss);
+    let (_, status) = cache
+        .get_or_render("a", || async { Ok(image(10)) })
+        .await
+        .unwrap();
+    assert_eq!(status, CacheStatus::Hit);
+    assert_eq!(cache.stats().hits, 1);
+    assert_eq!(cache.stats().misses, 1);
+}
+
+#[tokio::test]
+async fn least_recently_used_entries_are_evicted_by_size() {
+    let cache = RenderCache::new(100);
+    for key in ["a", "b"] {
+        cache
+            .get_or_render(key, || async { Ok(image(40)) })
+            .await
+            .unwrap();
+    }
+    // Touch "a" so that "b" becomes the eviction candidate.
+    cache
+        .get_or_render("a", || async { Ok(image(40)) })
+        .await
+        .unwrap();
+    cache
+        .get_or_render("c", || async { Ok(image(40)) })
+        .await
+        .unwrap();
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.bytes, 80);
+    assert_eq!(stats.evictions, 1);
+    let (_, status) = cache
+        .get_or_render("b", || async { Ok(image(40)) })
+        .await
+        .unwrap();
+    assert_eq!(status, CacheStatus::Miss);
+}
+
+#[tokio::test]
+async fn images_larger_than_the_budget_are_not_cached() {
+    let cache = RenderCache::new(10);
+    cache
+        .get_or_render("a", || async { Ok(image(20)) })
+        .await
+        .unwrap();
+    assert_eq!(cache.stats().entries, 0);
+}
+
+#[tokio::test]
+async fn concurrent_requests_render_once() {
+    let cache = RenderCache::new(1024);
+    let renders = AtomicU32::new(0);
+    let render = || async {
+        renders.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(image(10))
+    };
+    let (first, second) = futures::join!(
+        cache.get_or_render("a", render),
+        cache.get_or_render("a", render)
+    );
+    assert_eq!(renders.load(Ordering::SeqCst), 1);
+    let mut statuses = vec![first.unwrap().1, second.unwrap().1];
+    statuses.sort_by_key(|status| status.as_str());
+    assert_eq!(statuses, vec![CacheStatus::Coalesced, CacheStatus::Miss]);
+}
+
+#[tokio::test]
+async fn failed_renders_are_not_cached() {
+    let cache = RenderCache::new(1024);
+    let result = cache
+        .get_or_render("a", || async {
+            Err(AppError::avatat_generation_error("cairo failed"))
+        })
+        .await;
+    assert!(result.is_err());
+    let (_, status) = cache
+        .get_or_render("a", || async { Ok(image(10)) })
+        .await
+        .unwrap();
+    assert_eq!(status, CacheStatus::Miss);
+}
@@ -0,0 +1,46 @@
+use crate::{
+    models::{OutputFormat, Rendition},
+    renditions::negotiate,
+};
+
+fn rendition(format: OutputFormat, size: u64) -> Rendition {
+    Rendition {
+        format,
+        mime_type: format.mime_type().to_string(),
+        storage_key: format!("avatar.{}", format.extension()),
+        url: format!("https://example.com/avatar.{}", format.extension()),
+        size,
+        content_hash: None,
+    }
+}
+
+fn renditions() -> Vec<Rendition> {
+    vec![
+        rendition(OutputFormat::Webp, 2000),
+        rendition(OutputFormat::Avif, 1500),
+    ]
+}
+
+#[test]
+fn modern_browser_gets_smallest_format() {
+    let renditions = renditions();
+    let accept = "image/avif,image/webp,image/apng,image/*,*/*;q=0.8";
+    let chosen = negotiate(accept, &renditions).unwrap();
+    assert_eq!(chosen.format, OutputFormat::Avif);
+}
+
+#[test]
+fn quality_values_are_respected() {
+    let renditions = renditions();
+    let chosen = negotiate("image/avif;q=0.5, image/webp", &renditions).unwrap();
+    assert_eq!(chosen.format, OutputFormat::Webp);
+    assert!(negotiate("image/avif;q=0, image/png", &renditions).is_none());
+}
+
+#[test]
+fn wildcards_keep_original_format() {
+    let renditions = renditions();
+    assert!(negotiate("*/*", &renditions).is_none());
+    assert!(negotiate("image/*", &renditions).is_none());
+    assert!(negotiate("", &renditions).is_none());
+}
@@ -0,0 +1,106 @@
+use mongodb::bson::oid::ObjectId;
+
+use crate::signing::{sign, sign_review, verify, verify_review};
+
+const SECRET: &str = "project-secret";
+const NOW: i64 = 1_650_000_000;
+
+#[test]
+fn signature_round_trip() {
+    let avatar_id = ObjectId::new();
+    let signature = sign(SECRET, avatar_id, None, NOW + 60);
+    assert!(verify(SECRET, avatar_id, None, NOW + 60, &signature, NOW));
+}
+
+#[test]
+fn expired_signature_is_rejected() {
+    let avatar_id = ObjectId::new();
+    let signature = sign(SECRET, avatar_id, None, NOW - 1);
+    assert!(!verify(SECRET, avatar_id, None, NOW - 1, &signature, NOW));
+}
+
+#[test]
+fn signature_is_bound_to_avatar_version_and_expiry() {
+    let avatar_id = ObjectId::new();
+    let version_id = ObjectId::new();
+    let signature = sign(SECRET, avatar_id, Some(version_id), NOW + 60);
+    assert!(verify(
+        SECRET,
+        avatar_id,
+        Some(version_id),
+        NOW + 60,
+        &signature,
+        NOW
+    ));
+    assert!(!verify(SECRET, avatar_id, None, NOW + 60, &signature, NOW));
+    assert!(!verify(
+        SECRET,
+        ObjectId::new(),
+        Some(version_id),
+        NOW + 60,
+        &signature,
+        NOW
+    ));
+    assert!(!verify(
+        SECRET,
+        avatar_id,
+        Some(version_id),
+        NOW + 3600,
+        &signature,
+        NOW
+    ));
+    assert!(!verify(
+        "other-secret",
+        avatar_id,
+        Some(version_id),
+        NOW + 60,
+        &signature,
+        NOW
+    ));
+}
+
+#[test]
+fn malformed_signature_is_rejected() {
+    let avatar_id = ObjectId::new();
+    assert!(!verify(SECRET, avatar_id, None, NOW + 60, "not-hex", NOW));
+    assert!(!verify(
+        SECRET,
+        avatar_id,
+        None,
+        NOW + 60,
+        &"zz".repeat(32),
+        NOW
+    ));
+}
+
+#[test]
+fn review_signature_is_scoped() {
+    let avatar_id = ObjectId::new();
+    let review_signature = sign_review(SECRET, avatar_id, None, NOW + 60);
+    assert!(verify_review(
+        SECRET,
+        avatar_id,
+        None,
+        NOW + 60,
+        &review_signature,
+        NOW
+    ));
+    assert!(!verify(
+        SECRET,
+        avatar_id,
+        None,
+        NOW + 60,
+        &review_signature,
+        NOW
+    ));
+
+    let signature = sign(SECRET, avatar_id, None, NOW + 60);
+    assert!(!verify_review(
+        SECRET,
+        avatar_id,
+        None,
+        NOW + 60,
+        &signature,
+        NOW
+    ));
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::{http::Method, web, App, HttpRequest, HttpResponse, HttpServer};
+use lazy_static::lazy_static;
+
+type Objects = Arc<Mutex<HashMap<String, StoredObject>>>;
+
+#[derive(Clone, Debug)]
+pub struct StoredObject {
+    pub data: Vec<u8>,
+    pub acl: Option<String>,
+}
+
+pub struct StubStorage {
+    objects: Objects,
+}
+
+lazy_static! {
+    static ref STORAGE: StubStorage = StubStorage::start();
+}
+
+// Every test talks to this stub instead of S3, it runs on its own thread so it outlives the
+// runtime of the test that started it.
+pub fn storage() -> &'static StubStorage {
+    &STORAGE
+}
+
+impl StubStorage {
+    fn start() -> StubStorage {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let objects = Objects::default();
+        let server_objects = objects.clone();
+        std::thread::spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                HttpServer::new(move || {
+                    App::new()
+                        .app_data(web::Data::new(server_objects.clone()))
+                        .app_data(web::PayloadConfig::new(64 * 1024 * 1024))
+                        .default_service(web::to(handle))
+                })
+                .workers(1)
+                .listen(listener)
+                .expect("Failed to listen")
+                .run()
+                .await
+            })
+        });
+
+        std::env::set_var("S3_ENDPOINT", endpoint);
+        if std::env::var("AWS_ACCESS_KEY_ID").is_err() {
+            std::env::set_var("AWS_ACCESS_KEY_ID", "stub");
+            std::env::set_var("AWS_SECRET_ACCESS_KEY", "stub");
+        }
+        StubStorage { objects }
+    }
+
+    pub fn get(&self, bucket: &str, key: &str) -> Option<StoredObject> {
+        self.objects
+            .lock()
+            .unwrap()
+            .get(&format!("{}/{}", bucket, key))
+            .cloned()
+    }
+
+    pub fn put(&self, bucket: &str, key: &str, data: Vec<u8>) {
+        self.objects.lock().unwrap().insert(
+            format!("{}/{}", bucket, key),
+            StoredObject {
+                data,
+                acl: Some("public-read".to_string()),
+            },
+        );
+    }
+
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        let prefix = format!("{}/", bucket);
+        let mut keys = self
+            .objects
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|key| key.strip_prefix(&prefix).map(str::to_string))
+            .collect::<Vec<String>>();
+        keys.sort();
+        keys
+    }
+}
+
+// Requests are path style, `/{bucket}` for buckets and `/{bucket}/{key}` for objects.
+async fn handle(
+    request: HttpRequest,
+    body: web::Bytes,
+    objects: web::Data<Objects>,
+) -> HttpResponse {
+    let path = request.path().trim_start_matches('/').to_string();
+    let acl = request
+        .headers()
+        .get("x-amz-acl")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let mut objects = objects.lock().unwrap();
+    if !path.contains('/') {
+        return HttpResponse::Ok().finish();
+    }
+    match *request.method() {
+        Method::PUT if request.query_string().contains("acl") => match objects.get_mut(&path) {
+            Some(object) => {
+                object.acl = acl;
+                HttpResponse::Ok().finish()
+            }
+            None => HttpResponse::NotFound().finish(),
+        },
+        Method::PUT => {
+            objects.insert(
+                path,
+                StoredObject {
+                    data: body.to_vec(),
+                    acl,
+                },
+            );
+            HttpResponse::Ok().finish()
+        }
+        Method::GET => match objects.get(&path) {
+            Some(object) => HttpResponse::Ok().body(object.data.clone()),
+            None => HttpResponse::NotFound().finish(),
+        },
+        Method::DELETE => {
+            objects.remove(&path);
+            HttpResponse::NoContent().finish()
+        }
+        _ => HttpResponse::MethodNotAllowed().finish(),
+    }
+}
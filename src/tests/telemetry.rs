@@ -0,0 +1,56 @@
+use uuid::Uuid;
+
+use super::spawn_app;
+use crate::telemetry::{request_id, valid_request_id, REQUEST_ID_HEADER};
+
+async fn response_request_id(address: &str, request_id: Option<&str>) -> String {
+    let mut request = reqwest::Client::new().get(&format!("{}/healthz", address));
+    if let Some(request_id) = request_id {
+        request = request.header(REQUEST_ID_HEADER, request_id);
+    }
+    let response = request.send().await.expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .expect("Missing request id")
+        .to_string()
+}
+
+#[test]
+fn incoming_request_ids_are_validated() {
+    assert!(valid_request_id("3f0c5b8e-2a4d-4c1e-9d1f-0b6a7e2c9d10"));
+    assert!(valid_request_id("edge.req_42"));
+    assert!(!valid_request_id(""));
+    assert!(!valid_request_id("id with spaces"));
+    assert!(!valid_request_id(&"a".repeat(129)));
+}
+
+#[test]
+fn no_request_id_outside_requests() {
+    assert_eq!(request_id(), None);
+}
+
+#[tokio::test]
+async fn supplied_request_ids_are_echoed() {
+    let app = spawn_app().await;
+
+    assert_eq!(
+        response_request_id(&app.address, Some("edge.req_42")).await,
+        "edge.req_42"
+    );
+}
+
+#[tokio::test]
+async fn missing_or_invalid_request_ids_are_generated() {
+    let app = spawn_app().await;
+
+    let first = response_request_id(&app.address, None).await;
+    let second = response_request_id(&app.address, None).await;
+    let replaced = response_request_id(&app.address, Some("id with spaces")).await;
+
+    assert!(Uuid::parse_str(&first).is_ok());
+    assert!(Uuid::parse_str(&replaced).is_ok());
+    assert_ne!(first, second);
+}
@@ -0,0 +1,255 @@
+use std::{collections::HashMap, io::Cursor};
+
+use actix_web::{web::Bytes, ResponseError};
+use image::{
+    DynamicImage, ImageBuffer, ImageFormat, ImageOutputFormat, Rgb, RgbImage, Rgba, RgbaImage,
+};
+use mongodb::bson::oid::ObjectId;
+use validator::Validate;
+
+use super::{bearer_token, spawn_app, temporary_image, user, TestApp};
+use crate::{
+    errors::AppError, models::UploadPolicy, repositories::UserRepository, uploads::TemporaryImage,
+};
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+async fn member(app: &TestApp) -> (String, String) {
+    let mut member = user(None);
+    let project_id = ObjectId::new().to_string();
+    member.projects = vec![project_id.clone()];
+    let token = bearer_token(member.id);
+    UserRepository::new(app.database.clone())
+        .create(member)
+        .await
+        .unwrap();
+    (token, project_id)
+}
+
+fn encode(image: DynamicImage, format: ImageOutputFormat) -> Vec<u8> {
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+    data
+}
+
+fn sized_png(width: u32, height: u32) -> Vec<u8> {
+    encode(
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([200, 30, 30]))),
+        ImageOutputFormat::Png,
+    )
+}
+
+fn png() -> Vec<u8> {
+    sized_png(32, 32)
+}
+
+fn error_code(error: AppError) -> String {
+    error.problem_details().code.to_string()
+}
+
+async fn assert_problem(response: reqwest::Response, status: u16) {
+    assert_eq!(response.status().as_u16(), status);
+    assert_eq!(
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok()),
+        Some(PROBLEM_CONTENT_TYPE)
+    );
+}
+
+#[tokio::test]
+async fn malformed_data_urls_are_rejected() {
+    let app = spawn_app().await;
+    let (token, project_id) = member(&app).await;
+    let cases = vec![
+        ("data:image/png;base64,not base64!".to_string(), 400),
+        (base64::encode(png()), 400),
+        (
+            format!("data:text/plain;base64,{}", base64::encode(png())),
+            415,
+        ),
+    ];
+
+    for (image, status) in cases {
+        let response = reqwest::Client::new()
+            .post(&format!("{}/api/avatar", app.address))
+            .bearer_auth(&token)
+            .json(&HashMap::from([
+                ("name", "avatar".to_string()),
+                ("project", project_id.clone()),
+                ("image", image),
+            ]))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_problem(response, status).await;
+    }
+}
+
+#[tokio::test]
+async fn empty_multipart_bodies_are_rejected() {
+    let app = spawn_app().await;
+    let (token, _) = member(&app).await;
+
+    let response = reqwest::Client::new()
+        .post(&format!("{}/api/avatar", app.address))
+        .bearer_auth(&token)
+        .header("content-type", "multipart/form-data; boundary=boundary")
+        .body("")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_problem(response, 400).await;
+}
+
+#[tokio::test]
+async fn raw_bodies_that_are_not_images_are_rejected() {
+    let app = spawn_app().await;
+    let (token, project_id) = member(&app).await;
+
+    let response = reqwest::Client::new()
+        .post(&format!(
+            "{}/api/avatar?name=avatar&project={}",
+            app.address, project_id
+        ))
+        .bearer_auth(&token)
+        .header("content-type", "image/png")
+        .body("definitely not a png")
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_problem(response, 415).await;
+}
+
+#[tokio::test]
+async fn oversized_raw_bodies_are_rejected() {
+    let chunks = (0..4).map(|_| Ok::<_, String>(Bytes::from(vec![0u8; 64])));
+
+    let error = TemporaryImage::from_stream(
+        &ObjectId::new().to_string(),
+        futures::stream::iter(chunks),
+        128,
+    )
+    .await
+    .err()
+    .unwrap();
+
+    let response = error.error_response();
+    assert_eq!(response.status().as_u16(), 413);
+    assert_eq!(
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok()),
+        Some(PROBLEM_CONTENT_TYPE)
+    );
+}
+
+#[test]
+fn sniffed_format_wins_over_the_declared_type() {
+    let image = TemporaryImage::from_data_url(
+        &ObjectId::new().to_string(),
+        &format!("data:image/gif;base64,{}", base64::encode(png())),
+        1024 * 1024,
+    )
+    .unwrap();
+    assert_eq!(image.format, ImageFormat::Png);
+
+    let gif_only = UploadPolicy {
+        allowed_formats: vec!["gif".to_string()],
+        ..UploadPolicy::default()
+    };
+    assert_eq!(
+        error_code(image.validate(&gif_only).err().unwrap()),
+        "unsupported_format"
+    );
+    assert!(image.validate(&UploadPolicy::default()).is_ok());
+}
+
+#[test]
+fn formats_outside_the_allow_list_are_rejected() {
+    let bmp = encode(
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 32, Rgb([0, 0, 0]))),
+        ImageOutputFormat::Bmp,
+    );
+    let image = temporary_image(&bmp, ImageFormat::Bmp);
+
+    assert_eq!(
+        error_code(image.validate(&UploadPolicy::default()).err().unwrap()),
+        "unsupported_format"
+    );
+}
+
+#[test]
+fn dimensions_and_size_are_enforced() {
+    let policy = UploadPolicy {
+        max_width: 48,
+        max_height: 48,
+        ..UploadPolicy::default()
+    };
+    let cases = vec![
+        (sized_png(8, 8), policy.clone(), "invalid_dimensions"),
+        (sized_png(64, 32), policy.clone(), "invalid_dimensions"),
+        (
+            sized_png(32, 32),
+            UploadPolicy {
+                max_bytes: 16,
+                ..policy.clone()
+            },
+            "payload_too_large",
+        ),
+    ];
+
+    for (data, policy, code) in cases {
+        let image = temporary_image(&data, ImageFormat::Png);
+        assert_eq!(error_code(image.validate(&policy).err().unwrap()), code);
+    }
+    let image = temporary_image(&sized_png(32, 32), ImageFormat::Png);
+    assert!(image.validate(&policy).is_ok());
+}
+
+#[test]
+fn decoding_is_bounded_by_the_allocation_budget() {
+    let policy = UploadPolicy {
+        max_width: 32,
+        max_height: 32,
+        ..UploadPolicy::default()
+    };
+    // Same dimensions, but 16 bits per channel need twice the memory of the budget.
+    let deep = encode(
+        DynamicImage::ImageRgba16(ImageBuffer::from_pixel(32, 32, Rgba([1000u16; 4]))),
+        ImageOutputFormat::Png,
+    );
+    let image = temporary_image(&deep, ImageFormat::Png);
+    assert_eq!(
+        error_code(image.validate(&policy).err().unwrap()),
+        "invalid_dimensions"
+    );
+
+    let shallow = encode(
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(32, 32, Rgba([10, 20, 30, 255]))),
+        ImageOutputFormat::Png,
+    );
+    let image = temporary_image(&shallow, ImageFormat::Png);
+    assert!(image.validate(&policy).is_ok());
+}
+
+#[test]
+fn policies_with_inverted_dimensions_are_invalid() {
+    assert!(UploadPolicy::default().validate().is_ok());
+    assert!(UploadPolicy {
+        min_width: 512,
+        max_width: 256,
+        ..UploadPolicy::default()
+    }
+    .validate()
+    .is_err());
+    assert!(UploadPolicy {
+        min_height: 512,
+        max_height: 256,
+        ..UploadPolicy::default()
+    }
+    .validate()
+    .is_err());
+}
@@ -0,0 +1,133 @@
+use std::{collections::HashMap, io::Cursor};
+
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+use mongodb::bson::oid::ObjectId;
+
+use super::{member_project, spawn_app, storage::storage, TestApp};
+use crate::models::{Avatar, AvatarVersion, ProjectSettings};
+
+fn data_url(size: u32, color: [u8; 3]) -> String {
+    let mut data = Vec::new();
+    DynamicImage::ImageRgb8(RgbImage::from_pixel(size, size, Rgb(color)))
+        .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+        .unwrap();
+    format!("data:image/png;base64,{}", base64::encode(data))
+}
+
+async fn create(app: &TestApp, token: &str, project_id: ObjectId, image: String) -> Avatar {
+    let response = reqwest::Client::new()
+        .post(&format!("{}/api/avatar", app.address))
+        .bearer_auth(token)
+        .json(&HashMap::from([
+            ("name", "avatar".to_string()),
+            ("project", project_id.to_string()),
+            ("image", image),
+        ]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    response.json::<Avatar>().await.unwrap()
+}
+
+async fn replace(app: &TestApp, token: &str, avatar_id: ObjectId, image: String) -> Avatar {
+    let response = reqwest::Client::new()
+        .put(&format!("{}/api/avatar/{}", app.address, avatar_id))
+        .bearer_auth(token)
+        .json(&HashMap::from([("image", image)]))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    response.json::<Avatar>().await.unwrap()
+}
+
+async fn versions(app: &TestApp, token: &str, avatar_id: ObjectId) -> Vec<AvatarVersion> {
+    let response = reqwest::Client::new()
+        .get(&format!(
+            "{}/api/avatar/{}/versions",
+            app.address, avatar_id
+        ))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    response.json::<Vec<AvatarVersion>>().await.unwrap()
+}
+
+#[tokio::test]
+async fn replacements_can_be_rolled_back() {
+    let app = spawn_app().await;
+    let (token, project_id) = member_project(&app, ProjectSettings::default()).await;
+    let bucket = project_id.to_string();
+    let original = create(&app, &token, project_id, data_url(32, [200, 30, 30])).await;
+    let original_data = storage().get(&bucket, &original.key()).unwrap().data;
+
+    let replaced = replace(&app, &token, original._id, data_url(48, [30, 30, 200])).await;
+    assert_eq!(replaced.width, 48);
+    let previous_versions = versions(&app, &token, original._id).await;
+    assert_eq!(previous_versions.len(), 1);
+    assert_eq!(previous_versions[0].storage_key, original.key());
+
+    let response = reqwest::Client::new()
+        .post(&format!(
+            "{}/api/avatar/{}/versions/{}/rollback",
+            app.address, original._id, previous_versions[0].id
+        ))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+    let restored = response.json::<Avatar>().await.unwrap();
+    assert_eq!(restored.key(), original.key());
+    assert_eq!((restored.width, restored.height), (32, 32));
+    assert_eq!(restored.content_hash, original.content_hash);
+    assert_eq!(restored.mime_type, original.mime_type);
+    assert_eq!(
+        storage().get(&bucket, &restored.key()).unwrap().data,
+        original_data
+    );
+
+    // The replaced image is now the version to go back to.
+    let next_versions = versions(&app, &token, original._id).await;
+    assert_eq!(next_versions.len(), 1);
+    assert_eq!(next_versions[0].storage_key, replaced.key());
+}
+
+#[tokio::test]
+async fn pruned_versions_lose_their_objects() {
+    let app = spawn_app().await;
+    let mut settings = ProjectSettings::default();
+    settings.version_retention = 2;
+    let (token, project_id) = member_project(&app, settings).await;
+    let bucket = project_id.to_string();
+    let mut avatar = create(&app, &token, project_id, data_url(32, [0, 0, 0])).await;
+    let mut replaced_keys = Vec::new();
+    for shade in 1..5 {
+        replaced_keys.push(avatar.key());
+        avatar = replace(&app, &token, avatar._id, data_url(32, [shade * 40, 0, 0])).await;
+    }
+
+    let kept_versions = versions(&app, &token, avatar._id).await;
+    assert_eq!(
+        kept_versions
+            .iter()
+            .map(|version| version.storage_key.clone())
+            .collect::<Vec<String>>(),
+        vec![replaced_keys[3].clone(), replaced_keys[2].clone()]
+    );
+    for pruned_key in &replaced_keys[..2] {
+        assert!(storage().get(&bucket, pruned_key).is_none());
+    }
+
+    let mut kept_keys = std::iter::once(avatar.key())
+        .chain(avatar.derived_keys())
+        .chain(kept_versions.iter().flat_map(|version| {
+            std::iter::once(version.storage_key.clone()).chain(version.derived_keys())
+        }))
+        .collect::<Vec<String>>();
+    kept_keys.sort();
+    assert_eq!(storage().keys(&bucket), kept_keys);
+}
@@ -0,0 +1,200 @@
+use std::{fs::File, io::BufReader};
+
+use image::{
+    codecs::{
+        gif::{GifDecoder, GifEncoder, Repeat},
+        png::PngDecoder,
+        webp::WebPDecoder,
+    },
+    imageops::{self, FilterType},
+    AnimationDecoder, DynamicImage, Frame, Frames, ImageFormat,
+};
+
+use super::{
+    metadata::{apply_orientation, ImageMetadata, SanitizedImage},
+    TemporaryImage,
+};
+use crate::{
+    errors::AppError,
+    faces::CropRegion,
+    fingerprints,
+    models::{AnimationPolicy, AnimationSummary},
+};
+
+const MAX_ANIMATION_BYTES: u64 = 256 * 1024 * 1024;
+
+impl TemporaryImage {
+    pub fn decode_frames(&self, policy: &AnimationPolicy) -> Result<Option<Vec<Frame>>, AppError> {
+        let file =
+            BufReader::new(File::open(&self.path).map_err(|error| AppError::fs_error(error))?);
+        let frames: Frames = match self.format {
+            ImageFormat::Gif => GifDecoder::new(file)
+                .map_err(|error| AppError::unvalid_form_error(error))?
+                .into_frames(),
+            ImageFormat::Png => {
+                let decoder =
+                    PngDecoder::new(file).map_err(|error| AppError::unvalid_form_error(error))?;
+                if !decoder.is_apng() {
+                    return Ok(None);
+                }
+                decoder.apng().into_frames()
+            }
+            ImageFormat::WebP => {
+                let decoder =
+                    WebPDecoder::new(file).map_err(|error| AppError::unvalid_form_error(error))?;
+                if !decoder.has_animation() {
+                    return Ok(None);
+                }
+                decoder.into_frames()
+            }
+            _ => return Ok(None),
+        };
+
+        let mut decoded_frames = Vec::new();
+        let mut duration_ms = 0;
+        let mut decoded_bytes = 0;
+        for frame in frames {
+            // Limits are checked before a frame is kept so an oversized animation never
+            // holds more than the allowed frames in memory.
+            if decoded_frames.len() as u32 >= policy.max_frames {
+                return Err(AppError::animation_error(format!(
+                    "The animation has more than {} frames.",
+                    policy.max_frames
+                )));
+            }
+            let frame = frame.map_err(|error| AppError::unvalid_form_error(error))?;
+            duration_ms += frame_duration_ms(&frame);
+            decoded_bytes += frame.buffer().as_raw().len() as u64;
+            if duration_ms > policy.max_duration_ms {
+                return Err(AppError::animation_error(format!(
+                    "The animation lasts more than {} ms.",
+                    policy.max_duration_ms
+                )));
+            }
+            if decoded_bytes > MAX_ANIMATION_BYTES {
+                return Err(AppError::animation_error(
+                    "The animation needs too much memory to be decoded.",
+                ));
+            }
+            decoded_frames.push(frame);
+        }
+
+        Ok(match decoded_frames.len() {
+            0 | 1 => None,
+            _ => Some(decoded_frames),
+        })
+    }
+
+    // Animations are stored as GIF, the only animated format image can encode. APNG and animated
+    // WebP uploads are converted too, which limits each frame to a 256 colour palette with 1-bit
+    // transparency; the static rendition keeps the full colour first frame.
+    pub fn sanitize_frames(
+        &self,
+        frames: Vec<Frame>,
+        metadata: ImageMetadata,
+    ) -> Result<(SanitizedImage, AnimationSummary), AppError> {
+        let (width, height) = frames[0].buffer().dimensions();
+        let animation = AnimationSummary {
+            frame_count: frames.len() as u32,
+            duration_ms: frames.iter().map(frame_duration_ms).sum(),
+            static_url: None,
+            static_storage_key: None,
+        };
+
+        let mut encoded_image = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut encoded_image);
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(|error| AppError::avatat_generation_error(error))?;
+            encoder
+                .encode_frames(frames)
+                .map_err(|error| AppError::avatat_generation_error(error))?;
+        }
+
+        let path = format!("{}-clean", self.path);
+        std::fs::write(&path, &encoded_image).map_err(|error| AppError::fs_error(error))?;
+        Ok((
+            SanitizedImage {
+                image: TemporaryImage {
+                    path,
+                    format: ImageFormat::Gif,
+                    size: encoded_image.len() as u64,
+                },
+                width,
+                height,
+                metadata: metadata.summary,
+                content_hash: fingerprints::content_hash(&encoded_image),
+            },
+            animation,
+        ))
+    }
+
+    pub fn static_rendition(&self, image: &DynamicImage) -> Result<TemporaryImage, AppError> {
+        let path = format!("{}-static", self.path);
+        image
+            .save_with_format(&path, ImageFormat::Png)
+            .map_err(|error| AppError::avatat_generation_error(error))?;
+        let size = std::fs::metadata(&path)
+            .map_err(|error| AppError::fs_error(error))?
+            .len();
+        Ok(TemporaryImage {
+            path,
+            format: ImageFormat::Png,
+            size,
+        })
+    }
+}
+
+pub fn frame_duration_ms(frame: &Frame) -> u32 {
+    let (numerator, denominator) = frame.delay().numer_denom_ms();
+    numerator / denominator.max(1)
+}
+
+pub fn orient_frames(frames: Vec<Frame>, orientation: u32) -> Vec<Frame> {
+    if orientation <= 1 {
+        return frames;
+    }
+    map_frames(frames, |buffer| {
+        apply_orientation(DynamicImage::ImageRgba8(buffer), orientation).to_rgba8()
+    })
+}
+
+pub fn crop_frames(frames: Vec<Frame>, region: CropRegion) -> Vec<Frame> {
+    map_frames(frames, |buffer| {
+        imageops::crop_imm(
+            &buffer,
+            region.left,
+            region.top,
+            region.width,
+            region.height,
+        )
+        .to_image()
+    })
+}
+
+pub fn resize_frames(frames: Vec<Frame>, max_size: u32) -> Vec<Frame> {
+    let (width, height) = frames[0].buffer().dimensions();
+    if width <= max_size && height <= max_size {
+        return frames;
+    }
+    let scale = max_size as f32 / width.max(height) as f32;
+    let new_width = ((width as f32 * scale).round() as u32).max(1);
+    let new_height = ((height as f32 * scale).round() as u32).max(1);
+    map_frames(frames, |buffer| {
+        imageops::resize(&buffer, new_width, new_height, FilterType::Triangle)
+    })
+}
+
+fn map_frames(
+    frames: Vec<Frame>,
+    transform: impl Fn(image::RgbaImage) -> image::RgbaImage,
+) -> Vec<Frame> {
+    frames
+        .into_iter()
+        .map(|frame| {
+            let delay = frame.delay();
+            Frame::from_parts(transform(frame.into_buffer()), 0, 0, delay)
+        })
+        .collect()
+}
@@ -0,0 +1,264 @@
+use std::io::Cursor;
+
+use exif::{Context, In, Tag};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+
+use super::TemporaryImage;
+use crate::{errors::AppError, fingerprints, models::MetadataSummary};
+
+const JPEG_ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PNG_SIGNATURE_LENGTH: usize = 8;
+const XMP_MARKER: &[u8] = b"<x:xmpmeta";
+
+pub struct SanitizedImage {
+    pub image: TemporaryImage,
+    pub width: u32,
+    pub height: u32,
+    pub metadata: MetadataSummary,
+    pub content_hash: String,
+}
+
+#[derive(Default)]
+struct RawMetadata {
+    xmp: bool,
+    icc_profile: Vec<u8>,
+    text_fields: Vec<String>,
+}
+
+pub struct ImageMetadata {
+    pub summary: MetadataSummary,
+    icc_profile: Vec<u8>,
+}
+
+impl ImageMetadata {
+    pub fn orient(&self, image: DynamicImage) -> DynamicImage {
+        apply_orientation(image, self.summary.orientation.unwrap_or(1))
+    }
+}
+
+impl TemporaryImage {
+    pub fn read_metadata(&self) -> Result<ImageMetadata, AppError> {
+        let original = std::fs::read(&self.path).map_err(|error| AppError::fs_error(error))?;
+        let mut summary = MetadataSummary::default();
+
+        if let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(&original)) {
+            summary.orientation = exif
+                .get_field(Tag::Orientation, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0));
+            for field in exif.fields() {
+                if field.tag.context() == Context::Gps {
+                    summary.gps = true;
+                }
+                summary.removed_fields.push(format!("exif:{}", field.tag));
+            }
+        }
+
+        let raw_metadata = match self.format {
+            ImageFormat::Jpeg => read_jpeg_metadata(&original),
+            ImageFormat::Png => read_png_metadata(&original),
+            ImageFormat::WebP => read_webp_metadata(&original),
+            _ => RawMetadata::default(),
+        };
+        summary.xmp = raw_metadata.xmp || contains(&original, XMP_MARKER);
+        summary.icc_profile = !raw_metadata.icc_profile.is_empty();
+        summary.removed_fields.extend(raw_metadata.text_fields);
+
+        Ok(ImageMetadata {
+            summary,
+            icc_profile: raw_metadata.icc_profile,
+        })
+    }
+
+    pub fn sanitize(
+        &self,
+        image: &DynamicImage,
+        metadata: ImageMetadata,
+        keep_icc_profile: bool,
+    ) -> Result<SanitizedImage, AppError> {
+        let mut summary = metadata.summary;
+        let (format, output_format) = match self.format {
+            ImageFormat::Jpeg => (ImageFormat::Jpeg, ImageOutputFormat::Jpeg(90)),
+            ImageFormat::Gif => (ImageFormat::Gif, ImageOutputFormat::Gif),
+            ImageFormat::Bmp => (ImageFormat::Bmp, ImageOutputFormat::Bmp),
+            ImageFormat::Ico => (ImageFormat::Ico, ImageOutputFormat::Ico),
+            ImageFormat::Tiff => (ImageFormat::Tiff, ImageOutputFormat::Tiff),
+            // image has no WebP encoder, WebP uploads go through libwebp below instead.
+            ImageFormat::WebP => (ImageFormat::WebP, ImageOutputFormat::Png),
+            _ => (ImageFormat::Png, ImageOutputFormat::Png),
+        };
+        let mut encoded_image = Vec::new();
+        match format {
+            ImageFormat::WebP => {
+                let rgba_image = image.to_rgba8();
+                encoded_image =
+                    webp::Encoder::from_rgba(&rgba_image, image.width(), image.height())
+                        .encode(90.0)
+                        .to_vec();
+            }
+            _ => image
+                .write_to(&mut Cursor::new(&mut encoded_image), output_format)
+                .map_err(|error| AppError::avatat_generation_error(error))?,
+        }
+
+        if keep_icc_profile && summary.icc_profile && format == self.format {
+            // WebP profiles would need a VP8X header rewrite, they are dropped like other metadata.
+            match format {
+                ImageFormat::Jpeg => {
+                    encoded_image = insert_jpeg_segments(encoded_image, &metadata.icc_profile);
+                    summary.icc_profile_kept = true;
+                }
+                ImageFormat::Png => {
+                    encoded_image = insert_png_chunks(encoded_image, &metadata.icc_profile);
+                    summary.icc_profile_kept = true;
+                }
+                _ => {}
+            }
+        }
+
+        let path = format!("{}-clean", self.path);
+        std::fs::write(&path, &encoded_image).map_err(|error| AppError::fs_error(error))?;
+        Ok(SanitizedImage {
+            image: TemporaryImage {
+                path,
+                format,
+                size: encoded_image.len() as u64,
+            },
+            width: image.width(),
+            height: image.height(),
+            metadata: summary,
+            content_hash: fingerprints::content_hash(&encoded_image),
+        })
+    }
+}
+
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn contains(data: &[u8], pattern: &[u8]) -> bool {
+    data.windows(pattern.len()).any(|window| window == pattern)
+}
+
+fn read_jpeg_metadata(data: &[u8]) -> RawMetadata {
+    let mut metadata = RawMetadata::default();
+    let mut position = 2;
+
+    while position + 4 <= data.len() && data[position] == 0xFF {
+        let marker = data[position + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            position += 2;
+            continue;
+        }
+        let length = u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        let end = (position + 2 + length).min(data.len());
+        let payload = &data[(position + 4).min(end)..end];
+        match marker {
+            0xE1 if payload.starts_with(JPEG_XMP_HEADER) => metadata.xmp = true,
+            0xE2 if payload.starts_with(JPEG_ICC_HEADER) => {
+                metadata.icc_profile.extend_from_slice(&data[position..end])
+            }
+            0xFE => metadata.text_fields.push("jpeg:Comment".to_string()),
+            _ => {}
+        }
+        position = end;
+    }
+    metadata
+}
+
+fn read_png_metadata(data: &[u8]) -> RawMetadata {
+    let mut metadata = RawMetadata::default();
+    let mut position = PNG_SIGNATURE_LENGTH;
+
+    while position + 8 <= data.len() {
+        let length = u32::from_be_bytes([
+            data[position],
+            data[position + 1],
+            data[position + 2],
+            data[position + 3],
+        ]) as usize;
+        let chunk_type = &data[position + 4..position + 8];
+        let end = (position + 12 + length).min(data.len());
+        let chunk_data = &data[(position + 8).min(end)..(position + 8 + length).min(end)];
+        match chunk_type {
+            b"iCCP" => metadata.icc_profile.extend_from_slice(&data[position..end]),
+            b"tEXt" | b"zTXt" | b"iTXt" => {
+                let keyword = chunk_data
+                    .split(|byte| *byte == 0)
+                    .next()
+                    .map(|keyword| String::from_utf8_lossy(keyword).to_string())
+                    .unwrap_or_default();
+                if keyword == "XML:com.adobe.xmp" {
+                    metadata.xmp = true;
+                } else {
+                    metadata.text_fields.push(format!("png:{}", keyword));
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        position = end;
+    }
+    metadata
+}
+
+fn read_webp_metadata(data: &[u8]) -> RawMetadata {
+    let mut metadata = RawMetadata::default();
+    let mut position = 12;
+
+    while position + 8 <= data.len() {
+        let chunk_type = &data[position..position + 4];
+        let length = u32::from_le_bytes([
+            data[position + 4],
+            data[position + 5],
+            data[position + 6],
+            data[position + 7],
+        ]) as usize;
+        let end = (position + 8 + length + length % 2).min(data.len());
+        match chunk_type {
+            b"XMP " => metadata.xmp = true,
+            b"ICCP" => metadata.icc_profile.extend_from_slice(&data[position..end]),
+            _ => {}
+        }
+        position = end;
+    }
+    metadata
+}
+
+fn insert_jpeg_segments(encoded_image: Vec<u8>, segments: &[u8]) -> Vec<u8> {
+    // The JFIF APP0 segment written by the encoder has to stay first.
+    let mut position = 2;
+    if encoded_image.len() > 6 && encoded_image[2] == 0xFF && encoded_image[3] == 0xE0 {
+        let length = u16::from_be_bytes([encoded_image[4], encoded_image[5]]) as usize;
+        position += 2 + length;
+    }
+    [
+        &encoded_image[..position],
+        segments,
+        &encoded_image[position..],
+    ]
+    .concat()
+}
+
+fn insert_png_chunks(encoded_image: Vec<u8>, chunks: &[u8]) -> Vec<u8> {
+    // iCCP must come right after IHDR, which is always 25 bytes long.
+    let position = PNG_SIGNATURE_LENGTH + 25;
+    [
+        &encoded_image[..position],
+        chunks,
+        &encoded_image[position..],
+    ]
+    .concat()
+}
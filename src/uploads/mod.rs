@@ -0,0 +1,234 @@
+pub mod animation;
+pub mod metadata;
+
+use std::{fs::File, io::BufReader};
+
+use actix_multipart::Field;
+use actix_web::{guard::GuardContext, http::header::CONTENT_TYPE, web::Bytes};
+use futures::{Stream, StreamExt, TryStreamExt};
+use image::{
+    io::{Limits, Reader},
+    DynamicImage, ImageError, ImageFormat,
+};
+use tokio::io::AsyncWriteExt;
+
+use crate::{errors::AppError, metrics, models::UploadPolicy};
+
+const MAX_TEXT_FIELD_SIZE: usize = 1024;
+const SNIFF_LENGTH: usize = 64;
+
+pub const MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024;
+
+pub struct TemporaryImage {
+    pub path: String,
+    pub format: ImageFormat,
+    pub size: u64,
+}
+
+impl TemporaryImage {
+    pub fn from_data_url(
+        key: &str,
+        data_url: &str,
+        max_bytes: u64,
+    ) -> Result<TemporaryImage, AppError> {
+        let (header, body) = data_url
+            .split_once(',')
+            .ok_or(AppError::unvalid_form_error(
+                "The image must be a base64 data URL.",
+            ))?;
+        if !header.starts_with("data:") || !header.ends_with(";base64") {
+            return Err(AppError::unvalid_form_error(
+                "The image must be a base64 data URL.",
+            ));
+        }
+        let mime_type = &header["data:".len()..header.len() - ";base64".len()];
+        if !mime_type.starts_with("image/") {
+            return Err(AppError::unsupported_format_error(mime_type));
+        }
+        if body.len() as u64 / 4 * 3 > max_bytes {
+            return Err(AppError::payload_too_large_error(max_bytes));
+        }
+        let decoded_image = base64::decode(body).map_err(|error| {
+            AppError::unvalid_form_error(format!("The image is not valid base64: {}", error))
+        })?;
+        if decoded_image.len() as u64 > max_bytes {
+            return Err(AppError::payload_too_large_error(max_bytes));
+        }
+
+        let format = image::guess_format(&decoded_image)
+            .map_err(|_| AppError::unsupported_format_error("unknown"))?;
+        let path = format!("./tmp/{}", key);
+        std::fs::write(&path, &decoded_image).map_err(|error| AppError::fs_error(error))?;
+        metrics::UPLOAD_SIZE
+            .with_label_values(&[format.extensions_str()[0]])
+            .observe(decoded_image.len() as f64);
+        Ok(TemporaryImage {
+            path,
+            format,
+            size: decoded_image.len() as u64,
+        })
+    }
+
+    pub async fn from_stream<S, E>(
+        key: &str,
+        mut stream: S,
+        max_bytes: u64,
+    ) -> Result<TemporaryImage, AppError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: ToString,
+    {
+        let path = format!("./tmp/{}", key);
+        let mut file = tokio::fs::File::create(&path)
+            .await
+            .map_err(|error| AppError::fs_error(error))?;
+        let mut size = 0;
+        let mut header = Vec::new();
+
+        let result = async {
+            while let Some(chunk) = stream.next().await {
+                let chunk = chunk.map_err(|error| AppError::unvalid_form_error(error))?;
+                size += chunk.len() as u64;
+                if size > max_bytes {
+                    return Err(AppError::payload_too_large_error(max_bytes));
+                }
+                if header.len() < SNIFF_LENGTH {
+                    let missing = (SNIFF_LENGTH - header.len()).min(chunk.len());
+                    header.extend_from_slice(&chunk[..missing]);
+                }
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|error| AppError::fs_error(error))?;
+            }
+            file.flush()
+                .await
+                .map_err(|error| AppError::fs_error(error))
+        }
+        .await;
+        if let Err(error) = result {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(error);
+        }
+
+        match image::guess_format(&header) {
+            Ok(format) => {
+                metrics::UPLOAD_SIZE
+                    .with_label_values(&[format.extensions_str()[0]])
+                    .observe(size as f64);
+                Ok(TemporaryImage { path, format, size })
+            }
+            Err(_) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                Err(AppError::unsupported_format_error("unknown"))
+            }
+        }
+    }
+
+    pub fn extension(&self) -> String {
+        self.format.extensions_str()[0].to_string()
+    }
+
+    pub fn validate(&self, policy: &UploadPolicy) -> Result<DynamicImage, AppError> {
+        if self.size > policy.max_bytes {
+            return Err(AppError::payload_too_large_error(policy.max_bytes));
+        }
+
+        let format_name = format_name(self.format);
+        if !policy
+            .allowed_formats
+            .iter()
+            .any(|allowed_format| allowed_format == format_name)
+        {
+            return Err(AppError::unsupported_format_error(format_name));
+        }
+
+        let (width, height) = self
+            .reader()?
+            .into_dimensions()
+            .map_err(|_| AppError::unvalid_form_error("The uploaded file is not a valid image."))?;
+        if width > policy.max_width || height > policy.max_height {
+            return Err(AppError::image_dimensions_error(format!(
+                "The image is {}x{} pixels, the maximum is {}x{}.",
+                width, height, policy.max_width, policy.max_height
+            )));
+        }
+        if width < policy.min_width || height < policy.min_height {
+            return Err(AppError::image_dimensions_error(format!(
+                "The image is {}x{} pixels, the minimum is {}x{}.",
+                width, height, policy.min_width, policy.min_height
+            )));
+        }
+
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(policy.max_width);
+        limits.max_image_height = Some(policy.max_height);
+        // The pipeline works on 8-bit RGBA, deeper pixel formats only fit in smaller images.
+        limits.max_alloc = Some(policy.max_width as u64 * policy.max_height as u64 * 4);
+        let mut reader = self.reader()?;
+        reader.limits(limits);
+        reader.decode().map_err(|error| match error {
+            ImageError::Limits(_) => {
+                AppError::image_dimensions_error("The image needs too much memory to be decoded.")
+            }
+            _ => AppError::unvalid_form_error("The uploaded file is not a valid image."),
+        })
+    }
+
+    fn reader(&self) -> Result<Reader<BufReader<File>>, AppError> {
+        let mut reader = Reader::open(&self.path).map_err(|error| AppError::fs_error(error))?;
+        reader.set_format(self.format);
+        Ok(reader)
+    }
+}
+
+impl Drop for TemporaryImage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub async fn read_text_field(field: &mut Field) -> Result<String, AppError> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field
+        .try_next()
+        .await
+        .map_err(|error| AppError::unvalid_form_error(error))?
+    {
+        if value.len() + chunk.len() > MAX_TEXT_FIELD_SIZE {
+            return Err(AppError::unvalid_form_error("A form field is too long."));
+        }
+        value.extend_from_slice(&chunk);
+    }
+    String::from_utf8(value).map_err(|error| AppError::unvalid_form_error(error))
+}
+
+pub fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::Gif => "gif",
+        ImageFormat::WebP => "webp",
+        ImageFormat::Bmp => "bmp",
+        ImageFormat::Tiff => "tiff",
+        ImageFormat::Ico => "ico",
+        ImageFormat::Avif => "avif",
+        _ => "unknown",
+    }
+}
+
+fn content_type(ctx: &GuardContext) -> String {
+    ctx.head()
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+pub fn multipart_guard(ctx: &GuardContext) -> bool {
+    content_type(ctx).starts_with("multipart/form-data")
+}
+
+pub fn raw_image_guard(ctx: &GuardContext) -> bool {
+    content_type(ctx).starts_with("image/")
+}
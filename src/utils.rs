@@ -1,7 +1,12 @@
+use std::str::FromStr;
+
 use jsonwebtoken::{encode, EncodingKey, Header};
 use mongodb::bson::{doc, oid::ObjectId};
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::AppError;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Claims {
@@ -10,6 +15,12 @@ pub struct Claims {
     pub id: ObjectId,
 }
 
+// Ids come from paths and query strings, a malformed one is the client's mistake.
+pub fn parse_object_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::from_str(id)
+        .map_err(|_| AppError::unvalid_form_error(format!("{} is not a valid id.", id)))
+}
+
 pub fn encode_jwt(claims: Claims) -> Result<String, Box<dyn std::error::Error>> {
     let token = encode(
         &Header::default(),
@@ -32,3 +43,26 @@ pub async fn generate_credentials() -> (String, String) {
         .collect();
     (key, secret)
 }
+
+// Media URLs are signed with their own key so the short API secret is never used for HMAC.
+pub fn generate_signing_key() -> String {
+    random_hex::<32>()
+}
+
+pub fn generate_verification_token() -> String {
+    random_hex::<16>()
+}
+
+// Only a digest of invitation tokens is stored, a database dump can not redeem them.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.trim().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn random_hex<const N: usize>() -> String {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill(&mut bytes[..]);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}